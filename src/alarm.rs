@@ -0,0 +1,66 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use prometheus::{register_int_gauge_vec, IntGaugeVec};
+use serde::Serialize;
+use strum_macros::IntoStaticStr;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, IntoStaticStr)]
+pub enum AlarmKind {
+    Frost,
+    RisingWhileHeatOff,
+    FallingWhileHeatOn,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Alarm {
+    pub source: Box<str>,
+    pub kind: AlarmKind,
+    pub since: DateTime<Utc>,
+}
+
+type AlarmKey = (Box<str>, AlarmKind);
+
+lazy_static! {
+    static ref ACTIVE_ALARMS: Mutex<BTreeMap<AlarmKey, DateTime<Utc>>> =
+        Mutex::new(BTreeMap::new());
+    static ref ALARM_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "alarm_active",
+        "Active alarms by source and kind",
+        &["source", "kind"]
+    )
+    .expect("Cannot register alarm gauge");
+}
+
+pub fn raise_alarm(source: &str, kind: AlarmKind) {
+    let mut alarms = ACTIVE_ALARMS.lock().expect("Alarm lock poisoned");
+    if alarms.contains_key(&(source.into(), kind)) {
+        return;
+    }
+    warn!("Alarm raised: {kind:?} on {source}");
+    alarms.insert((source.into(), kind), Utc::now());
+    ALARM_GAUGE.with_label_values(&[source, kind.into()]).set(1);
+}
+
+pub fn clear_alarm(source: &str, kind: AlarmKind) {
+    let mut alarms = ACTIVE_ALARMS.lock().expect("Alarm lock poisoned");
+    if alarms.remove(&(source.into(), kind)).is_some() {
+        info!("Alarm cleared: {kind:?} on {source}");
+        ALARM_GAUGE.with_label_values(&[source, kind.into()]).set(0);
+    }
+}
+
+pub fn active_alarms() -> Vec<Alarm> {
+    ACTIVE_ALARMS
+        .lock()
+        .expect("Alarm lock poisoned")
+        .iter()
+        .map(|((source, kind), since)| Alarm {
+            source: source.clone(),
+            kind: *kind,
+            since: *since,
+        })
+        .collect()
+}
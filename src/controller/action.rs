@@ -1,55 +1,609 @@
+use std::{num::Saturating, time::Duration};
+
+use chrono::{DateTime, NaiveTime, Utc};
 use futures::Stream;
-use log::error;
-use tokio::{sync::mpsc, task::AbortHandle};
+use log::{error, info};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::{sleep, Instant},
+};
 use tokio_stream::StreamExt;
 
-use crate::data::registry::{
-    ButtonState, EventRegistry, SingleButtonKey, SingleButtonLayout, SwitchOutputKey,
+use crate::{
+    controller::manager::{ControllerHandle, ControllerState, StateSender},
+    data::{
+        registry::{
+            ButtonState, EventRegistry, HouseMode, LightGroupKey, LightGroupSenders, RingEvent,
+            SingleButtonLayout,
+        },
+        wiring::{AllOffController, FlashLight, HouseModeButton, RingController},
+    },
+    terminator::JoinHandleTerminator,
+    util::{optional_stream, time_in_range},
 };
 
+/// Times a light is toggled to signal a ring during quiet hours
+const FLASH_COUNT: usize = 3;
+const FLASH_TIME: Duration = Duration::from_millis(400);
+
 pub async fn ring_controller(
     event_registry: &EventRegistry,
-    input: SingleButtonKey,
-    output: SwitchOutputKey,
-) -> AbortHandle {
+    settings: &RingController,
+    state: StateSender,
+) -> ControllerHandle {
+    let quiet_hours = settings.quiet_hours.as_ref();
+    let flash = quiet_hours.and_then(|q| q.flash);
     let input_stream = event_registry
-        .single_button_stream(input)
+        .single_button_stream(settings.input)
         .await
-        .map(ActionMessage::Button);
-    let sender = event_registry.switch_sender(output).await;
+        .map(RingMessage::Button)
+        .merge(
+            optional_stream(quiet_hours.map(|q| event_registry.clock(q.clock)))
+                .await
+                .map(|now| RingMessage::Time(now.time())),
+        )
+        .merge(
+            optional_stream(flash.and_then(|f| match f {
+                FlashLight::Switch { key } => Some(event_registry.switch_stream(key)),
+                FlashLight::Dimmer { .. } => None,
+            }))
+            .await
+            .map(|on| RingMessage::Light(if on { 255 } else { 0 })),
+        )
+        .merge(
+            optional_stream(flash.and_then(|f| match f {
+                FlashLight::Switch { .. } => None,
+                FlashLight::Dimmer { key } => Some(event_registry.brightness_stream(key)),
+            }))
+            .await
+            .map(|brightness| RingMessage::Light(brightness.0)),
+        );
+    let outputs = RingOutputs {
+        bell: event_registry.switch_sender(settings.output).await,
+        flash: match flash {
+            Some(FlashLight::Switch { key }) => {
+                Some(FlashOutput::Switch(event_registry.switch_sender(key).await))
+            }
+            Some(FlashLight::Dimmer { key }) => Some(FlashOutput::Dimmer(
+                event_registry.brightness_sender(key).await,
+            )),
+            None => None,
+        },
+        events: event_registry.ring_event_sender().await,
+    };
+    let settings = settings.clone();
     tokio::spawn(async move {
-        if let Err(error) = ring_task(input_stream, sender).await {
-            error!("Failed handle ring: {error}")
+        if let Err(error) = ring_task(input_stream, outputs, settings, state).await {
+            error!("Failed handle ring: {error}");
+            return Err(error.into());
         }
+        Ok(())
     })
-    .abort_handle()
+}
+
+/// Internal state of a ring controller, published on every event
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RingState {
+    pub last_ring: Option<DateTime<Utc>>,
+    pub quiet: bool,
+    /// the bell or the flash light is signalling a ring
+    pub signalling: bool,
+}
+
+#[derive(Error, Debug)]
+enum RingError {
+    #[error("Cannot send switch: {0}")]
+    Switch(#[from] mpsc::error::SendError<bool>),
+    #[error("Cannot send brightness: {0}")]
+    Brightness(#[from] mpsc::error::SendError<Saturating<u8>>),
+}
+
+struct RingOutputs {
+    bell: mpsc::Sender<bool>,
+    flash: Option<FlashOutput>,
+    events: broadcast::Sender<RingEvent>,
+}
+
+#[derive(Clone)]
+enum FlashOutput {
+    Switch(mpsc::Sender<bool>),
+    Dimmer(mpsc::Sender<Saturating<u8>>),
+}
+
+impl FlashOutput {
+    /// Toggles the light a few times and leaves it at its current brightness
+    async fn flash(&self, current: u8) -> Result<(), RingError> {
+        let toggled = if current > 0 { 0 } else { 255 };
+        for _ in 0..FLASH_COUNT {
+            self.send(toggled).await?;
+            sleep(FLASH_TIME).await;
+            self.send(current).await?;
+            sleep(FLASH_TIME).await;
+        }
+        Ok(())
+    }
+    async fn send(&self, brightness: u8) -> Result<(), RingError> {
+        match self {
+            FlashOutput::Switch(sender) => sender.send(brightness > 0).await?,
+            FlashOutput::Dimmer(sender) => sender.send(Saturating(brightness)).await?,
+        }
+        Ok(())
+    }
+}
+
+enum RingMessage {
+    Button(ButtonState<SingleButtonLayout>),
+    Time(NaiveTime),
+    /// current brightness of the flash light
+    Light(u8),
+}
+
+/// Bell pattern or flashing running on its own task, so the controller keeps handling events
+struct RunningSignal {
+    task: JoinHandleTerminator<()>,
+    reset: SignalReset,
+}
+
+/// Output state restored when a running signal is cancelled
+enum SignalReset {
+    Bell,
+    Flash(u8),
+}
+
+impl RunningSignal {
+    fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+    /// Stops the signal and leaves the output as it was before
+    async fn cancel(self, outputs: &RingOutputs) -> Result<(), RingError> {
+        let running = self.is_running();
+        drop(self.task);
+        if running {
+            match (self.reset, &outputs.flash) {
+                (SignalReset::Bell, _) => outputs.bell.send(false).await?,
+                (SignalReset::Flash(light), Some(flash)) => flash.send(light).await?,
+                (SignalReset::Flash(_), None) => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 async fn ring_task(
-    mut input: impl Stream<Item = ActionMessage> + Unpin,
-    output: mpsc::Sender<bool>,
-) -> Result<(), mpsc::error::SendError<bool>> {
+    mut input: impl Stream<Item = RingMessage> + Unpin,
+    outputs: RingOutputs,
+    settings: RingController,
+    state: StateSender,
+) -> Result<(), RingError> {
     let mut button_pressed = false;
+    let mut bell_on = false;
+    let mut quiet = false;
+    let mut light = 0;
+    let mut last_ring = None::<Instant>;
+    let mut last_ring_time = None;
+    let mut running = None::<RunningSignal>;
     while let Some(event) = input.next().await {
         match event {
-            ActionMessage::Button(ButtonState::ShortPressStart(_)) => {
-                if !button_pressed {
-                    output.send(true).await?;
+            RingMessage::Button(
+                ButtonState::ShortPressStart(_)
+                | ButtonState::DoubleClickStart(_)
+                | ButtonState::TripleClickStart(_),
+            ) => {
+                if button_pressed {
+                    continue;
                 }
                 button_pressed = true;
+                if last_ring.is_some_and(|last| last.elapsed() < settings.debounce) {
+                    continue;
+                }
+                last_ring = Some(Instant::now());
+                let time = Utc::now();
+                last_ring_time = Some(time);
+                // nobody listening for notifications is not an error
+                let _ = outputs.events.send(RingEvent {
+                    button: settings.input,
+                    bell: settings.output,
+                    time,
+                    quiet,
+                });
+                if let Some(signal) = running.take() {
+                    signal.cancel(&outputs).await?;
+                }
+                if quiet {
+                    if let Some(flash) = outputs.flash.clone() {
+                        running = Some(RunningSignal {
+                            task: tokio::spawn(async move {
+                                if let Err(error) = flash.flash(light).await {
+                                    error!("Cannot flash light: {error}");
+                                }
+                            })
+                            .into(),
+                            reset: SignalReset::Flash(light),
+                        });
+                    }
+                } else if settings.pattern.is_empty() {
+                    outputs.bell.send(true).await?;
+                    bell_on = true;
+                } else {
+                    let bell = outputs.bell.clone();
+                    let pattern = settings.pattern.clone();
+                    running = Some(RunningSignal {
+                        task: tokio::spawn(async move {
+                            if let Err(error) = play_pattern(&bell, &pattern).await {
+                                error!("Cannot play bell pattern: {error}");
+                            }
+                        })
+                        .into(),
+                        reset: SignalReset::Bell,
+                    });
+                }
             }
-            ActionMessage::Button(ButtonState::Released) => {
-                if button_pressed {
-                    output.send(false).await?;
+            RingMessage::Button(ButtonState::Released) => {
+                if bell_on {
+                    outputs.bell.send(false).await?;
                 }
+                bell_on = false;
                 button_pressed = false;
             }
-            ActionMessage::Button(ButtonState::LongPressStart(_)) => {}
+            RingMessage::Button(ButtonState::LongPressStart(_)) => {}
+            RingMessage::Time(now) => {
+                let was_quiet = quiet;
+                quiet = settings
+                    .quiet_hours
+                    .as_ref()
+                    .is_some_and(|q| time_in_range(q.start, q.end, now));
+                // the start of the quiet hours silences a running pattern
+                if quiet != was_quiet {
+                    if let Some(signal) = running.take() {
+                        signal.cancel(&outputs).await?;
+                    }
+                }
+            }
+            RingMessage::Light(brightness) => {
+                // the flashing itself is no change of the light
+                if !running
+                    .as_ref()
+                    .is_some_and(|r| r.is_running() && matches!(r.reset, SignalReset::Flash(_)))
+                {
+                    light = brightness;
+                }
+            }
+        }
+        state.send_replace(ControllerState::Ring(RingState {
+            last_ring: last_ring_time,
+            quiet,
+            signalling: bell_on || running.as_ref().is_some_and(RunningSignal::is_running),
+        }));
+    }
+    Ok(())
+}
+
+/// Rings the bell for the even entries of the pattern and pauses for the odd ones
+async fn play_pattern(bell: &mpsc::Sender<bool>, pattern: &[Duration]) -> Result<(), RingError> {
+    for (idx, duration) in pattern.iter().enumerate() {
+        bell.send(idx % 2 == 0).await?;
+        sleep(*duration).await;
+    }
+    bell.send(false).await?;
+    Ok(())
+}
+
+pub async fn all_off_controller(
+    event_registry: &EventRegistry,
+    settings: &AllOffController,
+) -> ControllerHandle {
+    let input_stream = event_registry
+        .single_button_stream(settings.input)
+        .await
+        .map(ActionMessage::Button);
+    let senders = event_registry
+        .light_group_senders(&settings.group, &settings.exceptions)
+        .await;
+    let group = settings.group.key;
+    tokio::spawn(async move {
+        all_off_task(input_stream, senders, group).await;
+        Ok(())
+    })
+}
+
+async fn all_off_task(
+    mut input: impl Stream<Item = ActionMessage> + Unpin,
+    senders: LightGroupSenders,
+    group: LightGroupKey,
+) {
+    while let Some(event) = input.next().await {
+        match event {
+            ActionMessage::Button(
+                ButtonState::ShortPressStart(_)
+                | ButtonState::DoubleClickStart(_)
+                | ButtonState::TripleClickStart(_),
+            ) => {
+                let count = senders.send_brightness(Saturating(0)).await;
+                info!("Switched off {count} lights of {group:?}");
+            }
+            ActionMessage::Button(_) => {}
+        }
+    }
+}
+
+pub async fn house_mode_button(
+    event_registry: &EventRegistry,
+    settings: &HouseModeButton,
+) -> ControllerHandle {
+    let input_stream = event_registry
+        .single_button_stream(settings.input)
+        .await
+        .map(HouseModeMessage::Button)
+        .merge(
+            event_registry
+                .house_mode_stream()
+                .await
+                .map(HouseModeMessage::HouseMode),
+        );
+    let sender = event_registry.house_mode_sender().await;
+    let mode = settings.mode;
+    tokio::spawn(async move {
+        if let Err(error) = house_mode_task(input_stream, sender, mode).await {
+            error!("Failed house mode button: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+async fn house_mode_task(
+    mut input: impl Stream<Item = HouseModeMessage> + Unpin,
+    output: mpsc::Sender<HouseMode>,
+    mode: HouseMode,
+) -> Result<(), mpsc::error::SendError<HouseMode>> {
+    let mut current_mode = HouseMode::Home;
+    while let Some(event) = input.next().await {
+        match event {
+            HouseModeMessage::Button(
+                ButtonState::ShortPressStart(_)
+                | ButtonState::DoubleClickStart(_)
+                | ButtonState::TripleClickStart(_),
+            ) => {
+                let next_mode = if current_mode == mode {
+                    HouseMode::Home
+                } else {
+                    mode
+                };
+                info!("Switch house mode to {next_mode:?}");
+                output.send(next_mode).await?;
+            }
+            HouseModeMessage::Button(_) => {}
+            HouseModeMessage::HouseMode(mode) => {
+                current_mode = mode;
+            }
         }
     }
     Ok(())
 }
 
+enum HouseModeMessage {
+    Button(ButtonState<SingleButtonLayout>),
+    HouseMode(HouseMode),
+}
+
 enum ActionMessage {
     Button(ButtonState<SingleButtonLayout>),
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::NaiveTime;
+    use chrono_tz::Tz;
+    use tokio::{
+        sync::{broadcast, mpsc, watch},
+        task::JoinHandle,
+        time::{sleep_until, Instant},
+    };
+    use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+    use crate::{
+        controller::{
+            action::{
+                house_mode_task, ring_task, FlashOutput, HouseModeMessage, RingMessage,
+                RingOutputs, RingState,
+            },
+            manager::ControllerState,
+        },
+        data::{
+            registry::{
+                BrightnessKey, ButtonState, ClockKey, ClockKeyResolution, HouseMode,
+                SingleButtonKey, SingleButtonLayout, SwitchOutputKey,
+            },
+            wiring::{FlashLight, QuietHours, RingController},
+        },
+    };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+    fn time(hour: u32, minute: u32) -> RingMessage {
+        RingMessage::Time(NaiveTime::from_hms_opt(hour, minute, 0).unwrap())
+    }
+    fn press() -> RingMessage {
+        RingMessage::Button(ButtonState::ShortPressStart(SingleButtonLayout))
+    }
+    fn release() -> RingMessage {
+        RingMessage::Button(ButtonState::Released)
+    }
+    fn record<T: Send + 'static>(
+        start: Instant,
+        rx: mpsc::Receiver<T>,
+    ) -> JoinHandle<Vec<(Duration, T)>> {
+        tokio::spawn(
+            ReceiverStream::new(rx)
+                .map(move |value| (start.elapsed(), value))
+                .collect(),
+        )
+    }
+
+    /// Sends the messages at their offsets, returns the bell and flash light changes and the last state
+    async fn run_ring(
+        messages: Vec<(Duration, RingMessage)>,
+    ) -> (Vec<(Duration, bool)>, Vec<(Duration, u8)>, RingState) {
+        let settings = RingController {
+            input: SingleButtonKey::Button(Default::default()),
+            output: SwitchOutputKey::Bell(Default::default()),
+            pattern: Box::new([ms(500), ms(200), ms(500)]),
+            debounce: ms(5000),
+            quiet_hours: Some(QuietHours {
+                clock: ClockKey {
+                    resolution: ClockKeyResolution::Minutes,
+                    tz: Tz::Europe__Zurich,
+                },
+                start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+                flash: Some(FlashLight::Dimmer {
+                    key: BrightnessKey::Light(Default::default()),
+                }),
+            }),
+        };
+        let start = Instant::now();
+        let (bell_tx, bell_rx) = mpsc::channel(4);
+        let (flash_tx, flash_rx) = mpsc::channel(4);
+        let (events, _) = broadcast::channel(4);
+        let outputs = RingOutputs {
+            bell: bell_tx,
+            flash: Some(FlashOutput::Dimmer(flash_tx)),
+            events,
+        };
+        let bell = record(start, bell_rx);
+        let flash = record(start, flash_rx);
+        let (input_tx, input_rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            for (at, message) in messages {
+                sleep_until(start + at).await;
+                input_tx.send(message).await.unwrap();
+            }
+            // let the running signals finish before the input ends
+            sleep_until(start + ms(10000)).await;
+        });
+        let (state_tx, state_rx) = watch::channel(ControllerState::Unknown);
+        ring_task(ReceiverStream::new(input_rx), outputs, settings, state_tx)
+            .await
+            .unwrap();
+        let flash = flash.await.unwrap();
+        let ControllerState::Ring(state) = state_rx.borrow().clone() else {
+            panic!("No ring state published");
+        };
+        (
+            bell.await.unwrap(),
+            flash.into_iter().map(|(at, b)| (at, b.0)).collect(),
+            state,
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ring_pattern() {
+        let (bell, flash, _) = run_ring(vec![
+            (ms(0), time(12, 0)),
+            (ms(0), press()),
+            (ms(100), release()),
+            // handled while the pattern plays, but within the debounce time
+            (ms(300), press()),
+            (ms(400), release()),
+        ])
+        .await;
+        assert_eq!(
+            bell,
+            [
+                (ms(0), true),
+                (ms(500), false),
+                (ms(700), true),
+                (ms(1200), false),
+            ]
+        );
+        assert!(flash.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ring_quiet_hours() {
+        let (bell, flash, state) = run_ring(vec![
+            (ms(0), time(23, 0)),
+            (ms(0), RingMessage::Light(100)),
+            (ms(0), press()),
+            (ms(100), release()),
+        ])
+        .await;
+        assert!(bell.is_empty());
+        assert_eq!(
+            flash,
+            [
+                (ms(0), 0),
+                (ms(400), 100),
+                (ms(800), 0),
+                (ms(1200), 100),
+                (ms(1600), 0),
+                (ms(2000), 100),
+            ]
+        );
+        assert!(state.quiet);
+        assert!(state.last_ring.is_some());
+        // last published on the release, while the light was still flashing
+        assert!(state.signalling);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ring_quiet_hours_start() {
+        // the start of the quiet hours stops the running pattern
+        let (bell, flash, _) = run_ring(vec![
+            (ms(0), time(21, 59)),
+            (ms(0), press()),
+            (ms(100), release()),
+            (ms(800), time(22, 0)),
+        ])
+        .await;
+        assert_eq!(
+            bell,
+            [
+                (ms(0), true),
+                (ms(500), false),
+                (ms(700), true),
+                (ms(800), false)
+            ]
+        );
+        assert!(flash.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_house_mode_any_click() {
+        let (tx, rx) = mpsc::channel(4);
+        let (mode_tx, mut mode_rx) = mpsc::channel(4);
+        let task = tokio::spawn(house_mode_task(
+            ReceiverStream::new(rx),
+            mode_tx,
+            HouseMode::Away,
+        ));
+        let clicks = [
+            ButtonState::ShortPressStart(SingleButtonLayout),
+            ButtonState::DoubleClickStart(SingleButtonLayout),
+            ButtonState::TripleClickStart(SingleButtonLayout),
+        ];
+        let mut expected = HouseMode::Away;
+        for click in clicks {
+            tx.send(HouseModeMessage::Button(click)).await.unwrap();
+            tx.send(HouseModeMessage::Button(ButtonState::Released))
+                .await
+                .unwrap();
+            let mode = mode_rx.recv().await.unwrap();
+            assert_eq!(expected, mode);
+            tx.send(HouseModeMessage::HouseMode(mode)).await.unwrap();
+            expected = if mode == HouseMode::Away {
+                HouseMode::Home
+            } else {
+                HouseMode::Away
+            };
+        }
+        drop(tx);
+        task.await.unwrap().unwrap();
+        assert!(mode_rx.recv().await.is_none());
+    }
+}
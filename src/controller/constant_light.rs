@@ -0,0 +1,245 @@
+use std::{cmp::Ordering, num::Saturating, time::Duration};
+
+use futures::stream::SelectAll;
+use log::{error, info};
+use tokio::{
+    sync::mpsc::{self, error::SendError, Sender},
+    time::sleep,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use crate::{
+    controller::manager::ControllerHandle,
+    data::{
+        registry::{ButtonState, EventRegistry},
+        wiring::ConstantLight,
+    },
+    terminator::JoinHandleTerminator,
+};
+
+/// Largest correction of the brightness per illuminance reading
+const MAX_CORRECTION: i64 = 16;
+
+pub async fn constant_light(
+    event_registry: &EventRegistry,
+    settings: &ConstantLight,
+) -> ControllerHandle {
+    let mut presence_streams = SelectAll::new();
+    for presence_key in settings.presence.iter() {
+        presence_streams.push(
+            event_registry
+                .single_button_stream(*presence_key)
+                .await
+                .filter_map(|s| match s {
+                    ButtonState::ShortPressStart(_)
+                    | ButtonState::DoubleClickStart(_)
+                    | ButtonState::TripleClickStart(_) => Some(ConstantLightMessage::Presence),
+                    _ => None,
+                }),
+        );
+    }
+    let input_stream = event_registry
+        .illuminance_stream(settings.sensor)
+        .await
+        .map(ConstantLightMessage::Illuminance)
+        .merge(
+            event_registry
+                .brightness_stream(settings.output)
+                .await
+                .map(ConstantLightMessage::OutputBrightness),
+        )
+        .merge(presence_streams);
+    let sender = event_registry.brightness_sender(settings.output).await;
+    let regulation = Regulation {
+        target_lux: settings.target_lux,
+        min_brightness: settings.min_brightness,
+        max_brightness: settings.max_brightness.max(settings.min_brightness),
+    };
+    let vacancy_time = settings.vacancy_time;
+    let ramp_step_time = settings.ramp_step_time;
+    tokio::spawn(async move {
+        if let Err(error) = constant_light_task(
+            input_stream,
+            sender,
+            regulation,
+            vacancy_time,
+            ramp_step_time,
+        )
+        .await
+        {
+            error!("Failed constant light: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Regulation {
+    target_lux: u32,
+    min_brightness: u8,
+    max_brightness: u8,
+}
+
+impl Regulation {
+    /// Next brightness for the measured illuminance, holds within 5 % of the target
+    fn next_brightness(&self, brightness: u8, illuminance: u32) -> u8 {
+        let target = self.target_lux.max(1) as i64;
+        let error = target - illuminance as i64;
+        if error.abs() * 20 <= target {
+            return brightness.clamp(self.min_brightness, self.max_brightness);
+        }
+        let correction = (error * MAX_CORRECTION / target).clamp(-MAX_CORRECTION, MAX_CORRECTION);
+        let correction = if correction == 0 {
+            error.signum()
+        } else {
+            correction
+        };
+        (brightness as i64 + correction)
+            .clamp(self.min_brightness as i64, self.max_brightness as i64) as u8
+    }
+}
+
+async fn constant_light_task(
+    input: impl Stream<Item = ConstantLightMessage> + Unpin,
+    sender: Sender<Saturating<u8>>,
+    regulation: Regulation,
+    vacancy_time: Duration,
+    ramp_step_time: Duration,
+) -> Result<(), SendError<Saturating<u8>>> {
+    let (tx, rx) = mpsc::channel(2);
+    let mut input = input.merge(ReceiverStream::new(rx));
+
+    let mut occupied = false;
+    let mut manual = false;
+    let mut output_initialized = false;
+    // the brightness of the last occupation is a good start for the next one
+    let mut target_brightness = regulation.max_brightness;
+    let mut sent = None::<u8>;
+    let mut previous_sent = None::<u8>;
+    let mut vacancy_timer = None::<JoinHandleTerminator<()>>;
+    let mut ramp_timer = None::<JoinHandleTerminator<()>>;
+    while let Some(event) = input.next().await {
+        match event {
+            ConstantLightMessage::Presence => {
+                if !occupied {
+                    info!("Room occupied, hold {} lux", regulation.target_lux);
+                    occupied = true;
+                    manual = false;
+                }
+                let tx = tx.clone();
+                vacancy_timer.replace(
+                    tokio::spawn(async move {
+                        sleep(vacancy_time).await;
+                        if let Err(error) = tx.send(ConstantLightMessage::Vacant).await {
+                            error!("Error sending vacancy: {error}");
+                        }
+                    })
+                    .into(),
+                );
+            }
+            ConstantLightMessage::Vacant => {
+                occupied = false;
+                manual = false;
+                vacancy_timer.take();
+                ramp_timer.take();
+                sent = Some(0);
+                sender.send(Saturating(0)).await?;
+            }
+            ConstantLightMessage::Illuminance(lux) => {
+                if occupied && !manual {
+                    target_brightness =
+                        regulation.next_brightness(sent.unwrap_or(target_brightness), lux);
+                }
+            }
+            ConstantLightMessage::OutputBrightness(brightness) => {
+                if !output_initialized {
+                    output_initialized = true;
+                } else if occupied
+                    && !manual
+                    && sent != Some(brightness.0)
+                    && previous_sent != Some(brightness.0)
+                {
+                    info!("Manual brightness, pause constant light until the room is vacant");
+                    manual = true;
+                    ramp_timer.take();
+                }
+            }
+            ConstantLightMessage::RampStep => {
+                if let Some(current) = sent.filter(|_| occupied && !manual) {
+                    let next = match current.cmp(&target_brightness) {
+                        Ordering::Less => current + 1,
+                        Ordering::Greater => current - 1,
+                        Ordering::Equal => current,
+                    };
+                    if next != current {
+                        previous_sent = sent;
+                        sent = Some(next);
+                        sender.send(Saturating(next)).await?;
+                    }
+                }
+            }
+        }
+        if !occupied || manual {
+            continue;
+        }
+        if sent.is_none_or(|s| s == 0) {
+            // switching on jumps to the brightness of the last occupation, the maximum until the
+            // sensor reported, so a missing sensor does not leave the room dark
+            previous_sent = sent;
+            sent = Some(target_brightness);
+            sender.send(Saturating(target_brightness)).await?;
+        }
+        if sent != Some(target_brightness) {
+            if ramp_timer.is_none() {
+                let tx = tx.clone();
+                ramp_timer.replace(
+                    tokio::spawn(async move {
+                        loop {
+                            sleep(ramp_step_time).await;
+                            if let Err(error) = tx.send(ConstantLightMessage::RampStep).await {
+                                error!("Error sending ramp step: {error}");
+                                break;
+                            }
+                        }
+                    })
+                    .into(),
+                );
+            }
+        } else {
+            ramp_timer.take();
+        }
+    }
+    Ok(())
+}
+
+enum ConstantLightMessage {
+    Illuminance(u32),
+    OutputBrightness(Saturating<u8>),
+    Presence,
+    Vacant,
+    RampStep,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::controller::constant_light::Regulation;
+
+    #[test]
+    fn test_regulation() {
+        let regulation = Regulation {
+            target_lux: 500,
+            min_brightness: 20,
+            max_brightness: 250,
+        };
+        // within the tolerance
+        assert_eq!(100, regulation.next_brightness(100, 480));
+        // far too dark, limited correction
+        assert_eq!(116, regulation.next_brightness(100, 0));
+        // slightly too bright, at least one step
+        assert_eq!(99, regulation.next_brightness(100, 560));
+        // limits
+        assert_eq!(250, regulation.next_brightness(245, 0));
+        assert_eq!(20, regulation.next_brightness(25, 5000));
+    }
+}
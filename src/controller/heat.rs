@@ -1,18 +1,71 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use futures::stream::SelectAll;
 use futures::Stream;
-use log::error;
+use log::{error, info};
+use serde::Serialize;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::SendError;
-use tokio::task::AbortHandle;
+use tokio::time::{sleep, Instant};
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 
-use crate::data::registry::{EventRegistry, SwitchOutputKey, TemperatureKey};
+use crate::alarm::{clear_alarm, raise_alarm, AlarmKind};
+use crate::controller::manager::{ControllerHandle, ControllerState, StateSender};
+use crate::data::registry::{
+    ButtonState, EventRegistry, HouseMode, SwitchOutputKey, TemperatureKey,
+};
+use crate::data::wiring::WindowOpenDetection;
+use crate::terminator::JoinHandleTerminator;
+
+/// Limits applied to every heat controller, independent of the user setpoints
+#[derive(Debug, Clone, Copy)]
+pub struct HeatProtection {
+    pub frost_temperature: f32,
+    /// upper limit of the target while nobody is at home
+    pub eco_temperature: f32,
+    pub alarm_temperature_change: f32,
+    pub alarm_settle_time: Duration,
+}
 
 pub async fn heat_controller(
     event_registry: &EventRegistry,
     current_value: TemperatureKey,
     target_value: TemperatureKey,
     output: SwitchOutputKey,
-) -> AbortHandle {
+    window_open_detection: Option<&WindowOpenDetection>,
+    protection: HeatProtection,
+    state: StateSender,
+) -> ControllerHandle {
+    let mut window_contact_streams = SelectAll::new();
+    let mut window_detection = None;
+    if let Some(settings) = window_open_detection {
+        for contact in settings.window_contacts.iter() {
+            window_contact_streams.push(
+                event_registry
+                    .single_button_stream(*contact)
+                    .await
+                    .filter_map(|s| match s {
+                        ButtonState::ShortPressStart(_)
+                        | ButtonState::DoubleClickStart(_)
+                        | ButtonState::TripleClickStart(_) => {
+                            Some(HeatContollerMessage::WindowOpened)
+                        }
+                        ButtonState::Released => Some(HeatContollerMessage::WindowClosed),
+                        _ => None,
+                    }),
+            );
+        }
+        window_detection = Some(WindowDetection {
+            temperature_drop: settings.temperature_drop.map(|d| d as f32 / 100.0),
+            drop_interval: settings.drop_interval,
+            suspend_time: settings.suspend_time,
+            state_sender: event_registry
+                .window_open_sender(settings.state_output)
+                .await,
+        });
+    }
     let input_stream = event_registry
         .temperature_stream(current_value)
         .await
@@ -22,39 +75,370 @@ pub async fn heat_controller(
                 .temperature_stream(target_value)
                 .await
                 .map(HeatContollerMessage::UpdateTargetTemperature),
+        )
+        .merge(window_contact_streams)
+        .merge(
+            event_registry
+                .house_mode_stream()
+                .await
+                .map(HeatContollerMessage::HouseMode),
         );
     let sender = event_registry.switch_sender(output).await;
+    let alarm_source = format!("{output:?}").into_boxed_str();
     tokio::spawn(async move {
-        if let Err(error) = heat_task(input_stream, sender).await {
-            error!("Failed dual input dimmer: {error}")
+        if let Err(error) = heat_task(
+            input_stream,
+            sender,
+            window_detection,
+            protection,
+            alarm_source,
+            state,
+        )
+        .await
+        {
+            error!("Failed heat controller {output:?}: {error}");
+            return Err(error.into());
         }
+        Ok(())
     })
-    .abort_handle()
+}
+
+/// Internal state of a heat controller, published on every event
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct HeatState {
+    pub current_temperature: f32,
+    /// setpoint after the house mode limits
+    pub target_temperature: f32,
+    pub heating: bool,
+    /// heating is suspended by an open window
+    pub window_open: bool,
+    pub frost: bool,
+}
+
+struct WindowDetection {
+    temperature_drop: Option<f32>,
+    drop_interval: Duration,
+    suspend_time: Duration,
+    state_sender: mpsc::Sender<bool>,
 }
 
 async fn heat_task(
-    mut input: impl Stream<Item = HeatContollerMessage> + Unpin,
+    input: impl Stream<Item = HeatContollerMessage> + Unpin,
     output: mpsc::Sender<bool>,
+    window_detection: Option<WindowDetection>,
+    protection: HeatProtection,
+    alarm_source: Box<str>,
+    state: StateSender,
 ) -> Result<(), SendError<bool>> {
+    let (tx, rx) = mpsc::channel(2);
+    let mut input = input.merge(ReceiverStream::new(rx));
+
     let mut current_temperature = 21.0;
     let mut target_temperature = 21.0;
+    let mut recent_temperatures = VecDeque::<(Instant, f32)>::new();
+    let mut window_timer = None::<JoinHandleTerminator<()>>;
+    let mut frost_active = false;
+    let mut house_mode = HouseMode::Home;
+    let mut monitor = OutputMonitor {
+        heating: false,
+        since: Instant::now(),
+        reference: None,
+    };
     while let Some(event) = input.next().await {
+        let mut window_opened = false;
+        let mut temperature_updated = false;
         match event {
             HeatContollerMessage::UpdateTargetTemperature(target) => {
                 target_temperature = target;
             }
             HeatContollerMessage::UpdateCurrentTemperature(current) => {
                 current_temperature = current;
+                temperature_updated = true;
+                if let Some(WindowDetection {
+                    temperature_drop: Some(temperature_drop),
+                    drop_interval,
+                    ..
+                }) = &window_detection
+                {
+                    if detect_temperature_drop(
+                        &mut recent_temperatures,
+                        Instant::now(),
+                        current,
+                        *drop_interval,
+                        *temperature_drop,
+                    ) {
+                        info!("Fast temperature drop to {current:.2}°C, assuming open window");
+                        recent_temperatures.clear();
+                        window_opened = true;
+                    }
+                }
+            }
+            HeatContollerMessage::WindowOpened => {
+                window_opened = true;
+            }
+            HeatContollerMessage::WindowTimeout => {
+                window_timer.take();
+                if let Some(detection) = &window_detection {
+                    detection.state_sender.send(false).await?;
+                }
+            }
+            HeatContollerMessage::WindowClosed => {
+                // a closed contact resumes heating before the suspend time is over
+                if window_timer.take().is_some() {
+                    if let Some(detection) = &window_detection {
+                        detection.state_sender.send(false).await?;
+                    }
+                }
+            }
+            HeatContollerMessage::HouseMode(mode) => {
+                house_mode = mode;
             }
         };
-        output
-            .send(current_temperature < target_temperature)
-            .await?;
+        if window_opened {
+            if let Some(detection) = &window_detection {
+                detection.state_sender.send(true).await?;
+                start_window_timer(detection.suspend_time, &mut window_timer, &tx);
+            }
+        }
+        let frost = current_temperature < protection.frost_temperature;
+        if frost != frost_active {
+            frost_active = frost;
+            if frost {
+                raise_alarm(&alarm_source, AlarmKind::Frost);
+            } else {
+                clear_alarm(&alarm_source, AlarmKind::Frost);
+            }
+        }
+        let effective_target = effective_target(target_temperature, house_mode, &protection);
+        let heat = frost || (window_timer.is_none() && current_temperature < effective_target);
+        let now = Instant::now();
+        if monitor.update_output(heat, now) {
+            clear_alarm(&alarm_source, AlarmKind::RisingWhileHeatOff);
+            clear_alarm(&alarm_source, AlarmKind::FallingWhileHeatOn);
+        }
+        if temperature_updated {
+            if let Some(alarm) = monitor.observe(current_temperature, now, &protection) {
+                raise_alarm(&alarm_source, alarm);
+            }
+        }
+        output.send(heat).await?;
+        state.send_replace(ControllerState::Heat(HeatState {
+            current_temperature,
+            target_temperature: effective_target,
+            heating: heat,
+            window_open: window_timer.is_some(),
+            frost,
+        }));
     }
     Ok(())
 }
 
+/// The user setpoint at home, lowered while nobody is at home
+fn effective_target(target: f32, house_mode: HouseMode, protection: &HeatProtection) -> f32 {
+    match house_mode {
+        HouseMode::Home => target,
+        HouseMode::Away => target.min(protection.eco_temperature),
+        // the frost protection keeps the heating running when needed
+        HouseMode::Vacation => protection.frost_temperature,
+    }
+}
+
+/// Watches the temperature trend against the heat output to detect stuck relays or valves
+struct OutputMonitor {
+    heating: bool,
+    since: Instant,
+    reference: Option<f32>,
+}
+
+impl OutputMonitor {
+    fn update_output(&mut self, heating: bool, now: Instant) -> bool {
+        if self.heating == heating {
+            return false;
+        }
+        self.heating = heating;
+        self.since = now;
+        self.reference = None;
+        true
+    }
+    fn observe(
+        &mut self,
+        current: f32,
+        now: Instant,
+        protection: &HeatProtection,
+    ) -> Option<AlarmKind> {
+        if now.duration_since(self.since) < protection.alarm_settle_time {
+            return None;
+        }
+        let reference = self.reference.get_or_insert(current);
+        if self.heating {
+            *reference = reference.max(current);
+            (*reference - current >= protection.alarm_temperature_change)
+                .then_some(AlarmKind::FallingWhileHeatOn)
+        } else {
+            *reference = reference.min(current);
+            (current - *reference >= protection.alarm_temperature_change)
+                .then_some(AlarmKind::RisingWhileHeatOff)
+        }
+    }
+}
+
+fn start_window_timer(
+    suspend_time: Duration,
+    timer_handle: &mut Option<JoinHandleTerminator<()>>,
+    tx: &mpsc::Sender<HeatContollerMessage>,
+) {
+    let tx = tx.clone();
+    timer_handle.replace(
+        tokio::spawn(async move {
+            sleep(suspend_time).await;
+            if let Err(error) = tx.send(HeatContollerMessage::WindowTimeout).await {
+                error!("Error sending window timeout: {error}");
+            }
+        })
+        .into(),
+    );
+}
+
+fn detect_temperature_drop(
+    recent_temperatures: &mut VecDeque<(Instant, f32)>,
+    now: Instant,
+    current: f32,
+    drop_interval: Duration,
+    temperature_drop: f32,
+) -> bool {
+    while let Some((timestamp, _)) = recent_temperatures.front() {
+        if now.duration_since(*timestamp) > drop_interval {
+            recent_temperatures.pop_front();
+        } else {
+            break;
+        }
+    }
+    recent_temperatures.push_back((now, current));
+    recent_temperatures
+        .iter()
+        .map(|(_, t)| *t)
+        .fold(current, f32::max)
+        - current
+        >= temperature_drop
+}
+
 enum HeatContollerMessage {
     UpdateTargetTemperature(f32),
     UpdateCurrentTemperature(f32),
+    WindowOpened,
+    WindowClosed,
+    WindowTimeout,
+    HouseMode(HouseMode),
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::alarm::AlarmKind;
+    use crate::controller::heat::{
+        detect_temperature_drop, effective_target, HeatProtection, OutputMonitor,
+    };
+    use crate::data::registry::HouseMode;
+
+    #[test]
+    fn test_detect_temperature_drop() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(180);
+        let mut history = VecDeque::new();
+        assert!(!detect_temperature_drop(
+            &mut history,
+            start,
+            21.0,
+            interval,
+            0.5
+        ));
+        assert!(!detect_temperature_drop(
+            &mut history,
+            start + Duration::from_secs(60),
+            20.7,
+            interval,
+            0.5
+        ));
+        assert!(detect_temperature_drop(
+            &mut history,
+            start + Duration::from_secs(120),
+            20.4,
+            interval,
+            0.5
+        ));
+        let mut history = VecDeque::new();
+        assert!(!detect_temperature_drop(
+            &mut history,
+            start,
+            21.0,
+            interval,
+            0.5
+        ));
+        assert!(!detect_temperature_drop(
+            &mut history,
+            start + Duration::from_secs(600),
+            20.0,
+            interval,
+            0.5
+        ));
+    }
+
+    #[test]
+    fn test_output_monitor() {
+        let protection = HeatProtection {
+            frost_temperature: 7.0,
+            eco_temperature: 17.0,
+            alarm_temperature_change: 1.0,
+            alarm_settle_time: Duration::from_secs(600),
+        };
+        let start = Instant::now();
+        let mut monitor = OutputMonitor {
+            heating: false,
+            since: start,
+            reference: None,
+        };
+        assert!(monitor.update_output(true, start));
+        assert_eq!(
+            None,
+            monitor.observe(20.0, start + Duration::from_secs(60), &protection)
+        );
+        assert_eq!(
+            None,
+            monitor.observe(20.0, start + Duration::from_secs(700), &protection)
+        );
+        assert_eq!(
+            Some(AlarmKind::FallingWhileHeatOn),
+            monitor.observe(18.9, start + Duration::from_secs(1200), &protection)
+        );
+        assert!(monitor.update_output(false, start + Duration::from_secs(1300)));
+        assert_eq!(
+            None,
+            monitor.observe(19.0, start + Duration::from_secs(2000), &protection)
+        );
+        assert_eq!(
+            Some(AlarmKind::RisingWhileHeatOff),
+            monitor.observe(20.5, start + Duration::from_secs(2600), &protection)
+        );
+    }
+
+    #[test]
+    fn test_effective_target() {
+        let protection = HeatProtection {
+            frost_temperature: 7.0,
+            eco_temperature: 17.0,
+            alarm_temperature_change: 1.0,
+            alarm_settle_time: Duration::from_secs(600),
+        };
+        assert_eq!(21.0, effective_target(21.0, HouseMode::Home, &protection));
+        assert_eq!(17.0, effective_target(21.0, HouseMode::Away, &protection));
+        assert_eq!(16.0, effective_target(16.0, HouseMode::Away, &protection));
+        assert_eq!(
+            7.0,
+            effective_target(21.0, HouseMode::Vacation, &protection)
+        );
+    }
 }
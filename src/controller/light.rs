@@ -1,51 +1,157 @@
-use std::{hash::Hash, num::Saturating, time::Duration};
+use std::{fmt::Debug, hash::Hash, num::Saturating, time::Duration};
 
+use chrono::NaiveTime;
 use futures::stream::SelectAll;
 use log::error;
+use serde::{Serialize, Serializer};
 use tokio::{
     sync::mpsc::{self, error::SendError, Sender},
-    task::AbortHandle,
-    time::sleep,
+    time::{sleep, Instant},
 };
 use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 
 use crate::{
+    controller::manager::{ControllerHandle, ControllerState, StateSender},
     data::registry::{
-        BrightnessKey, ButtonState, DualButtonKey, DualButtonLayout, EventRegistry,
-        SingleButtonKey, SingleButtonLayout, SwitchOutputKey,
+        ButtonState, DualButtonKey, DualButtonLayout, EventRegistry, HouseMode, SingleButtonKey,
+        SingleButtonLayout, SwitchOutputKey,
+    },
+    data::wiring::{
+        DaylightThreshold, DualInputDimmer, MotionDetector, PreWarning, SingleInputDimmer,
+        TimeSchedule, TimeWindow,
     },
     terminator::JoinHandleTerminator,
-    util::optional_stream,
+    util::{optional_stream, time_in_range},
 };
 
 pub async fn dual_input_dimmer(
     event_registry: &EventRegistry,
-    inputs: &[DualButtonKey],
-    output: BrightnessKey,
-    auto_switch_off_time: Duration,
-    presences: &[SingleButtonKey],
-) -> AbortHandle {
+    settings: &DualInputDimmer,
+    state: StateSender,
+) -> ControllerHandle {
     let current_brightness = event_registry
-        .brightness_stream(output)
+        .brightness_stream(settings.output)
         .await
         .next()
         .await
         .unwrap_or_default();
-    let sender = event_registry.brightness_sender(output).await;
-    let input_stream = merge_dual_buttons_and_presences(event_registry, inputs, presences).await;
+    let sender = event_registry.brightness_sender(settings.output).await;
+    let schedule = settings.schedule.as_ref();
+    let input_stream =
+        merge_dual_buttons_and_presences(event_registry, &settings.input, &settings.presence)
+            .await
+            .merge(create_schedule_stream(event_registry, schedule).await);
+    let switch_off = SwitchOff::new(
+        settings.auto_switch_off_time,
+        settings.pre_warning,
+        schedule,
+    );
+    let dimm_duration = settings.dimm_duration;
     tokio::spawn(async move {
         if let Err(error) = dual_input_dimmer_task(
             input_stream,
-            auto_switch_off_time,
+            switch_off,
+            dimm_duration,
             current_brightness,
             sender,
+            LightStateReporter::new(state),
         )
         .await
         {
-            error!("Failed dual input dimmer: {error}")
+            error!("Failed dual input dimmer: {error}");
+            return Err(error.into());
         }
+        Ok(())
     })
-    .abort_handle()
+}
+
+pub async fn single_input_dimmer(
+    event_registry: &EventRegistry,
+    settings: &SingleInputDimmer,
+    state: StateSender,
+) -> ControllerHandle {
+    let output = settings.output;
+    let sender = event_registry.brightness_sender(output).await;
+    let input_stream =
+        merge_single_buttons_and_presences(event_registry, &settings.input, &settings.presence)
+            .await
+            .merge(
+                event_registry
+                    .brightness_stream(output)
+                    .await
+                    .map(DimmerEvent::OutputBrightness),
+            );
+    let auto_switch_off_time = settings.auto_switch_off_time;
+    let pre_warning = settings.pre_warning;
+    let dimm_duration = settings.dimm_duration;
+    tokio::spawn(async move {
+        if let Err(error) = single_input_dimmer_task(
+            input_stream,
+            auto_switch_off_time,
+            pre_warning,
+            dimm_duration,
+            sender,
+            LightStateReporter::new(state),
+        )
+        .await
+        {
+            error!("Failed single input dimmer: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+pub async fn single_input_switch(
+    event_registry: &EventRegistry,
+    inputs: &[SingleButtonKey],
+    output: SwitchOutputKey,
+    auto_switch_off_time: Duration,
+    presences: &[SingleButtonKey],
+    pre_warning: Option<PreWarning>,
+    state: StateSender,
+) -> ControllerHandle {
+    let sender = event_registry.switch_sender(output).await;
+    let input_stream = merge_single_buttons_and_presences(event_registry, inputs, presences)
+        .await
+        .merge(
+            event_registry
+                .switch_stream(output)
+                .await
+                .map(DimmerEvent::OutputSwitch),
+        );
+    tokio::spawn(async move {
+        if let Err(error) = single_input_switch_task(
+            auto_switch_off_time,
+            pre_warning,
+            sender,
+            input_stream,
+            LightStateReporter::new(state),
+        )
+        .await
+        {
+            error!("Failed single input switch: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+async fn merge_single_buttons_and_presences(
+    event_registry: &EventRegistry,
+    inputs: &[SingleButtonKey],
+    presences: &[SingleButtonKey],
+) -> impl Stream<Item = DimmerEvent<SingleButtonLayout>> + Unpin {
+    let mut button_streams = SelectAll::new();
+    for input in inputs {
+        button_streams.push(
+            event_registry
+                .single_button_stream(*input)
+                .await
+                .map(DimmerEvent::ButtonState),
+        );
+    }
+    button_streams.merge(create_presences_stream(event_registry, presences).await)
 }
 
 async fn merge_dual_buttons_and_presences(
@@ -76,13 +182,68 @@ async fn create_presences_stream<L: Copy + Eq + Hash>(
                 .single_button_stream(*presence_key)
                 .await
                 .filter_map(|s| match s {
-                    ButtonState::ShortPressStart(_) => Some(DimmerEvent::<L>::PresenceDetected),
+                    ButtonState::ShortPressStart(_)
+                    | ButtonState::DoubleClickStart(_)
+                    | ButtonState::TripleClickStart(_) => Some(DimmerEvent::<L>::PresenceDetected),
                     _ => None,
                 }),
         );
     }
     presence_streams
 }
+async fn create_schedule_stream<L: Copy + Eq + Hash>(
+    event_registry: &EventRegistry,
+    schedule: Option<&TimeSchedule>,
+) -> impl Stream<Item = DimmerEvent<L>> + Unpin {
+    optional_stream(schedule.map(|s| event_registry.clock(s.clock)))
+        .await
+        .map(|now| DimmerEvent::Time(now.time()))
+}
+
+async fn create_illuminance_stream<L: Copy + Eq + Hash>(
+    event_registry: &EventRegistry,
+    daylight: Option<DaylightThreshold>,
+) -> impl Stream<Item = DimmerEvent<L>> + Unpin {
+    optional_stream(daylight.map(|d| event_registry.illuminance_stream(d.sensor)))
+        .await
+        .map(DimmerEvent::Illuminance)
+}
+
+/// Automatic switch off of a light, the time windows of the schedule override the default time
+struct SwitchOff {
+    default_time: Duration,
+    pre_warning: Option<PreWarning>,
+    windows: Box<[TimeWindow]>,
+}
+
+impl SwitchOff {
+    fn new(
+        default_time: Duration,
+        pre_warning: Option<PreWarning>,
+        schedule: Option<&TimeSchedule>,
+    ) -> Self {
+        Self {
+            default_time,
+            pre_warning,
+            windows: schedule
+                .map(|s| s.windows.clone())
+                .unwrap_or_else(|| Box::new([])),
+        }
+    }
+}
+
+/// Switch off time and brightness of the first window containing the time
+fn scheduled_values(
+    windows: &[TimeWindow],
+    time: NaiveTime,
+    default_switch_off_time: Duration,
+) -> (Duration, Option<Saturating<u8>>) {
+    windows
+        .iter()
+        .find(|w| time_in_range(w.start, w.end, time))
+        .map(|w| (w.switch_off_time, w.brightness.map(Saturating)))
+        .unwrap_or((default_switch_off_time, None))
+}
 /*
 async fn create_presence_stream<L: Copy + Eq + Hash>(
     event_registry: &EventRegistry,
@@ -102,7 +263,9 @@ pub async fn dual_input_switch(
     output: SwitchOutputKey,
     auto_switch_off_time: Duration,
     presences: &[SingleButtonKey],
-) -> AbortHandle {
+    pre_warning: Option<PreWarning>,
+    state: StateSender,
+) -> ControllerHandle {
     let current_state = event_registry
         .switch_stream(output)
         .await
@@ -112,185 +275,500 @@ pub async fn dual_input_switch(
     let sender = event_registry.switch_sender(output).await;
     let input_stream = merge_dual_buttons_and_presences(event_registry, inputs, presences).await;
     tokio::spawn(async move {
-        if let Err(error) =
-            dual_input_switch_task(auto_switch_off_time, current_state, sender, input_stream).await
+        if let Err(error) = dual_input_switch_task(
+            auto_switch_off_time,
+            pre_warning,
+            current_state,
+            sender,
+            input_stream,
+            LightStateReporter::new(state),
+        )
+        .await
         {
-            error!("Failed dual input switch: {error}")
+            error!("Failed dual input switch: {error}");
+            return Err(error.into());
         }
+        Ok(())
     })
-    .abort_handle()
 }
 
 pub async fn motion_detector(
     event_registry: &EventRegistry,
-    inputs: &[SingleButtonKey],
-    output: SwitchOutputKey,
-    switch_off_time: Duration,
-) -> AbortHandle {
-    let sender = event_registry.switch_sender(output).await;
-    let current_value = event_registry.switch_stream(output).await.next().await;
-    let input_stream = create_presences_stream(event_registry, inputs).await;
-    tokio::spawn(async move {
-        if let Err(error) =
-            motion_detector_task(switch_off_time, sender, input_stream, current_value).await
-        {
-            error!("Failed motion detector: {error}")
+    settings: &MotionDetector,
+    state: StateSender,
+) -> ControllerHandle {
+    match settings {
+        MotionDetector::Switch {
+            input,
+            output,
+            switch_off_time,
+            pre_warning,
+            schedule,
+            daylight,
+        } => {
+            let sender = event_registry.switch_sender(*output).await;
+            let current_value = event_registry.switch_stream(*output).await.next().await;
+            let input_stream = create_presences_stream(event_registry, input)
+                .await
+                .merge(create_schedule_stream(event_registry, schedule.as_ref()).await)
+                .merge(create_illuminance_stream(event_registry, *daylight).await)
+                .merge(
+                    event_registry
+                        .house_mode_stream()
+                        .await
+                        .map(DimmerEvent::HouseMode),
+                );
+            let switch_off = SwitchOff::new(*switch_off_time, *pre_warning, schedule.as_ref());
+            let daylight_lux = daylight.map(|d| d.lux);
+            tokio::spawn(async move {
+                if let Err(error) = motion_detector_task(
+                    switch_off,
+                    daylight_lux,
+                    sender,
+                    input_stream,
+                    current_value,
+                    LightStateReporter::new(state),
+                )
+                .await
+                {
+                    error!("Failed motion detector: {error}");
+                    return Err(error.into());
+                }
+                Ok(())
+            })
         }
-    })
-    .abort_handle()
-}
-pub async fn motion_detector_dimmer(
-    event_registry: &EventRegistry,
-    inputs: &[SingleButtonKey],
-    brightness: Option<BrightnessKey>,
-    output: BrightnessKey,
-    switch_off_time: Duration,
-) -> AbortHandle {
-    let current_brightness = event_registry.brightness_stream(output).await.next().await;
-    let sender = event_registry.brightness_sender(output).await;
-    let input_stream = create_presences_stream(event_registry, inputs).await.merge(
-        optional_stream(brightness.map(|k| event_registry.brightness_stream(k)))
-            .await
-            .map(DimmerEvent::SetBrightness),
-    );
-    tokio::spawn(async move {
-        if let Err(error) =
-            motion_detector_dimmer_task(switch_off_time, sender, input_stream, current_brightness)
+        MotionDetector::Dimmer {
+            input,
+            output,
+            brightness,
+            switch_off_time,
+            pre_warning,
+            schedule,
+            daylight,
+        } => {
+            let current_brightness = event_registry.brightness_stream(*output).await.next().await;
+            let sender = event_registry.brightness_sender(*output).await;
+            let input_stream = create_presences_stream(event_registry, input)
                 .await
-        {
-            error!("Failed motion detector: {error}")
+                .merge(
+                    optional_stream(brightness.map(|k| event_registry.brightness_stream(k)))
+                        .await
+                        .map(DimmerEvent::SetBrightness),
+                )
+                .merge(create_schedule_stream(event_registry, schedule.as_ref()).await)
+                .merge(create_illuminance_stream(event_registry, *daylight).await)
+                .merge(
+                    event_registry
+                        .house_mode_stream()
+                        .await
+                        .map(DimmerEvent::HouseMode),
+                );
+            let switch_off = SwitchOff::new(*switch_off_time, *pre_warning, schedule.as_ref());
+            let daylight_lux = daylight.map(|d| d.lux);
+            tokio::spawn(async move {
+                if let Err(error) = motion_detector_dimmer_task(
+                    switch_off,
+                    daylight_lux,
+                    sender,
+                    input_stream,
+                    current_brightness,
+                    LightStateReporter::new(state),
+                )
+                .await
+                {
+                    error!("Failed motion detector: {error}");
+                    return Err(error.into());
+                }
+                Ok(())
+            })
         }
-    })
-    .abort_handle()
+    }
 }
 
 async fn motion_detector_dimmer_task(
-    auto_switch_off_time: Duration,
+    switch_off: SwitchOff,
+    daylight_lux: Option<u32>,
     output_sender: Sender<Saturating<u8>>,
     input_stream: impl Stream<Item = DimmerEvent<SingleButtonLayout>> + Sized + Unpin,
     current_brightness: Option<Saturating<u8>>,
+    mut reporter: LightStateReporter,
 ) -> Result<(), SendError<Saturating<u8>>> {
+    let SwitchOff {
+        default_time: default_switch_off_time,
+        pre_warning,
+        windows,
+    } = switch_off;
     let (tx, rx) = mpsc::channel(2);
 
     let mut timer_handle = None::<JoinHandleTerminator<()>>;
+    let mut auto_switch_off_time = default_switch_off_time;
+    let mut window_brightness = None;
     let mut on_brightness = Saturating(255);
+    let mut output_brightness = on_brightness;
     let mut light_enabled = false;
+    let mut illuminance = 0;
+    let mut house_mode = HouseMode::Home;
+    // a brightness set while the light is on wins over the schedule until the switch off
+    let mut manual_brightness = false;
+    let mut warning = false;
     if let Some(br) = current_brightness {
         if br.0 > 0 {
             light_enabled = true;
             on_brightness = br;
-            start_switchoff_timer(auto_switch_off_time, &mut timer_handle, &tx);
+            output_brightness = br;
+            reporter.switch_off_at = Some(start_switchoff_timer(
+                auto_switch_off_time,
+                pre_warning,
+                &mut timer_handle,
+                &tx,
+            ));
         } else {
             light_enabled = false;
         }
     }
     let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
     while let Some(event) = input_stream.next().await {
+        reporter.record(&event);
         match event {
             DimmerEvent::ButtonState(_) => {}
             DimmerEvent::KeepPressing(_) => {}
             DimmerEvent::AutoSwitchOff => {
                 output_sender.send(Saturating(0)).await?;
                 light_enabled = false;
+                manual_brightness = false;
+                warning = false;
             }
+            DimmerEvent::PreWarning => {
+                if light_enabled {
+                    warning = true;
+                    output_sender
+                        .send(warning_brightness(pre_warning, output_brightness))
+                        .await?;
+                }
+            }
+            DimmerEvent::FlashEnd => {
+                if warning && is_flashing(pre_warning) {
+                    warning = false;
+                    output_sender.send(output_brightness).await?;
+                }
+            }
+            // a light already on stays on, it raises the illuminance itself
+            DimmerEvent::PresenceDetected
+                if !light_enabled && daylight_lux.is_some_and(|lux| illuminance > lux) => {}
+            // movements while nobody is at home are pets or intruders
+            DimmerEvent::PresenceDetected if !light_enabled && house_mode != HouseMode::Home => {}
             DimmerEvent::PresenceDetected => {
-                output_sender.send(on_brightness).await?;
+                if !manual_brightness {
+                    output_brightness = window_brightness.unwrap_or(on_brightness);
+                }
+                output_sender.send(output_brightness).await?;
                 light_enabled = true;
-                start_switchoff_timer(auto_switch_off_time, &mut timer_handle, &tx);
+                warning = false;
+                reporter.switch_off_at = Some(start_switchoff_timer(
+                    auto_switch_off_time,
+                    pre_warning,
+                    &mut timer_handle,
+                    &tx,
+                ));
             }
             DimmerEvent::SetBrightness(br) => {
                 on_brightness = br;
                 if light_enabled {
-                    output_sender.send(on_brightness).await?;
+                    manual_brightness = true;
+                    output_brightness = br;
+                    if !warning {
+                        output_sender.send(output_brightness).await?;
+                    }
                 }
             }
+            DimmerEvent::Time(time) => {
+                (auto_switch_off_time, window_brightness) =
+                    scheduled_values(&windows, time, default_switch_off_time);
+            }
+            DimmerEvent::Illuminance(lux) => {
+                illuminance = lux;
+            }
+            DimmerEvent::HouseMode(mode) => {
+                house_mode = mode;
+            }
+            DimmerEvent::OutputBrightness(_) | DimmerEvent::OutputSwitch(_) => {}
         }
+        reporter.publish(light_enabled, false, warning);
     }
     Ok(())
 }
 
 fn start_switchoff_timer<T: Copy + Eq + Hash + Send + 'static>(
     auto_switch_off_time: Duration,
+    pre_warning: Option<PreWarning>,
     timer_handle: &mut Option<JoinHandleTerminator<()>>,
     tx: &Sender<DimmerEvent<T>>,
-) {
+) -> Instant {
     let tx = tx.clone();
     timer_handle.replace(
         tokio::spawn(async move {
-            loop {
-                sleep(auto_switch_off_time).await;
-                if let Err(error) = tx.send(DimmerEvent::<T>::AutoSwitchOff).await {
-                    error!("Error sending switchoff: {error}");
-                    break;
-                }
+            if let Err(error) = switchoff_timer(auto_switch_off_time, pre_warning, &tx).await {
+                error!("Error sending switchoff: {error}");
             }
         })
         .into(),
     );
+    Instant::now() + auto_switch_off_time
 }
 
-async fn motion_detector_task(
+async fn switchoff_timer<T: Copy + Eq + Hash>(
     auto_switch_off_time: Duration,
+    pre_warning: Option<PreWarning>,
+    tx: &Sender<DimmerEvent<T>>,
+) -> Result<(), SendError<DimmerEvent<T>>> {
+    let pre_warning = pre_warning.filter(|w| w.lead_time < auto_switch_off_time);
+    loop {
+        if let Some(PreWarning {
+            lead_time,
+            flash_time,
+            ..
+        }) = pre_warning
+        {
+            sleep(auto_switch_off_time - lead_time).await;
+            tx.send(DimmerEvent::PreWarning).await?;
+            let flash_time = flash_time.min(lead_time);
+            sleep(flash_time).await;
+            tx.send(DimmerEvent::FlashEnd).await?;
+            sleep(lead_time - flash_time).await;
+        } else {
+            sleep(auto_switch_off_time).await;
+        }
+        tx.send(DimmerEvent::AutoSwitchOff).await?;
+    }
+}
+
+/// Output value while the pre-warning is active, a dimmer without warning level flashes
+fn warning_brightness(
+    pre_warning: Option<PreWarning>,
+    on_brightness: Saturating<u8>,
+) -> Saturating<u8> {
+    pre_warning
+        .and_then(|w| w.brightness)
+        .map(|brightness| Saturating(brightness).min(on_brightness))
+        .unwrap_or(Saturating(0))
+}
+
+/// Dimming to a warning level lasts until the switch off, a flash ends early
+fn is_flashing(pre_warning: Option<PreWarning>) -> bool {
+    pre_warning.is_some_and(|w| w.brightness.is_none())
+}
+
+async fn motion_detector_task(
+    switch_off: SwitchOff,
+    daylight_lux: Option<u32>,
     output_sender: Sender<bool>,
     input_stream: impl Stream<Item = DimmerEvent<SingleButtonLayout>> + Sized + Unpin,
     current_value: Option<bool>,
+    mut reporter: LightStateReporter,
 ) -> Result<(), SendError<bool>> {
+    let SwitchOff {
+        default_time: default_switch_off_time,
+        pre_warning,
+        windows,
+    } = switch_off;
     let (tx, rx) = mpsc::channel(2);
 
     let mut timer_handle = None::<JoinHandleTerminator<()>>;
-    if let Some(current_state) = current_value {
-        if current_state {
-            start_switchoff_timer(auto_switch_off_time, &mut timer_handle, &tx);
-        }
+    let mut auto_switch_off_time = default_switch_off_time;
+    let mut light_enabled = current_value.unwrap_or_default();
+    let mut illuminance = 0;
+    let mut house_mode = HouseMode::Home;
+    let mut warning = false;
+    if light_enabled {
+        reporter.switch_off_at = Some(start_switchoff_timer(
+            auto_switch_off_time,
+            pre_warning,
+            &mut timer_handle,
+            &tx,
+        ));
     }
     let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
     while let Some(event) = input_stream.next().await {
+        reporter.record(&event);
         match event {
             DimmerEvent::ButtonState(_) => {}
             DimmerEvent::KeepPressing(_) => {}
             DimmerEvent::AutoSwitchOff => {
                 output_sender.send(false).await?;
+                light_enabled = false;
+                warning = false;
             }
+            DimmerEvent::PreWarning => {
+                if light_enabled {
+                    warning = true;
+                    output_sender.send(false).await?;
+                }
+            }
+            DimmerEvent::FlashEnd => {
+                if warning {
+                    warning = false;
+                    output_sender.send(true).await?;
+                }
+            }
+            // a light already on stays on, it raises the illuminance itself
+            DimmerEvent::PresenceDetected
+                if !light_enabled && daylight_lux.is_some_and(|lux| illuminance > lux) => {}
+            // movements while nobody is at home are pets or intruders
+            DimmerEvent::PresenceDetected if !light_enabled && house_mode != HouseMode::Home => {}
             DimmerEvent::PresenceDetected => {
                 output_sender.send(true).await?;
-                start_switchoff_timer(auto_switch_off_time, &mut timer_handle, &tx);
+                light_enabled = true;
+                warning = false;
+                reporter.switch_off_at = Some(start_switchoff_timer(
+                    auto_switch_off_time,
+                    pre_warning,
+                    &mut timer_handle,
+                    &tx,
+                ));
             }
             DimmerEvent::SetBrightness(_) => {}
+            DimmerEvent::Time(time) => {
+                (auto_switch_off_time, _) =
+                    scheduled_values(&windows, time, default_switch_off_time);
+            }
+            DimmerEvent::Illuminance(lux) => {
+                illuminance = lux;
+            }
+            DimmerEvent::HouseMode(mode) => {
+                house_mode = mode;
+            }
+            DimmerEvent::OutputBrightness(_) | DimmerEvent::OutputSwitch(_) => {}
         }
+        reporter.publish(light_enabled, false, warning);
     }
     Ok(())
 }
 
+/// Internal state of a light controller, published on every event
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct LightState {
+    /// seconds until the automatic switch off
+    #[serde(rename = "auto_off_seconds", serialize_with = "serialize_remaining")]
+    pub auto_off: Option<Instant>,
+    pub last_button: Option<Box<str>>,
+    pub long_press: bool,
+    pub warning: bool,
+}
+
+fn serialize_remaining<S: Serializer>(
+    deadline: &Option<Instant>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    deadline
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs())
+        .serialize(serializer)
+}
+
+struct LightStateReporter {
+    sender: StateSender,
+    last_button: Option<Box<str>>,
+    switch_off_at: Option<Instant>,
+}
+
+impl LightStateReporter {
+    fn new(sender: StateSender) -> Self {
+        Self {
+            sender,
+            last_button: None,
+            switch_off_at: None,
+        }
+    }
+    fn record<L: Copy + Eq + Hash + Debug>(&mut self, event: &DimmerEvent<L>) {
+        match event {
+            DimmerEvent::ButtonState(ButtonState::Released) => {}
+            DimmerEvent::ButtonState(state) => {
+                self.last_button = Some(format!("{state:?}").into());
+            }
+            _ => {}
+        }
+    }
+    fn publish(&self, on: bool, long_press: bool, warning: bool) {
+        self.sender.send_replace(ControllerState::Light(LightState {
+            // dimming replaces the switch off timer
+            auto_off: self.switch_off_at.filter(|_| on && !long_press),
+            last_button: self.last_button.clone(),
+            long_press,
+            warning,
+        }));
+    }
+}
+
 enum DimmerEvent<L: Copy + Eq + Hash> {
     ButtonState(ButtonState<L>),
     KeepPressing(L),
     AutoSwitchOff,
+    /// the auto switch off is coming soon
+    PreWarning,
+    /// end of the flash at the start of the pre-warning
+    FlashEnd,
     PresenceDetected,
     SetBrightness(Saturating<u8>),
+    /// time of day for scheduled brightness and switch off time
+    Time(NaiveTime),
+    /// ambient light in lux
+    Illuminance(u32),
+    HouseMode(HouseMode),
+    /// current value of the output register, changed by any source
+    OutputBrightness(Saturating<u8>),
+    OutputSwitch(bool),
 }
 
 async fn dual_input_dimmer_task(
     input_stream: impl Stream<Item = DimmerEvent<DualButtonLayout>> + Unpin,
-    auto_switch_off_time: Duration,
+    switch_off: SwitchOff,
+    dimm_duration: Duration,
     mut current_brightness: Saturating<u8>,
     sender: Sender<Saturating<u8>>,
+    mut reporter: LightStateReporter,
 ) -> Result<(), SendError<Saturating<u8>>> {
+    let SwitchOff {
+        default_time: default_switch_off_time,
+        pre_warning,
+        windows,
+    } = switch_off;
     let (tx, rx) = mpsc::channel(2);
+    let dimm_step_time = (dimm_duration / u8::MAX as u32).max(Duration::from_millis(1));
 
     let mut last_on_brightness = current_brightness;
     let mut is_long_press = false;
     let mut last_button = None;
+    let mut warning = false;
+    let mut auto_switch_off_time = default_switch_off_time;
     let mut dimm_timer_handle = None::<JoinHandleTerminator<()>>;
     if current_brightness.0 > 0 {
-        start_switchoff_timer(auto_switch_off_time, &mut dimm_timer_handle, &tx);
+        reporter.switch_off_at = Some(start_switchoff_timer(
+            auto_switch_off_time,
+            pre_warning,
+            &mut dimm_timer_handle,
+            &tx,
+        ));
     }
 
     let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
     while let Some(event) = input_stream.next().await {
+        reporter.record(&event);
+        if let DimmerEvent::ButtonState(
+            ButtonState::ShortPressStart(_)
+            | ButtonState::DoubleClickStart(_)
+            | ButtonState::TripleClickStart(_)
+            | ButtonState::LongPressStart(_),
+        ) = event
+        {
+            // every press sends a new value anyway
+            warning = false;
+        }
         match event {
             DimmerEvent::ButtonState(ButtonState::Released) => {
+                let pressed = is_long_press || last_button.is_some();
                 if !is_long_press {
                     match last_button {
+                        // manual presses keep the last brightness, the scheduled one is for
+                        // presence only
                         Some(DualButtonLayout::Up) => {
                             if last_on_brightness.0 > 0 {
                                 current_brightness = last_on_brightness;
@@ -309,17 +787,47 @@ async fn dual_input_dimmer_task(
                 } else {
                     last_on_brightness = current_brightness;
                 }
-                last_button = None;
-                if current_brightness.0 > 0 {
-                    start_switchoff_timer(auto_switch_off_time, &mut dimm_timer_handle, &tx);
-                } else {
-                    dimm_timer_handle.take();
+                // a release without a press before leaves a pending warning running
+                if pressed {
+                    if current_brightness.0 > 0 {
+                        reporter.switch_off_at = Some(start_switchoff_timer(
+                            auto_switch_off_time,
+                            pre_warning,
+                            &mut dimm_timer_handle,
+                            &tx,
+                        ));
+                    } else {
+                        dimm_timer_handle.take();
+                    }
                 }
+                is_long_press = false;
+                last_button = None;
             }
             DimmerEvent::ButtonState(ButtonState::ShortPressStart(button)) => {
                 is_long_press = false;
                 last_button = Some(button);
             }
+            DimmerEvent::ButtonState(
+                ButtonState::DoubleClickStart(button) | ButtonState::TripleClickStart(button),
+            ) => {
+                is_long_press = false;
+                last_button = None;
+                current_brightness = match button {
+                    DualButtonLayout::Up => Saturating(255),
+                    DualButtonLayout::Down => Saturating(0),
+                };
+                sender.send(current_brightness).await?;
+                if current_brightness.0 > 0 {
+                    reporter.switch_off_at = Some(start_switchoff_timer(
+                        auto_switch_off_time,
+                        pre_warning,
+                        &mut dimm_timer_handle,
+                        &tx,
+                    ));
+                } else {
+                    dimm_timer_handle.take();
+                }
+            }
             DimmerEvent::ButtonState(ButtonState::LongPressStart(button)) => {
                 is_long_press = true;
                 last_button = Some(button);
@@ -327,7 +835,7 @@ async fn dual_input_dimmer_task(
                 dimm_timer_handle.replace(
                     tokio::spawn(async move {
                         loop {
-                            sleep(Duration::from_millis(10)).await;
+                            sleep(dimm_step_time).await;
                             if let Err(error) = tx.send(DimmerEvent::KeepPressing(button)).await {
                                 error!("Error sending dim events: {error}");
                                 break;
@@ -350,43 +858,90 @@ async fn dual_input_dimmer_task(
             }
             DimmerEvent::AutoSwitchOff => {
                 current_brightness = Saturating(0);
+                warning = false;
                 sender.send(current_brightness).await?;
             }
+            DimmerEvent::PreWarning => {
+                if current_brightness.0 > 0 && !is_long_press {
+                    warning = true;
+                    sender
+                        .send(warning_brightness(pre_warning, current_brightness))
+                        .await?;
+                }
+            }
+            DimmerEvent::FlashEnd => {
+                if warning && is_flashing(pre_warning) {
+                    warning = false;
+                    sender.send(current_brightness).await?;
+                }
+            }
             DimmerEvent::PresenceDetected => {
                 if current_brightness.0 > 0 {
-                    start_switchoff_timer(auto_switch_off_time, &mut dimm_timer_handle, &tx);
+                    if warning {
+                        warning = false;
+                        sender.send(current_brightness).await?;
+                    }
+                    reporter.switch_off_at = Some(start_switchoff_timer(
+                        auto_switch_off_time,
+                        pre_warning,
+                        &mut dimm_timer_handle,
+                        &tx,
+                    ));
                 }
             }
             DimmerEvent::SetBrightness(_) => {}
+            DimmerEvent::Time(time) => {
+                (auto_switch_off_time, _) =
+                    scheduled_values(&windows, time, default_switch_off_time);
+            }
+            DimmerEvent::Illuminance(_) | DimmerEvent::HouseMode(_) => {}
+            DimmerEvent::OutputBrightness(_) | DimmerEvent::OutputSwitch(_) => {}
         }
+        reporter.publish(current_brightness.0 > 0, is_long_press, warning);
     }
     Ok(())
 }
 
 async fn dual_input_switch_task(
     auto_switch_off_time: Duration,
+    pre_warning: Option<PreWarning>,
     mut current_state: bool,
     sender: Sender<bool>,
     input_stream: impl Stream<Item = DimmerEvent<DualButtonLayout>> + Sized + Unpin,
+    mut reporter: LightStateReporter,
 ) -> Result<(), SendError<bool>> {
     let (tx, rx) = mpsc::channel(2);
     let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
 
     let mut switch_timer_handle = None::<JoinHandleTerminator<()>>;
+    let mut warning = false;
     if current_state {
-        start_switchoff_timer(auto_switch_off_time, &mut switch_timer_handle, &tx);
+        reporter.switch_off_at = Some(start_switchoff_timer(
+            auto_switch_off_time,
+            pre_warning,
+            &mut switch_timer_handle,
+            &tx,
+        ));
     }
 
     while let Some(event) = input_stream.next().await {
+        reporter.record(&event);
         match event {
+            // the further clicks of a double click repeat the first one
             DimmerEvent::ButtonState(ButtonState::ShortPressStart(button)) => {
                 current_state = match button {
                     DualButtonLayout::Up => true,
                     DualButtonLayout::Down => false,
                 };
+                warning = false;
                 sender.send(current_state).await?;
                 if current_state {
-                    start_switchoff_timer(auto_switch_off_time, &mut switch_timer_handle, &tx);
+                    reporter.switch_off_at = Some(start_switchoff_timer(
+                        auto_switch_off_time,
+                        pre_warning,
+                        &mut switch_timer_handle,
+                        &tx,
+                    ));
                 } else {
                     switch_timer_handle.take();
                 }
@@ -395,17 +950,309 @@ async fn dual_input_switch_task(
             DimmerEvent::KeepPressing(_) => {}
             DimmerEvent::AutoSwitchOff => {
                 current_state = false;
+                warning = false;
                 sender.send(false).await?;
             }
+            DimmerEvent::PreWarning => {
+                if current_state {
+                    warning = true;
+                    sender.send(false).await?;
+                }
+            }
+            DimmerEvent::FlashEnd => {
+                if warning {
+                    warning = false;
+                    sender.send(true).await?;
+                }
+            }
             DimmerEvent::PresenceDetected => {
                 if current_state {
-                    start_switchoff_timer(auto_switch_off_time, &mut switch_timer_handle, &tx);
+                    if warning {
+                        warning = false;
+                        sender.send(true).await?;
+                    }
+                    reporter.switch_off_at = Some(start_switchoff_timer(
+                        auto_switch_off_time,
+                        pre_warning,
+                        &mut switch_timer_handle,
+                        &tx,
+                    ));
                 } else {
                     switch_timer_handle.take();
                 }
             }
-            DimmerEvent::SetBrightness(_) => {}
+            DimmerEvent::SetBrightness(_)
+            | DimmerEvent::Time(_)
+            | DimmerEvent::Illuminance(_)
+            | DimmerEvent::HouseMode(_) => {}
+            DimmerEvent::OutputBrightness(_) | DimmerEvent::OutputSwitch(_) => {}
+        }
+        reporter.publish(current_state, false, warning);
+    }
+    Ok(())
+}
+
+async fn single_input_switch_task(
+    auto_switch_off_time: Duration,
+    pre_warning: Option<PreWarning>,
+    sender: Sender<bool>,
+    input_stream: impl Stream<Item = DimmerEvent<SingleButtonLayout>> + Sized + Unpin,
+    mut reporter: LightStateReporter,
+) -> Result<(), SendError<bool>> {
+    let (tx, rx) = mpsc::channel(2);
+    let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
+
+    let mut current_state = false;
+    let mut warning = false;
+    let mut switch_timer_handle = None::<JoinHandleTerminator<()>>;
+    while let Some(event) = input_stream.next().await {
+        reporter.record(&event);
+        let mut restart_timer = false;
+        match event {
+            // the further clicks of a double click would toggle the light back
+            DimmerEvent::ButtonState(ButtonState::ShortPressStart(_)) => {
+                // a press while warning keeps the light on instead of toggling
+                if !warning {
+                    current_state = !current_state;
+                }
+                warning = false;
+                restart_timer = true;
+                sender.send(current_state).await?;
+            }
+            DimmerEvent::ButtonState(_) => {}
+            DimmerEvent::KeepPressing(_) => {}
+            DimmerEvent::AutoSwitchOff => {
+                current_state = false;
+                warning = false;
+                sender.send(false).await?;
+            }
+            DimmerEvent::PreWarning => {
+                if current_state {
+                    warning = true;
+                    sender.send(false).await?;
+                }
+            }
+            DimmerEvent::FlashEnd => {
+                if warning {
+                    warning = false;
+                    sender.send(true).await?;
+                }
+            }
+            DimmerEvent::PresenceDetected => {
+                if warning {
+                    warning = false;
+                    sender.send(true).await?;
+                }
+                restart_timer = true;
+            }
+            DimmerEvent::SetBrightness(_)
+            | DimmerEvent::Time(_)
+            | DimmerEvent::Illuminance(_)
+            | DimmerEvent::HouseMode(_) => {}
+            DimmerEvent::OutputBrightness(_) => {}
+            // the flash is not a real switch off
+            DimmerEvent::OutputSwitch(_) if warning => {}
+            DimmerEvent::OutputSwitch(state) => {
+                restart_timer = state && !current_state;
+                current_state = state;
+            }
+        }
+        if !current_state {
+            switch_timer_handle.take();
+        } else if restart_timer || switch_timer_handle.is_none() {
+            reporter.switch_off_at = Some(start_switchoff_timer(
+                auto_switch_off_time,
+                pre_warning,
+                &mut switch_timer_handle,
+                &tx,
+            ));
         }
+        reporter.publish(current_state, false, warning);
     }
     Ok(())
 }
+
+async fn single_input_dimmer_task(
+    input_stream: impl Stream<Item = DimmerEvent<SingleButtonLayout>> + Unpin,
+    auto_switch_off_time: Duration,
+    pre_warning: Option<PreWarning>,
+    dimm_duration: Duration,
+    sender: Sender<Saturating<u8>>,
+    mut reporter: LightStateReporter,
+) -> Result<(), SendError<Saturating<u8>>> {
+    let (tx, rx) = mpsc::channel(2);
+    let dimm_step_time = (dimm_duration / u8::MAX as u32).max(Duration::from_millis(1));
+    let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
+
+    let mut current_brightness = Saturating(0);
+    let mut last_on_brightness = Saturating(255);
+    let mut is_long_press = false;
+    let mut is_pressed = false;
+    let mut dimm_up = false;
+    let mut warning = false;
+    let mut dimm_timer_handle = None::<JoinHandleTerminator<()>>;
+    let mut switch_timer_handle = None::<JoinHandleTerminator<()>>;
+    while let Some(event) = input_stream.next().await {
+        reporter.record(&event);
+        let mut restart_timer = false;
+        match event {
+            DimmerEvent::ButtonState(ButtonState::ShortPressStart(_)) => {
+                is_long_press = false;
+                // a press while warning keeps the light on instead of toggling
+                is_pressed = !warning;
+                if warning {
+                    warning = false;
+                    restart_timer = true;
+                    sender.send(current_brightness).await?;
+                }
+            }
+            DimmerEvent::ButtonState(
+                ButtonState::DoubleClickStart(_) | ButtonState::TripleClickStart(_),
+            ) => {
+                is_long_press = false;
+                is_pressed = false;
+                warning = false;
+                current_brightness = Saturating(255);
+                restart_timer = true;
+                sender.send(current_brightness).await?;
+            }
+            DimmerEvent::ButtonState(ButtonState::LongPressStart(button)) => {
+                is_long_press = true;
+                warning = false;
+                dimm_up = if current_brightness.0 == 0 {
+                    true
+                } else if current_brightness.0 == u8::MAX {
+                    false
+                } else {
+                    !dimm_up
+                };
+                let tx = tx.clone();
+                dimm_timer_handle.replace(
+                    tokio::spawn(async move {
+                        loop {
+                            sleep(dimm_step_time).await;
+                            if let Err(error) = tx.send(DimmerEvent::KeepPressing(button)).await {
+                                error!("Error sending dim events: {error}");
+                                break;
+                            }
+                        }
+                    })
+                    .into(),
+                );
+            }
+            DimmerEvent::ButtonState(ButtonState::Released) => {
+                dimm_timer_handle.take();
+                if is_long_press {
+                    if current_brightness.0 > 0 {
+                        last_on_brightness = current_brightness;
+                    }
+                } else if is_pressed {
+                    current_brightness = if current_brightness.0 > 0 {
+                        Saturating(0)
+                    } else {
+                        last_on_brightness
+                    };
+                    sender.send(current_brightness).await?;
+                }
+                is_long_press = false;
+                is_pressed = false;
+                restart_timer = true;
+            }
+            DimmerEvent::KeepPressing(_) => {
+                if dimm_up {
+                    current_brightness += 1;
+                } else {
+                    current_brightness -= 1;
+                }
+                sender.send(current_brightness).await?;
+            }
+            DimmerEvent::AutoSwitchOff => {
+                current_brightness = Saturating(0);
+                warning = false;
+                sender.send(current_brightness).await?;
+            }
+            DimmerEvent::PreWarning => {
+                if current_brightness.0 > 0 && !is_long_press && !is_pressed {
+                    warning = true;
+                    sender
+                        .send(warning_brightness(pre_warning, current_brightness))
+                        .await?;
+                }
+            }
+            DimmerEvent::FlashEnd => {
+                if warning && is_flashing(pre_warning) {
+                    warning = false;
+                    sender.send(current_brightness).await?;
+                }
+            }
+            DimmerEvent::PresenceDetected => {
+                if warning {
+                    warning = false;
+                    sender.send(current_brightness).await?;
+                }
+                restart_timer = true;
+            }
+            DimmerEvent::SetBrightness(_)
+            | DimmerEvent::Time(_)
+            | DimmerEvent::Illuminance(_)
+            | DimmerEvent::HouseMode(_) => {}
+            // echoes of the own dimm steps may arrive late while dimming, the warning level is no
+            // new brightness
+            DimmerEvent::OutputBrightness(brightness) if !is_long_press && !warning => {
+                restart_timer = brightness.0 > 0 && current_brightness.0 == 0;
+                current_brightness = brightness;
+                if brightness.0 > 0 {
+                    last_on_brightness = brightness;
+                }
+            }
+            DimmerEvent::OutputBrightness(_) => {}
+            DimmerEvent::OutputSwitch(_) => {}
+        }
+        if current_brightness.0 == 0 {
+            switch_timer_handle.take();
+        } else if restart_timer || switch_timer_handle.is_none() {
+            reporter.switch_off_at = Some(start_switchoff_timer(
+                auto_switch_off_time,
+                pre_warning,
+                &mut switch_timer_handle,
+                &tx,
+            ));
+        }
+        reporter.publish(current_brightness.0 > 0, is_long_press, warning);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{num::Saturating, time::Duration};
+
+    use chrono::NaiveTime;
+
+    use crate::{controller::light::scheduled_values, data::wiring::TimeWindow};
+
+    #[test]
+    fn test_scheduled_values() {
+        let windows = [
+            TimeWindow {
+                start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+                brightness: Some(25),
+                switch_off_time: Duration::from_secs(60),
+            },
+            TimeWindow {
+                start: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                brightness: None,
+                switch_off_time: Duration::from_secs(600),
+            },
+        ];
+        let default = Duration::from_secs(3600);
+        let at =
+            |h, m| scheduled_values(&windows, NaiveTime::from_hms_opt(h, m, 0).unwrap(), default);
+        assert_eq!((Duration::from_secs(60), Some(Saturating(25))), at(23, 30));
+        assert_eq!((Duration::from_secs(60), Some(Saturating(25))), at(2, 0));
+        assert_eq!((Duration::from_secs(600), None), at(6, 0));
+        assert_eq!((default, None), at(12, 0));
+    }
+}
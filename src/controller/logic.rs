@@ -0,0 +1,479 @@
+use std::{num::Saturating, pin::Pin, time::Duration};
+
+use futures::stream::SelectAll;
+use log::error;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    sync::mpsc::{self, error::SendError, Sender},
+    time::sleep,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use crate::{
+    controller::manager::{ControllerHandle, ControllerState, StateSender},
+    data::{
+        registry::{ButtonState, EventRegistry},
+        wiring::{BoolOutput, BoolSignal, GateOperation, LogicBlock, NumberSignal},
+    },
+    terminator::JoinHandleTerminator,
+};
+
+type BoolStream = Pin<Box<dyn Stream<Item = bool> + Send>>;
+type NumberStream = Pin<Box<dyn Stream<Item = f32> + Send>>;
+
+pub async fn logic_block(
+    event_registry: &EventRegistry,
+    block: &LogicBlock,
+    state: StateSender,
+) -> ControllerHandle {
+    match block {
+        LogicBlock::Gate {
+            operation,
+            inputs,
+            output,
+        } => {
+            let mut input_stream = SelectAll::new();
+            for (idx, input) in inputs.iter().enumerate() {
+                input_stream.push(
+                    bool_stream(event_registry, *input)
+                        .await
+                        .map(move |value| (idx, value)),
+                );
+            }
+            let sender = BoolSender::new(event_registry, *output, state).await;
+            let operation = *operation;
+            let count = inputs.len();
+            spawn_block(gate_task(input_stream, count, operation, sender))
+        }
+        LogicBlock::Delay {
+            input,
+            output,
+            on_delay,
+            off_delay,
+        } => {
+            let input_stream = bool_stream(event_registry, *input).await;
+            let sender = BoolSender::new(event_registry, *output, state).await;
+            spawn_block(delay_task(input_stream, sender, *on_delay, *off_delay))
+        }
+        LogicBlock::Toggle { input, output } => {
+            let input_stream = bool_stream(event_registry, *input).await;
+            let current_value = bool_stream(event_registry, (*output).into())
+                .await
+                .next()
+                .await
+                .unwrap_or_default();
+            let sender = BoolSender::new(event_registry, *output, state).await;
+            spawn_block(toggle_task(input_stream, sender, current_value))
+        }
+        LogicBlock::SetReset { set, reset, output } => {
+            let input_stream = bool_stream(event_registry, *set)
+                .await
+                .map(SetResetEvent::Set)
+                .merge(
+                    bool_stream(event_registry, *reset)
+                        .await
+                        .map(SetResetEvent::Reset),
+                );
+            let sender = BoolSender::new(event_registry, *output, state).await;
+            spawn_block(set_reset_task(input_stream, sender))
+        }
+        LogicBlock::Threshold {
+            input,
+            output,
+            threshold,
+            hysteresis,
+            invert,
+        } => {
+            let input_stream = number_stream(event_registry, *input).await;
+            let sender = BoolSender::new(event_registry, *output, state).await;
+            let (threshold, hysteresis, invert) = (threshold.0, hysteresis.0, *invert);
+            spawn_block(async move {
+                let mut input_stream = input_stream;
+                let mut state = false;
+                while let Some(value) = input_stream.next().await {
+                    state = threshold_state(value, threshold, hysteresis, state);
+                    sender.send(state != invert).await?;
+                }
+                Ok(())
+            })
+        }
+        LogicBlock::Map {
+            input,
+            output,
+            input_min,
+            input_max,
+            output_min,
+            output_max,
+        } => {
+            let input_stream = number_stream(event_registry, *input).await;
+            let sender = NumberSender::new(event_registry, *output).await;
+            let input_range = (input_min.0, input_max.0);
+            let output_range = (output_min.0, output_max.0);
+            spawn_block(async move {
+                let mut input_stream = input_stream;
+                while let Some(value) = input_stream.next().await {
+                    let value = map_value(value, input_range, output_range);
+                    sender.send(value).await?;
+                    publish_output(&state, LogicValue::Number(value));
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+fn spawn_block(
+    task: impl std::future::Future<Output = Result<(), LogicError>> + Send + 'static,
+) -> ControllerHandle {
+    tokio::spawn(async move {
+        if let Err(error) = task.await {
+            error!("Failed logic block: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+#[derive(Error, Debug)]
+enum LogicError {
+    #[error("Cannot send switch: {0}")]
+    Switch(#[from] SendError<bool>),
+    #[error("Cannot send temperature: {0}")]
+    Temperature(#[from] SendError<f32>),
+    #[error("Cannot send brightness: {0}")]
+    Brightness(#[from] SendError<Saturating<u8>>),
+    #[error("Cannot send whitebalance: {0}")]
+    LightColor(#[from] SendError<Saturating<u16>>),
+    #[error("Cannot send illuminance: {0}")]
+    Illuminance(#[from] SendError<u32>),
+    #[error("Cannot send position: {0}")]
+    Position(#[from] SendError<u8>),
+}
+
+/// Internal state of a logic block, published on every output
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct LogicState {
+    pub output: Option<LogicValue>,
+    pub updates: u64,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(untagged)]
+pub enum LogicValue {
+    Bool(bool),
+    Number(f32),
+}
+
+fn publish_output(state: &StateSender, value: LogicValue) {
+    state.send_modify(|state| {
+        let updates = match state {
+            ControllerState::Logic(logic) => logic.updates + 1,
+            _ => 1,
+        };
+        *state = ControllerState::Logic(LogicState {
+            output: Some(value),
+            updates,
+        });
+    });
+}
+
+impl From<BoolOutput> for BoolSignal {
+    fn from(value: BoolOutput) -> Self {
+        match value {
+            BoolOutput::Switch { key } => BoolSignal::Switch { key },
+            BoolOutput::Logic { key } => BoolSignal::Logic { key },
+        }
+    }
+}
+
+async fn bool_stream(event_registry: &EventRegistry, signal: BoolSignal) -> BoolStream {
+    match signal {
+        BoolSignal::Switch { key } => Box::pin(event_registry.switch_stream(key).await),
+        BoolSignal::WindowOpen { key } => Box::pin(event_registry.window_open_stream(key).await),
+        BoolSignal::Logic { key } => Box::pin(event_registry.logic_stream(key).await),
+        BoolSignal::Button { key } => Box::pin(
+            event_registry
+                .single_button_stream(key)
+                .await
+                .map(|state| state != ButtonState::Released),
+        ),
+        BoolSignal::Brightness { key } => Box::pin(
+            event_registry
+                .brightness_stream(key)
+                .await
+                .map(|brightness| brightness.0 > 0),
+        ),
+    }
+}
+
+/// Output of a bool block, every sent value is published as state
+struct BoolSender {
+    sender: Sender<bool>,
+    state: StateSender,
+}
+
+impl BoolSender {
+    async fn new(event_registry: &EventRegistry, output: BoolOutput, state: StateSender) -> Self {
+        let sender = match output {
+            BoolOutput::Switch { key } => event_registry.switch_sender(key).await,
+            BoolOutput::Logic { key } => event_registry.logic_sender(key).await,
+        };
+        Self { sender, state }
+    }
+    async fn send(&self, value: bool) -> Result<(), SendError<bool>> {
+        self.sender.send(value).await?;
+        publish_output(&self.state, LogicValue::Bool(value));
+        Ok(())
+    }
+}
+
+async fn number_stream(event_registry: &EventRegistry, signal: NumberSignal) -> NumberStream {
+    match signal {
+        NumberSignal::Temperature { key } => Box::pin(event_registry.temperature_stream(key).await),
+        NumberSignal::Brightness { key } => Box::pin(
+            event_registry
+                .brightness_stream(key)
+                .await
+                .map(|v| v.0 as f32),
+        ),
+        NumberSignal::LightColor { key } => Box::pin(
+            event_registry
+                .light_color_stream(key)
+                .await
+                .map(|v| v.0 as f32),
+        ),
+        NumberSignal::Illuminance { key } => Box::pin(
+            event_registry
+                .illuminance_stream(key)
+                .await
+                .map(|v| v as f32),
+        ),
+        NumberSignal::ShutterPosition { key } => Box::pin(
+            event_registry
+                .shutter_position_stream(key)
+                .await
+                .map(|v| v as f32),
+        ),
+    }
+}
+
+enum NumberSender {
+    Temperature(Sender<f32>),
+    Brightness(Sender<Saturating<u8>>),
+    LightColor(Sender<Saturating<u16>>),
+    Illuminance(Sender<u32>),
+    ShutterPosition(Sender<u8>),
+}
+
+impl NumberSender {
+    async fn new(event_registry: &EventRegistry, signal: NumberSignal) -> Self {
+        match signal {
+            NumberSignal::Temperature { key } => {
+                NumberSender::Temperature(event_registry.temperature_sender(key).await)
+            }
+            NumberSignal::Brightness { key } => {
+                NumberSender::Brightness(event_registry.brightness_sender(key).await)
+            }
+            NumberSignal::LightColor { key } => {
+                NumberSender::LightColor(event_registry.light_color_sender(key).await)
+            }
+            NumberSignal::Illuminance { key } => {
+                NumberSender::Illuminance(event_registry.illuminance_sender(key).await)
+            }
+            NumberSignal::ShutterPosition { key } => {
+                NumberSender::ShutterPosition(event_registry.shutter_position_sender(key).await)
+            }
+        }
+    }
+    /// rounds and clamps the value to the range of the register
+    async fn send(&self, value: f32) -> Result<(), LogicError> {
+        match self {
+            NumberSender::Temperature(sender) => sender.send(value).await?,
+            NumberSender::Brightness(sender) => {
+                sender
+                    .send(Saturating(value.round().clamp(0.0, 255.0) as u8))
+                    .await?
+            }
+            NumberSender::LightColor(sender) => {
+                sender
+                    .send(Saturating(value.round().clamp(0.0, u16::MAX as f32) as u16))
+                    .await?
+            }
+            NumberSender::Illuminance(sender) => {
+                sender
+                    .send(value.round().clamp(0.0, u32::MAX as f32) as u32)
+                    .await?
+            }
+            NumberSender::ShutterPosition(sender) => {
+                sender.send(value.round().clamp(0.0, 100.0) as u8).await?
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn gate_task(
+    mut input_stream: impl Stream<Item = (usize, bool)> + Unpin,
+    count: usize,
+    operation: GateOperation,
+    sender: BoolSender,
+) -> Result<(), LogicError> {
+    let mut values = vec![false; count];
+    while let Some((idx, value)) = input_stream.next().await {
+        values[idx] = value;
+        sender.send(evaluate_gate(operation, &values)).await?;
+    }
+    Ok(())
+}
+
+enum DelayEvent {
+    Input(bool),
+    Elapsed(bool),
+}
+
+async fn delay_task(
+    input_stream: impl Stream<Item = bool> + Unpin,
+    sender: BoolSender,
+    on_delay: Duration,
+    off_delay: Duration,
+) -> Result<(), LogicError> {
+    let (tx, rx) = mpsc::channel(2);
+    let mut input_stream = input_stream
+        .map(DelayEvent::Input)
+        .merge(ReceiverStream::new(rx));
+    let mut timer = None::<JoinHandleTerminator<()>>;
+    let mut last_input = None;
+    while let Some(event) = input_stream.next().await {
+        match event {
+            DelayEvent::Input(value) => {
+                if last_input == Some(value) {
+                    continue;
+                }
+                last_input = Some(value);
+                let delay = if value { on_delay } else { off_delay };
+                if delay.is_zero() {
+                    timer.take();
+                    sender.send(value).await?;
+                } else {
+                    let tx = tx.clone();
+                    timer.replace(
+                        tokio::spawn(async move {
+                            sleep(delay).await;
+                            if let Err(error) = tx.send(DelayEvent::Elapsed(value)).await {
+                                error!("Cannot send delay: {error}");
+                            }
+                        })
+                        .into(),
+                    );
+                }
+            }
+            DelayEvent::Elapsed(value) => sender.send(value).await?,
+        }
+    }
+    Ok(())
+}
+
+async fn toggle_task(
+    mut input_stream: impl Stream<Item = bool> + Unpin,
+    sender: BoolSender,
+    mut current_value: bool,
+) -> Result<(), LogicError> {
+    // the first value only sets the reference, it is no edge
+    let mut last_input = None;
+    while let Some(value) = input_stream.next().await {
+        if last_input == Some(false) && value {
+            current_value = !current_value;
+            sender.send(current_value).await?;
+        }
+        last_input = Some(value);
+    }
+    Ok(())
+}
+
+enum SetResetEvent {
+    Set(bool),
+    Reset(bool),
+}
+
+async fn set_reset_task(
+    mut input_stream: impl Stream<Item = SetResetEvent> + Unpin,
+    sender: BoolSender,
+) -> Result<(), LogicError> {
+    let mut set = None;
+    let mut reset = None;
+    while let Some(event) = input_stream.next().await {
+        match event {
+            SetResetEvent::Set(value) => {
+                if set == Some(false) && value && reset != Some(true) {
+                    sender.send(true).await?;
+                }
+                set = Some(value);
+            }
+            SetResetEvent::Reset(value) => {
+                if reset == Some(false) && value {
+                    sender.send(false).await?;
+                }
+                reset = Some(value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn evaluate_gate(operation: GateOperation, values: &[bool]) -> bool {
+    match operation {
+        GateOperation::And => values.iter().all(|v| *v),
+        GateOperation::Or => values.iter().any(|v| *v),
+        GateOperation::Xor => values.iter().filter(|v| **v).count() % 2 == 1,
+        GateOperation::Not => !values.iter().any(|v| *v),
+    }
+}
+
+fn threshold_state(value: f32, threshold: f32, hysteresis: f32, current_state: bool) -> bool {
+    if value >= threshold {
+        true
+    } else if value < threshold - hysteresis {
+        false
+    } else {
+        current_state
+    }
+}
+
+fn map_value(value: f32, (in_min, in_max): (f32, f32), (out_min, out_max): (f32, f32)) -> f32 {
+    if in_max == in_min {
+        return out_min;
+    }
+    let ratio = ((value - in_min) / (in_max - in_min)).clamp(0.0, 1.0);
+    out_min + ratio * (out_max - out_min)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        controller::logic::{evaluate_gate, map_value, threshold_state},
+        data::wiring::GateOperation,
+    };
+
+    #[test]
+    fn test_evaluate_gate() {
+        assert!(evaluate_gate(GateOperation::And, &[true, true]));
+        assert!(!evaluate_gate(GateOperation::And, &[true, false]));
+        assert!(evaluate_gate(GateOperation::Or, &[false, true]));
+        assert!(!evaluate_gate(GateOperation::Xor, &[true, true]));
+        assert!(evaluate_gate(GateOperation::Not, &[false]));
+        assert!(!evaluate_gate(GateOperation::Not, &[true]));
+    }
+
+    #[test]
+    fn test_threshold_and_map() {
+        assert!(threshold_state(22.0, 22.0, 1.0, false));
+        // inside the hysteresis the state is kept
+        assert!(threshold_state(21.5, 22.0, 1.0, true));
+        assert!(!threshold_state(21.5, 22.0, 1.0, false));
+        assert!(!threshold_state(20.9, 22.0, 1.0, true));
+        assert_eq!(127.5, map_value(50.0, (0.0, 100.0), (0.0, 255.0)));
+        assert_eq!(255.0, map_value(150.0, (0.0, 100.0), (0.0, 255.0)));
+        assert_eq!(370.0, map_value(0.0, (0.0, 100.0), (370.0, 153.0)));
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::FutureExt;
+use log::{error, info, warn};
+use serde::Serialize;
+use strum_macros::IntoStaticStr;
+use thiserror::Error;
+use tokio::{
+    sync::{watch, Mutex},
+    task::JoinHandle,
+    time::{interval, Instant, MissedTickBehavior},
+};
+
+use crate::{
+    controller::{
+        action::{all_off_controller, house_mode_button, ring_controller, RingState},
+        constant_light::constant_light,
+        heat::{heat_controller, HeatProtection, HeatState},
+        light::{
+            dual_input_dimmer, dual_input_switch, motion_detector, single_input_dimmer,
+            single_input_switch, LightState,
+        },
+        logic::{logic_block, LogicState},
+        presence_simulation::presence_simulation,
+        scene::{scene_button, scene_controller, SceneState},
+        script::{script_controller, ScriptState},
+        shutter::{shutter, ShutterState},
+        ventilation::ventilation_fan,
+        wake_up::wake_up_light,
+        whitebalance::circadian_whitebalance,
+    },
+    data::{
+        registry::EventRegistry,
+        wiring::{
+            AllOffController, CircadianWhitebalance, ConstantLight, Controllers, DualInputDimmer,
+            DualInputSwitch, HeatController, HouseModeButton, LogicBlock, MotionDetector,
+            PresenceSimulation, RingController, Scene, SceneButton, Script, Shutter,
+            SingleInputDimmer, SingleInputSwitch, VentilationFan, WakeUpLight,
+        },
+    },
+    supervisor::RestartBackoff,
+    terminator::JoinHandleTerminator,
+};
+
+pub type StateSender = watch::Sender<ControllerState>;
+/// Exit of a controller task, the controller logs its error before returning it
+pub type ControllerResult = Result<(), Box<dyn Error + Send + Sync>>;
+pub type ControllerHandle = JoinHandle<ControllerResult>;
+
+/// Internal state a controller publishes for introspection
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(untagged)]
+pub enum ControllerState {
+    #[default]
+    Unknown,
+    Light(LightState),
+    Heat(HeatState),
+    Ring(RingState),
+    Shutter(ShutterState),
+    Scene(SceneState),
+    Script(ScriptState),
+    Logic(LogicState),
+}
+
+/// Config of a single controller, the unit that can be started and stopped
+#[derive(Serialize, Clone, Debug, PartialEq, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum ControllerConfig {
+    DualInputDimmer(DualInputDimmer),
+    DualInputSwitch(DualInputSwitch),
+    MotionDetector(MotionDetector),
+    HeatController(HeatController),
+    RingController(RingController),
+    SingleInputDimmer(SingleInputDimmer),
+    SingleInputSwitch(SingleInputSwitch),
+    CircadianWhitebalance(CircadianWhitebalance),
+    ConstantLight(ConstantLight),
+    AllOffController(AllOffController),
+    Scenes(Box<[Scene]>),
+    SceneButton(SceneButton),
+    HouseModeButton(HouseModeButton),
+    PresenceSimulation(PresenceSimulation),
+    Shutter(Shutter),
+    LogicBlock(LogicBlock),
+    Script(Script),
+    WakeUpLight(WakeUpLight),
+    VentilationFan(VentilationFan),
+}
+
+impl ControllerConfig {
+    pub fn from_controllers(controllers: Controllers) -> Vec<ControllerConfig> {
+        let Controllers {
+            dual_input_dimmers,
+            dual_input_switches,
+            motion_detectors,
+            heat_controllers,
+            ring_controllers,
+            circadian_whitebalances,
+            single_input_dimmers,
+            single_input_switches,
+            constant_lights,
+            all_off_controllers,
+            scenes,
+            scene_buttons,
+            house_mode_buttons,
+            presence_simulations,
+            shutters,
+            logic_blocks,
+            wake_up_lights,
+            ventilation_fans,
+            scripts,
+        } = controllers;
+        let mut configs = Vec::new();
+        configs.extend(
+            dual_input_dimmers
+                .into_vec()
+                .into_iter()
+                .map(Self::DualInputDimmer),
+        );
+        configs.extend(
+            dual_input_switches
+                .into_vec()
+                .into_iter()
+                .map(Self::DualInputSwitch),
+        );
+        configs.extend(
+            motion_detectors
+                .into_vec()
+                .into_iter()
+                .map(Self::MotionDetector),
+        );
+        configs.extend(
+            heat_controllers
+                .into_vec()
+                .into_iter()
+                .map(Self::HeatController),
+        );
+        configs.extend(
+            ring_controllers
+                .into_vec()
+                .into_iter()
+                .map(Self::RingController),
+        );
+        configs.extend(
+            single_input_dimmers
+                .into_vec()
+                .into_iter()
+                .map(Self::SingleInputDimmer),
+        );
+        configs.extend(
+            single_input_switches
+                .into_vec()
+                .into_iter()
+                .map(Self::SingleInputSwitch),
+        );
+        configs.extend(
+            circadian_whitebalances
+                .into_vec()
+                .into_iter()
+                .map(Self::CircadianWhitebalance),
+        );
+        configs.extend(
+            constant_lights
+                .into_vec()
+                .into_iter()
+                .map(Self::ConstantLight),
+        );
+        configs.extend(
+            all_off_controllers
+                .into_vec()
+                .into_iter()
+                .map(Self::AllOffController),
+        );
+        // captured scenes can be recalled without any configured one
+        configs.push(Self::Scenes(scenes));
+        configs.extend(scene_buttons.into_vec().into_iter().map(Self::SceneButton));
+        configs.extend(
+            house_mode_buttons
+                .into_vec()
+                .into_iter()
+                .map(Self::HouseModeButton),
+        );
+        configs.extend(
+            presence_simulations
+                .into_vec()
+                .into_iter()
+                .map(Self::PresenceSimulation),
+        );
+        configs.extend(shutters.into_vec().into_iter().map(Self::Shutter));
+        configs.extend(logic_blocks.into_vec().into_iter().map(Self::LogicBlock));
+        configs.extend(scripts.into_vec().into_iter().map(Self::Script));
+        configs.extend(wake_up_lights.into_vec().into_iter().map(Self::WakeUpLight));
+        configs.extend(
+            ventilation_fans
+                .into_vec()
+                .into_iter()
+                .map(Self::VentilationFan),
+        );
+        configs
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.into()
+    }
+
+    pub(crate) async fn start(
+        &self,
+        event_registry: &EventRegistry,
+        protection: HeatProtection,
+        state: StateSender,
+    ) -> ControllerHandle {
+        match self {
+            Self::DualInputDimmer(cfg) => dual_input_dimmer(event_registry, cfg, state).await,
+            Self::DualInputSwitch(cfg) => {
+                dual_input_switch(
+                    event_registry,
+                    cfg.input.as_ref(),
+                    cfg.output,
+                    cfg.auto_switch_off_time,
+                    cfg.presence.as_ref(),
+                    cfg.pre_warning,
+                    state,
+                )
+                .await
+            }
+            Self::MotionDetector(cfg) => motion_detector(event_registry, cfg, state).await,
+            Self::HeatController(cfg) => {
+                heat_controller(
+                    event_registry,
+                    cfg.current_value_input,
+                    cfg.target_value_input,
+                    cfg.output,
+                    cfg.window_open_detection.as_ref(),
+                    protection,
+                    state,
+                )
+                .await
+            }
+            Self::RingController(cfg) => ring_controller(event_registry, cfg, state).await,
+            Self::SingleInputDimmer(cfg) => single_input_dimmer(event_registry, cfg, state).await,
+            Self::SingleInputSwitch(cfg) => {
+                single_input_switch(
+                    event_registry,
+                    cfg.input.as_ref(),
+                    cfg.output,
+                    cfg.auto_switch_off_time,
+                    cfg.presence.as_ref(),
+                    cfg.pre_warning,
+                    state,
+                )
+                .await
+            }
+            Self::CircadianWhitebalance(cfg) => circadian_whitebalance(event_registry, cfg).await,
+            Self::ConstantLight(cfg) => constant_light(event_registry, cfg).await,
+            Self::AllOffController(cfg) => all_off_controller(event_registry, cfg).await,
+            Self::Scenes(scenes) => scene_controller(event_registry, scenes, state).await,
+            Self::SceneButton(cfg) => scene_button(event_registry, cfg, state).await,
+            Self::HouseModeButton(cfg) => house_mode_button(event_registry, cfg).await,
+            Self::PresenceSimulation(cfg) => presence_simulation(event_registry, cfg).await,
+            Self::Shutter(cfg) => shutter(event_registry, cfg, state).await,
+            Self::LogicBlock(cfg) => logic_block(event_registry, cfg, state).await,
+            Self::Script(script) => script_controller(event_registry, script, state).await,
+            Self::WakeUpLight(cfg) => wake_up_light(event_registry, cfg).await,
+            Self::VentilationFan(cfg) => ventilation_fan(event_registry, cfg).await,
+        }
+    }
+}
+
+/// Identifier of a controller, equal configs result in equal ids over reloads
+fn controller_id(config: &ControllerConfig) -> Box<str> {
+    // FNV-1a, the std hasher is not stable over releases
+    let hash = serde_json::to_vec(config)
+        .unwrap_or_default()
+        .iter()
+        .fold(0x811c9dc5u32, |hash, byte| {
+            (hash ^ *byte as u32).wrapping_mul(0x01000193)
+        });
+    format!("{}-{hash:08x}", config.kind()).into()
+}
+
+#[derive(Error, Debug)]
+pub enum ControllerError {
+    #[error("No controller {0}")]
+    NotFound(Box<str>),
+}
+
+#[derive(Serialize, Debug)]
+pub struct ControllerInfo {
+    pub id: Box<str>,
+    pub kind: &'static str,
+    pub enabled: bool,
+    pub running: bool,
+    /// terminated without an error, stays stopped until it is enabled or its config changes
+    pub stopped: bool,
+    /// failures in a row, reset after running stable for a while
+    pub failures: u32,
+    pub state: ControllerState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ControllerConfig>,
+}
+
+type StartedController = (
+    JoinHandleTerminator<ControllerResult>,
+    watch::Receiver<ControllerState>,
+);
+
+struct RunningController {
+    config: ControllerConfig,
+    handle: Option<JoinHandleTerminator<ControllerResult>>,
+    stopped: bool,
+    state: watch::Receiver<ControllerState>,
+    backoff: RestartBackoff,
+    restart_at: Option<Instant>,
+}
+
+impl RunningController {
+    fn disabled(id: &str, config: ControllerConfig) -> Self {
+        Self {
+            config,
+            handle: None,
+            stopped: false,
+            state: watch::channel(ControllerState::default()).1,
+            backoff: RestartBackoff::new(id),
+            restart_at: None,
+        }
+    }
+
+    fn info(&self, id: &str, with_config: bool) -> ControllerInfo {
+        ControllerInfo {
+            id: id.into(),
+            kind: self.config.kind(),
+            enabled: self.handle.is_some(),
+            running: self.handle.as_ref().is_some_and(|h| !h.is_finished()),
+            stopped: self.stopped,
+            failures: self.backoff.failures(),
+            state: self.state.borrow().clone(),
+            config: with_config.then(|| self.config.clone()),
+        }
+    }
+
+    fn run(&mut self, (handle, state): StartedController) {
+        self.handle = Some(handle);
+        self.stopped = false;
+        self.state = state;
+        self.restart_at = None;
+    }
+}
+
+struct InnerControllerManager {
+    controllers: BTreeMap<Box<str>, RunningController>,
+    /// ids stay disabled over reloads as long as the config of the controller is unchanged
+    disabled: BTreeSet<Box<str>>,
+}
+
+/// Running controllers by id, shared with the management server
+///
+/// Starting a controller waits on the event registry, so it happens without holding the lock.
+#[derive(Clone)]
+pub struct ControllerManager {
+    event_registry: EventRegistry,
+    protection: HeatProtection,
+    inner: Arc<Mutex<InnerControllerManager>>,
+}
+
+impl ControllerManager {
+    pub fn new(event_registry: EventRegistry, protection: HeatProtection) -> Self {
+        Self {
+            event_registry,
+            protection,
+            inner: Arc::new(Mutex::new(InnerControllerManager {
+                controllers: BTreeMap::new(),
+                disabled: BTreeSet::new(),
+            })),
+        }
+    }
+
+    async fn start(&self, id: &str, config: ControllerConfig) -> RunningController {
+        let mut controller = RunningController::disabled(id, config);
+        let started = self.spawn(&controller.config).await;
+        controller.run(started);
+        controller
+    }
+
+    async fn spawn(&self, config: &ControllerConfig) -> StartedController {
+        let (tx, rx) = watch::channel(ControllerState::default());
+        let handle = config
+            .start(&self.event_registry, self.protection, tx)
+            .await;
+        (JoinHandleTerminator::new(handle), rx)
+    }
+
+    /// Stops all running controllers and starts the ones of the new config
+    pub async fn replace(&self, configs: Vec<ControllerConfig>) {
+        let disabled = {
+            let mut inner = self.inner.lock().await;
+            info!("Terminating running controllers");
+            inner.controllers.clear();
+            inner.disabled.clone()
+        };
+        let mut controllers = BTreeMap::new();
+        for config in configs {
+            let base_id = controller_id(&config);
+            let mut id = base_id.clone();
+            let mut count = 1;
+            while controllers.contains_key(&id) {
+                count += 1;
+                id = format!("{base_id}-{count}").into();
+            }
+            let controller = if disabled.contains(&id) {
+                info!("Controller {id} stays disabled");
+                RunningController::disabled(&id, config)
+            } else {
+                self.start(&id, config).await
+            };
+            controllers.insert(id, controller);
+        }
+        let mut inner = self.inner.lock().await;
+        inner.disabled.retain(|id| controllers.contains_key(id));
+        inner.controllers = controllers;
+    }
+
+    pub async fn list(&self) -> Vec<ControllerInfo> {
+        self.inner
+            .lock()
+            .await
+            .controllers
+            .iter()
+            .map(|(id, controller)| controller.info(id, false))
+            .collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<ControllerInfo> {
+        self.inner
+            .lock()
+            .await
+            .controllers
+            .get(id)
+            .map(|controller| controller.info(id, true))
+    }
+
+    /// Stops or restarts a single controller until the next change of its config
+    pub async fn set_enabled(&self, id: &str, enabled: bool) -> Result<(), ControllerError> {
+        let config = {
+            let mut inner = self.inner.lock().await;
+            let Some(controller) = inner.controllers.get(id) else {
+                return Err(ControllerError::NotFound(id.into()));
+            };
+            if enabled == controller.handle.is_some() {
+                return Ok(());
+            }
+            let config = controller.config.clone();
+            if !enabled {
+                info!("Disable controller {id}");
+                inner.disabled.insert(id.into());
+                let controller = RunningController::disabled(id, config);
+                inner.controllers.insert(id.into(), controller);
+                return Ok(());
+            }
+            info!("Enable controller {id}");
+            inner.disabled.remove(id);
+            config
+        };
+        let controller = self.start(id, config).await;
+        let mut inner = self.inner.lock().await;
+        // a reload or another call may have changed the controller while it started
+        if !inner.disabled.contains(id) {
+            if let Some(current) = inner.controllers.get_mut(id) {
+                if current.handle.is_none() {
+                    *current = controller;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts enabled controllers which failed, with a growing delay on repeated failures
+    pub async fn supervise(&self) {
+        let mut interval = interval(Duration::from_secs(1));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let due = {
+                let mut inner = self.inner.lock().await;
+                let now = Instant::now();
+                let mut due = Vec::new();
+                for (id, controller) in inner.controllers.iter_mut() {
+                    let Some(handle) = &mut controller.handle else {
+                        continue;
+                    };
+                    if !handle.is_finished() {
+                        controller.backoff.check_stable();
+                        continue;
+                    }
+                    if controller.stopped {
+                        continue;
+                    }
+                    match controller.restart_at {
+                        // the result is taken once, a restart replaces the handle
+                        None => match exited_cleanly(id, handle).now_or_never() {
+                            Some(true) => controller.stopped = true,
+                            Some(false) => {
+                                let delay = controller.backoff.failed();
+                                warn!("Controller {id} terminated, restarting in {delay:?}");
+                                controller.restart_at = Some(now + delay);
+                            }
+                            // a finished task is only pending when the task budget is used up
+                            None => {}
+                        },
+                        Some(restart_at) if restart_at <= now => {
+                            due.push((id.clone(), controller.config.clone(), restart_at));
+                        }
+                        Some(_) => {}
+                    }
+                }
+                due
+            };
+            for (id, config, restart_at) in due {
+                info!("Restart controller {id}");
+                let started = self.spawn(&config).await;
+                let mut inner = self.inner.lock().await;
+                // controllers replaced or disabled meanwhile keep their state, the new task is
+                // aborted on drop
+                if let Some(controller) = inner
+                    .controllers
+                    .get_mut(&id)
+                    .filter(|controller| controller.restart_at == Some(restart_at))
+                {
+                    controller.run(started);
+                    controller.backoff.restarted();
+                }
+            }
+        }
+    }
+}
+
+/// Takes the result of a finished controller task, errors and panics are failures
+async fn exited_cleanly(id: &str, handle: &mut JoinHandleTerminator<ControllerResult>) -> bool {
+    match handle.join().await {
+        Ok(Ok(())) => {
+            info!("Controller {id} finished");
+            true
+        }
+        Ok(Err(_)) => false,
+        Err(error) => {
+            error!("Controller {id} panicked: {error}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        controller::manager::{controller_id, exited_cleanly, ControllerConfig},
+        data::{
+            registry::{LightGroupKey, SingleButtonKey},
+            wiring::{AllOffController, LightGroup},
+        },
+        terminator::JoinHandleTerminator,
+    };
+
+    #[test]
+    fn test_controller_id() {
+        let all_off = |group| {
+            ControllerConfig::AllOffController(AllOffController {
+                input: SingleButtonKey::Button(Default::default()),
+                group: LightGroup {
+                    key: group,
+                    brightness: Box::new([]),
+                    switches: Box::new([]),
+                },
+                exceptions: Box::new([]),
+            })
+        };
+        let id = controller_id(&all_off(LightGroupKey::House));
+        assert!(id.starts_with("all_off_controller-"));
+        assert_eq!(id, controller_id(&all_off(LightGroupKey::House)));
+        assert_ne!(id, controller_id(&all_off(LightGroupKey::Floor(1))));
+    }
+
+    #[tokio::test]
+    async fn test_exited_cleanly() {
+        let mut finished = JoinHandleTerminator::new(tokio::spawn(async { Ok(()) }));
+        assert!(exited_cleanly("finished", &mut finished).await);
+        let mut failed = JoinHandleTerminator::new(tokio::spawn(async { Err("failed".into()) }));
+        assert!(!exited_cleanly("failed", &mut failed).await);
+        let mut panicked = JoinHandleTerminator::new(tokio::spawn(async { panic!("panicked") }));
+        assert!(!exited_cleanly("panicked", &mut panicked).await);
+    }
+}
@@ -1,3 +1,15 @@
 pub mod action;
+pub mod constant_light;
 pub mod heat;
 pub mod light;
+pub mod logic;
+pub mod manager;
+pub mod presence_simulation;
+pub mod scene;
+pub mod script;
+pub mod shutter;
+#[cfg(test)]
+mod simulation;
+pub mod ventilation;
+pub mod wake_up;
+pub mod whitebalance;
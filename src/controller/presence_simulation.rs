@@ -0,0 +1,262 @@
+use std::num::Saturating;
+
+use chrono::NaiveTime;
+use log::{error, info};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use thiserror::Error;
+use tokio::{
+    sync::mpsc::{error::SendError, Sender},
+    time::Instant,
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    controller::manager::ControllerHandle,
+    data::{
+        registry::{EventRegistry, HouseMode},
+        wiring::PresenceSimulation,
+    },
+    util::{random_duration, time_in_range},
+};
+
+pub async fn presence_simulation(
+    event_registry: &EventRegistry,
+    settings: &PresenceSimulation,
+) -> ControllerHandle {
+    let input_stream = event_registry
+        .clock(settings.clock)
+        .await
+        .map(|now| SimulationMessage::Time(now.time()))
+        .merge(
+            event_registry
+                .house_mode_stream()
+                .await
+                .map(SimulationMessage::HouseMode),
+        );
+    let mut lights = Vec::new();
+    for key in settings.switches.iter() {
+        lights.push(SimulatedLight::new(SimulatedOutput::Switch(
+            event_registry.switch_sender(*key).await,
+        )));
+    }
+    for key in settings.dimmers.iter() {
+        lights.push(SimulatedLight::new(SimulatedOutput::Dimmer(
+            event_registry.brightness_sender(*key).await,
+        )));
+    }
+    let settings = settings.clone();
+    let rng = SmallRng::from_os_rng();
+    tokio::spawn(async move {
+        if let Err(error) = presence_simulation_task(input_stream, lights, &settings, rng).await {
+            error!("Failed presence simulation: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+#[derive(Error, Debug)]
+enum SimulationError {
+    #[error("Cannot send switch: {0}")]
+    Switch(#[from] SendError<bool>),
+    #[error("Cannot send brightness: {0}")]
+    Brightness(#[from] SendError<Saturating<u8>>),
+}
+
+enum SimulatedOutput {
+    Switch(Sender<bool>),
+    Dimmer(Sender<Saturating<u8>>),
+}
+
+struct SimulatedLight {
+    output: SimulatedOutput,
+    on: bool,
+    next_change: Instant,
+}
+
+impl SimulatedLight {
+    fn new(output: SimulatedOutput) -> Self {
+        Self {
+            output,
+            on: false,
+            next_change: Instant::now(),
+        }
+    }
+    async fn set(&mut self, on: bool, brightness: u8) -> Result<(), SimulationError> {
+        self.on = on;
+        match &self.output {
+            SimulatedOutput::Switch(sender) => sender.send(on).await?,
+            SimulatedOutput::Dimmer(sender) => {
+                sender
+                    .send(Saturating(if on { brightness } else { 0 }))
+                    .await?
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn presence_simulation_task(
+    mut input: impl Stream<Item = SimulationMessage> + Unpin,
+    mut lights: Vec<SimulatedLight>,
+    settings: &PresenceSimulation,
+    mut rng: impl Rng,
+) -> Result<(), SimulationError> {
+    let mut time = None;
+    let mut house_mode = HouseMode::Home;
+    let mut active = false;
+    while let Some(event) = input.next().await {
+        match event {
+            SimulationMessage::Time(now) => time = Some(now),
+            SimulationMessage::HouseMode(mode) => house_mode = mode,
+        }
+        let now = Instant::now();
+        let should_run = house_mode != HouseMode::Home
+            && time.is_some_and(|t| time_in_range(settings.start, settings.end, t));
+        if should_run != active {
+            active = should_run;
+            if active {
+                info!("Start presence simulation");
+                // the lights start one after another
+                for light in lights.iter_mut() {
+                    light.next_change =
+                        now + random_duration(&mut rng, Default::default(), settings.max_off_time);
+                }
+            } else {
+                info!("Stop presence simulation");
+                // only lights switched on by the simulation are switched off
+                for light in lights.iter_mut().filter(|l| l.on) {
+                    light.set(false, settings.brightness).await?;
+                }
+            }
+        }
+        if !active {
+            continue;
+        }
+        for light in lights.iter_mut().filter(|l| l.next_change <= now) {
+            let on = !light.on;
+            light.set(on, settings.brightness).await?;
+            light.next_change = now
+                + if on {
+                    random_duration(&mut rng, settings.min_on_time, settings.max_on_time)
+                } else {
+                    random_duration(&mut rng, Default::default(), settings.max_off_time)
+                };
+        }
+    }
+    Ok(())
+}
+
+enum SimulationMessage {
+    Time(NaiveTime),
+    HouseMode(HouseMode),
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::NaiveTime;
+    use chrono_tz::Tz;
+    use rand::{rngs::SmallRng, SeedableRng};
+    use tokio::{
+        sync::mpsc,
+        time::{sleep, Instant},
+    };
+    use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+    use crate::{
+        controller::presence_simulation::{
+            presence_simulation_task, SimulatedLight, SimulatedOutput, SimulationMessage,
+        },
+        data::{
+            registry::{ClockKey, ClockKeyResolution, HouseMode},
+            wiring::PresenceSimulation,
+        },
+    };
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// Runs the simulation with a clock ticking every minute from 17:00 until 23:00, the house
+    /// mode changes at the given minutes, returns the switch changes as minutes since the start
+    async fn run_simulation(modes: Vec<(u32, HouseMode)>) -> Vec<(u32, bool)> {
+        let settings = PresenceSimulation {
+            clock: ClockKey {
+                resolution: ClockKeyResolution::Minutes,
+                tz: Tz::Europe__Zurich,
+            },
+            switches: Box::new([]),
+            dimmers: Box::new([]),
+            brightness: 200,
+            start: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            min_on_time: 10 * MINUTE,
+            max_on_time: 30 * MINUTE,
+            max_off_time: 20 * MINUTE,
+        };
+        let (input_tx, input_rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let start = NaiveTime::from_hms_opt(17, 0, 0).unwrap();
+            for minute in 0..=6 * 60 {
+                for (_, mode) in modes.iter().filter(|(at, _)| *at == minute) {
+                    input_tx
+                        .send(SimulationMessage::HouseMode(*mode))
+                        .await
+                        .unwrap();
+                }
+                let time = start + chrono::Duration::minutes(minute as i64);
+                input_tx.send(SimulationMessage::Time(time)).await.unwrap();
+                sleep(MINUTE).await;
+            }
+        });
+        let (light_tx, light_rx) = mpsc::channel(4);
+        let lights = vec![SimulatedLight::new(SimulatedOutput::Switch(light_tx))];
+        let start = Instant::now();
+        let recorder = tokio::spawn(
+            ReceiverStream::new(light_rx)
+                .map(move |on| ((start.elapsed().as_secs() / 60) as u32, on))
+                .collect::<Vec<_>>(),
+        );
+        presence_simulation_task(
+            ReceiverStream::new(input_rx),
+            lights,
+            &settings,
+            SmallRng::seed_from_u64(42),
+        )
+        .await
+        .unwrap();
+        recorder.await.unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_presence_simulation_window() {
+        let changes = run_simulation(vec![(0, HouseMode::Away)]).await;
+        assert!(changes.len() >= 4, "too few changes: {changes:?}");
+        // on and off alternate within the evening window, off at its end
+        let (first, _) = changes[0];
+        assert!((60..=80).contains(&first), "late start: {changes:?}");
+        for (idx, (minute, on)) in changes.iter().enumerate() {
+            assert_eq!(idx % 2 == 0, *on);
+            assert!((60..=300).contains(minute), "outside window: {changes:?}");
+        }
+        assert_eq!(Some(&(300, false)), changes.last());
+        for pair in changes.windows(2) {
+            let ((from, on), (to, _)) = (pair[0], pair[1]);
+            let duration = to - from;
+            if on && to < 300 {
+                assert!((10..=30).contains(&duration), "on time: {changes:?}");
+            } else if !on {
+                assert!(duration <= 20, "off time: {changes:?}");
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_presence_simulation_home() {
+        // nothing happens at home, coming home switches the simulated light off
+        assert_eq!(Vec::<(u32, bool)>::new(), run_simulation(vec![]).await);
+        let changes = run_simulation(vec![(0, HouseMode::Away), (120, HouseMode::Home)]).await;
+        assert!(changes.iter().all(|(minute, _)| *minute <= 120));
+        assert_eq!(Some(false), changes.last().map(|(_, on)| *on));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{num::Saturating, time::Duration};
+
+use log::{error, info, warn};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, error::SendError, Permit},
+    },
+    time::sleep,
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    controller::manager::{ControllerHandle, ControllerState, StateSender},
+    data::{
+        registry::{
+            ButtonState, EventRegistry, LightGroupKey, SceneCommand, SceneOutputs,
+            SingleButtonLayout,
+        },
+        wiring::{Scene, SceneButton},
+    },
+    terminator::JoinHandleTerminator,
+};
+
+/// Interval of the brightness and whitebalance updates during a transition
+const TRANSITION_STEP: Duration = Duration::from_millis(50);
+
+pub async fn scene_controller(
+    event_registry: &EventRegistry,
+    scenes: &[Scene],
+    state: StateSender,
+) -> ControllerHandle {
+    let commands = event_registry.scene_command_stream().await;
+    let event_registry = event_registry.clone();
+    let scenes: Box<[Scene]> = scenes.into();
+    tokio::spawn(async move {
+        scene_task(commands, event_registry, scenes, state).await;
+        Ok(())
+    })
+}
+
+pub async fn scene_button(
+    event_registry: &EventRegistry,
+    settings: &SceneButton,
+    state: StateSender,
+) -> ControllerHandle {
+    let input = event_registry.single_button_stream(settings.input).await;
+    let sender = event_registry.scene_command_sender().await;
+    let scene = settings.scene.clone();
+    let capture = settings.capture;
+    tokio::spawn(async move {
+        if let Err(error) = scene_button_task(input, sender, scene, capture, state).await {
+            error!("Failed scene button: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+/// Internal state of the scene controller and the scene buttons, published on every command
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SceneState {
+    pub last_recall: Option<Box<str>>,
+    pub last_capture: Option<Box<str>>,
+}
+
+#[derive(Error, Debug)]
+enum SceneError {
+    #[error("Output of the scene closed")]
+    Closed(#[from] SendError<()>),
+}
+
+async fn scene_task(
+    mut commands: impl Stream<Item = SceneCommand> + Unpin,
+    event_registry: EventRegistry,
+    scenes: Box<[Scene]>,
+    state: StateSender,
+) {
+    let mut running_transition = None::<JoinHandleTerminator<()>>;
+    let mut scene_state = SceneState::default();
+    while let Some(command) = commands.next().await {
+        match command {
+            SceneCommand::Recall { scene, transition } => {
+                let configured = scenes.iter().find(|s| s.name == scene);
+                // a captured scene replaces the values of the configured one
+                let values = if let Some(values) = event_registry.captured_scene(&scene).await {
+                    values
+                } else if let Some(configured) = configured {
+                    configured.values.clone()
+                } else {
+                    warn!("Unknown scene {scene}");
+                    continue;
+                };
+                let transition = transition
+                    .or(configured.map(|s| s.transition))
+                    .unwrap_or_default();
+                info!("Recall scene {scene}");
+                scene_state.last_recall = Some(scene.clone());
+                let outputs = event_registry.scene_outputs(&values).await;
+                running_transition.replace(
+                    tokio::spawn(async move {
+                        if let Err(error) = apply_scene(outputs, transition).await {
+                            error!("Failed applying scene {scene}: {error}");
+                        }
+                    })
+                    .into(),
+                );
+            }
+            SceneCommand::Capture { scene, group } => {
+                let values = event_registry.capture_scene(group).await;
+                info!(
+                    "Captured {} values of {group:?} into scene {scene}",
+                    values.brightness.len() + values.light_colors.len() + values.switches.len()
+                );
+                scene_state.last_capture = Some(scene.clone());
+                event_registry.store_scene(scene, values).await;
+            }
+        }
+        state.send_replace(ControllerState::Scene(scene_state.clone()));
+    }
+}
+
+async fn apply_scene(outputs: SceneOutputs, transition: Duration) -> Result<(), SceneError> {
+    let SceneOutputs {
+        brightness,
+        light_colors,
+        switches,
+    } = outputs;
+    let steps = (transition.as_millis() / TRANSITION_STEP.as_millis()).max(1) as u32;
+    for step in 1..=steps {
+        if steps > 1 {
+            sleep(TRANSITION_STEP).await;
+        }
+        // switch on with the first and off with the last step so the fading stays visible
+        let switch_values = switches
+            .iter()
+            .filter(|o| (o.target && step == 1) || (!o.target && step == steps))
+            .map(|o| (&o.sender, o.target));
+        let brightness_values = brightness.iter().map(|o| {
+            let value = interpolate(o.current.0 as u16, o.target.0 as u16, step, steps);
+            (&o.sender, Saturating(value as u8))
+        });
+        let light_color_values = light_colors.iter().map(|o| {
+            let value = interpolate(o.current.0, o.target.0, step, steps);
+            (&o.sender, Saturating(value))
+        });
+        // all values of a step are sent together, nobody sees a partially applied step
+        let switch_permits = reserve_all(switch_values).await?;
+        let brightness_permits = reserve_all(brightness_values).await?;
+        let light_color_permits = reserve_all(light_color_values).await?;
+        send_all(switch_permits);
+        send_all(brightness_permits);
+        send_all(light_color_permits);
+    }
+    Ok(())
+}
+
+async fn reserve_all<'a, T: 'a>(
+    values: impl Iterator<Item = (&'a mpsc::Sender<T>, T)>,
+) -> Result<Vec<(Permit<'a, T>, T)>, SendError<()>> {
+    let mut permits = Vec::new();
+    for (sender, value) in values {
+        permits.push((sender.reserve().await?, value));
+    }
+    Ok(permits)
+}
+
+fn send_all<T>(permits: Vec<(Permit<'_, T>, T)>) {
+    for (permit, value) in permits {
+        permit.send(value);
+    }
+}
+
+fn interpolate(start: u16, target: u16, step: u32, steps: u32) -> u16 {
+    let start = start as i64;
+    let target = target as i64;
+    (start + (target - start) * step as i64 / steps.max(1) as i64) as u16
+}
+
+async fn scene_button_task(
+    mut input: impl Stream<Item = ButtonState<SingleButtonLayout>> + Unpin,
+    sender: broadcast::Sender<SceneCommand>,
+    scene: Box<str>,
+    capture: Option<LightGroupKey>,
+    state: StateSender,
+) -> Result<(), broadcast::error::SendError<SceneCommand>> {
+    let recall = || SceneCommand::Recall {
+        scene: scene.clone(),
+        transition: None,
+    };
+    // a long press starts as short press, capturing buttons recall on release
+    let mut pending_recall = false;
+    let mut scene_state = SceneState::default();
+    while let Some(button) = input.next().await {
+        match (button, capture) {
+            (ButtonState::ShortPressStart(_), None) => {
+                sender.send(recall())?;
+                scene_state.last_recall = Some(scene.clone());
+            }
+            (ButtonState::ShortPressStart(_), Some(_)) => {
+                pending_recall = true;
+            }
+            (ButtonState::LongPressStart(_), Some(group)) => {
+                pending_recall = false;
+                sender.send(SceneCommand::Capture {
+                    scene: scene.clone(),
+                    group,
+                })?;
+                scene_state.last_capture = Some(scene.clone());
+            }
+            (ButtonState::Released, Some(_)) if pending_recall => {
+                pending_recall = false;
+                sender.send(recall())?;
+                scene_state.last_recall = Some(scene.clone());
+            }
+            _ => {}
+        }
+        state.send_replace(ControllerState::Scene(scene_state.clone()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::controller::scene::interpolate;
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(100, interpolate(100, 200, 0, 10));
+        assert_eq!(150, interpolate(100, 200, 5, 10));
+        assert_eq!(200, interpolate(100, 200, 10, 10));
+        assert_eq!(170, interpolate(200, 100, 3, 10));
+        // without transition the target is reached in the first step
+        assert_eq!(0, interpolate(255, 0, 1, 1));
+    }
+}
@@ -0,0 +1,601 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io,
+    num::Saturating,
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use futures::stream::SelectAll;
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use rhai::{
+    module_resolvers::DummyModuleResolver, Dynamic, Engine, EvalAltResult, FnPtr, ParseError,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    sync::mpsc::error::SendError,
+    task::{spawn_blocking, JoinError},
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::controller::manager::{ControllerHandle, ControllerState, StateSender};
+use crate::data::{
+    registry::{
+        BrightnessKey, ButtonState, DualButtonKey, DualButtonLayout, EventRegistry, HouseMode,
+        IlluminanceKey, LightColorKey, LogicKey, ShutterPositionKey, SingleButtonKey,
+        SwitchOutputKey, TemperatureKey, WindowOpenKey,
+    },
+    wiring::Script,
+};
+
+/// Operations a single run of a script may execute
+const MAX_OPERATIONS: u64 = 100_000;
+/// Wall time a single run of a script may take
+const MAX_RUN_TIME: Duration = Duration::from_millis(200);
+
+lazy_static! {
+    static ref SCRIPT_ERRORS: Mutex<BTreeMap<Box<str>, ScriptErrorEntry>> =
+        Mutex::new(BTreeMap::new());
+    /// error reading the script directory, stays until the directory is readable again
+    static ref SCRIPT_LOAD_ERROR: Mutex<Option<ScriptErrorEntry>> = Mutex::new(None);
+}
+
+/// Last error of a script since it was loaded
+#[derive(Clone, Debug, Serialize)]
+pub struct ScriptErrorEntry {
+    pub script: Box<str>,
+    pub error: Box<str>,
+    pub since: DateTime<Utc>,
+}
+
+pub fn script_errors() -> Vec<ScriptErrorEntry> {
+    let mut errors = SCRIPT_LOAD_ERROR
+        .lock()
+        .expect("Script load error lock poisoned")
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    errors.extend(
+        SCRIPT_ERRORS
+            .lock()
+            .expect("Script error lock poisoned")
+            .values()
+            .cloned(),
+    );
+    errors
+}
+
+pub fn clear_script_errors() {
+    SCRIPT_ERRORS
+        .lock()
+        .expect("Script error lock poisoned")
+        .clear();
+}
+
+fn report_script_error(script: &str, error: impl Display) {
+    warn!("Script {script}: {error}");
+    SCRIPT_ERRORS
+        .lock()
+        .expect("Script error lock poisoned")
+        .insert(
+            script.into(),
+            ScriptErrorEntry {
+                script: script.into(),
+                error: error.to_string().into_boxed_str(),
+                since: Utc::now(),
+            },
+        );
+}
+
+/// Reads the scripts of a directory, the previous scripts stay loaded if it cannot be read
+pub async fn load_scripts(directory: &Path, previous: &[Script]) -> Box<[Script]> {
+    let result = read_scripts(directory).await;
+    let mut load_error = SCRIPT_LOAD_ERROR
+        .lock()
+        .expect("Script load error lock poisoned");
+    match result {
+        Ok(scripts) => {
+            load_error.take();
+            scripts
+        }
+        Err(error) => {
+            let directory = directory.display();
+            error!("Cannot read scripts of {directory}: {error}");
+            let since = load_error.as_ref().map_or_else(Utc::now, |e| e.since);
+            load_error.replace(ScriptErrorEntry {
+                script: directory.to_string().into_boxed_str(),
+                error: error.to_string().into_boxed_str(),
+                since,
+            });
+            previous.into()
+        }
+    }
+}
+
+/// Reads all `*.rhai` files of a directory, a missing directory contains no scripts
+async fn read_scripts(directory: &Path) -> io::Result<Box<[Script]>> {
+    let mut entries = match tokio::fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Box::new([])),
+        Err(error) => return Err(error),
+    };
+    let mut scripts = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "rhai") {
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                scripts.push(Script {
+                    name: name.into(),
+                    source: tokio::fs::read_to_string(&path).await?.into_boxed_str(),
+                });
+            }
+        }
+    }
+    scripts.sort();
+    Ok(scripts.into_boxed_slice())
+}
+
+pub async fn script_controller(
+    event_registry: &EventRegistry,
+    script: &Script,
+    state: StateSender,
+) -> ControllerHandle {
+    let event_registry = event_registry.clone();
+    let script = script.clone();
+    tokio::spawn(async move {
+        info!("Start script {}", script.name);
+        if let Err(error) = script_task(&event_registry, &script, &state).await {
+            error!("Failed script {}: {error}", script.name);
+            report_script_error(&script.name, &error);
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+/// Internal state of a script, published after every run
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ScriptState {
+    pub subscriptions: usize,
+    pub handled_events: u64,
+    pub failed_runs: u64,
+    pub last_event: Option<DateTime<Utc>>,
+}
+
+#[derive(Error, Debug)]
+enum ScriptError {
+    #[error("Cannot parse: {0}")]
+    Parse(#[from] ParseError),
+    #[error("Cannot run: {0}")]
+    Runtime(#[from] Box<EvalAltResult>),
+    #[error("Cannot join script run: {0}")]
+    Join(#[from] JoinError),
+    #[error("Cannot send switch: {0}")]
+    Switch(#[from] SendError<bool>),
+    #[error("Cannot send brightness: {0}")]
+    Brightness(#[from] SendError<Saturating<u8>>),
+    #[error("Cannot send whitebalance: {0}")]
+    LightColor(#[from] SendError<Saturating<u16>>),
+    #[error("Cannot send temperature: {0}")]
+    Temperature(#[from] SendError<f32>),
+    #[error("Cannot send house mode: {0}")]
+    HouseMode(#[from] SendError<HouseMode>),
+}
+
+/// Register a script subscribes to or writes, e.g. `#{ Switch: #{ Light: #{ room: "1.4", idx: 0 } } }`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum ScriptSignal {
+    Switch(SwitchOutputKey),
+    Brightness(BrightnessKey),
+    LightColor(LightColorKey),
+    Temperature(TemperatureKey),
+    WindowOpen(WindowOpenKey),
+    Illuminance(IlluminanceKey),
+    ShutterPosition(ShutterPositionKey),
+    Logic(LogicKey),
+    Button(SingleButtonKey),
+    DualButton(DualButtonKey),
+    HouseMode,
+}
+
+enum ScriptValue {
+    Switch(SwitchOutputKey, bool),
+    Logic(LogicKey, bool),
+    Brightness(BrightnessKey, u8),
+    LightColor(LightColorKey, u16),
+    Temperature(TemperatureKey, f32),
+    HouseMode(HouseMode),
+}
+
+type SignalStream = Pin<Box<dyn Stream<Item = Dynamic> + Send>>;
+
+/// State shared between the engine callbacks and the task
+#[derive(Default)]
+struct ScriptContext {
+    subscriptions: Vec<(ScriptSignal, FnPtr)>,
+    writes: Vec<ScriptValue>,
+    run_start: Option<Instant>,
+}
+
+async fn script_task(
+    event_registry: &EventRegistry,
+    script: &Script,
+    state: &StateSender,
+) -> Result<(), ScriptError> {
+    let context = Arc::new(Mutex::new(ScriptContext::default()));
+    let engine = Arc::new(create_engine(&script.name, &context));
+    let ast = Arc::new(engine.compile(&*script.source)?);
+    // the top level registers the subscriptions
+    run_limited(&context, {
+        let engine = engine.clone();
+        let ast = ast.clone();
+        move || engine.run_ast(&ast)
+    })
+    .await??;
+    send_writes(event_registry, &context).await?;
+    let subscriptions = std::mem::take(&mut lock(&context).subscriptions);
+    let mut script_state = ScriptState {
+        subscriptions: subscriptions.len(),
+        ..Default::default()
+    };
+    state.send_replace(ControllerState::Script(script_state.clone()));
+    let mut input_stream = SelectAll::new();
+    for (idx, (signal, _)) in subscriptions.iter().enumerate() {
+        input_stream.push(
+            signal_stream(event_registry, *signal)
+                .await
+                .map(move |value| (idx, value)),
+        );
+    }
+    while let Some((idx, value)) = input_stream.next().await {
+        let handler = subscriptions[idx].1.clone();
+        let result = run_limited(&context, {
+            let engine = engine.clone();
+            let ast = ast.clone();
+            move || handler.call::<Dynamic>(&engine, &ast, (value,))
+        })
+        .await?;
+        script_state.handled_events += 1;
+        script_state.last_event = Some(Utc::now());
+        // a failing handler does not stop the other subscriptions
+        if let Err(error) = result {
+            script_state.failed_runs += 1;
+            report_script_error(&script.name, error);
+        }
+        state.send_replace(ControllerState::Script(script_state.clone()));
+        send_writes(event_registry, &context).await?;
+    }
+    Ok(())
+}
+
+fn lock(context: &Mutex<ScriptContext>) -> std::sync::MutexGuard<'_, ScriptContext> {
+    context.lock().expect("Script context lock poisoned")
+}
+
+async fn run_limited<R: Send + 'static>(
+    context: &Arc<Mutex<ScriptContext>>,
+    run: impl FnOnce() -> R + Send + 'static,
+) -> Result<R, JoinError> {
+    lock(context).run_start = Some(Instant::now());
+    let result = spawn_blocking(run).await;
+    lock(context).run_start = None;
+    result
+}
+
+fn create_engine(name: &str, context: &Arc<Mutex<ScriptContext>>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .disable_symbol("eval")
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(10_000)
+        .set_max_array_size(1_000)
+        .set_max_map_size(1_000);
+    let progress_context = context.clone();
+    engine.on_progress(move |_| {
+        lock(&progress_context)
+            .run_start
+            .filter(|start| start.elapsed() > MAX_RUN_TIME)
+            .map(|_| "Run time exceeded".into())
+    });
+    let print_name: Box<str> = name.into();
+    engine.on_print(move |text| info!("Script {print_name}: {text}"));
+    let subscribe_context = context.clone();
+    engine.register_fn(
+        "subscribe",
+        move |signal: Dynamic, handler: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            let signal = rhai::serde::from_dynamic::<ScriptSignal>(&signal)?;
+            lock(&subscribe_context)
+                .subscriptions
+                .push((signal, handler));
+            Ok(())
+        },
+    );
+    let set_context = context.clone();
+    engine.register_fn(
+        "set",
+        move |signal: Dynamic, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let signal = rhai::serde::from_dynamic::<ScriptSignal>(&signal)?;
+            let value = script_value(signal, value)?;
+            lock(&set_context).writes.push(value);
+            Ok(())
+        },
+    );
+    engine
+}
+
+fn script_value(signal: ScriptSignal, value: Dynamic) -> Result<ScriptValue, Box<EvalAltResult>> {
+    let type_name = value.type_name();
+    let mismatch = |expected: &str| -> Box<EvalAltResult> {
+        format!("Expected {expected} for {signal:?}, got {type_name}").into()
+    };
+    Ok(match signal {
+        ScriptSignal::Switch(key) => {
+            ScriptValue::Switch(key, value.as_bool().map_err(|_| mismatch("bool"))?)
+        }
+        ScriptSignal::Logic(key) => {
+            ScriptValue::Logic(key, value.as_bool().map_err(|_| mismatch("bool"))?)
+        }
+        ScriptSignal::Brightness(key) => ScriptValue::Brightness(
+            key,
+            value.as_int().map_err(|_| mismatch("int"))?.clamp(0, 255) as u8,
+        ),
+        ScriptSignal::LightColor(key) => ScriptValue::LightColor(
+            key,
+            value
+                .as_int()
+                .map_err(|_| mismatch("int"))?
+                .clamp(0, u16::MAX as i64) as u16,
+        ),
+        ScriptSignal::Temperature(key) => ScriptValue::Temperature(
+            key,
+            value
+                .as_float()
+                .or_else(|_| value.as_int().map(|v| v as f64))
+                .map_err(|_| mismatch("float"))? as f32,
+        ),
+        ScriptSignal::HouseMode => ScriptValue::HouseMode(rhai::serde::from_dynamic(&value)?),
+        ScriptSignal::WindowOpen(_)
+        | ScriptSignal::Illuminance(_)
+        | ScriptSignal::ShutterPosition(_)
+        | ScriptSignal::Button(_)
+        | ScriptSignal::DualButton(_) => {
+            return Err(format!("{signal:?} is not writable").into());
+        }
+    })
+}
+
+async fn send_writes(
+    event_registry: &EventRegistry,
+    context: &Mutex<ScriptContext>,
+) -> Result<(), ScriptError> {
+    let writes = std::mem::take(&mut lock(context).writes);
+    for value in writes {
+        match value {
+            ScriptValue::Switch(key, value) => {
+                event_registry.switch_sender(key).await.send(value).await?
+            }
+            ScriptValue::Logic(key, value) => {
+                event_registry.logic_sender(key).await.send(value).await?
+            }
+            ScriptValue::Brightness(key, value) => {
+                event_registry
+                    .brightness_sender(key)
+                    .await
+                    .send(Saturating(value))
+                    .await?
+            }
+            ScriptValue::LightColor(key, value) => {
+                event_registry
+                    .light_color_sender(key)
+                    .await
+                    .send(Saturating(value))
+                    .await?
+            }
+            ScriptValue::Temperature(key, value) => {
+                event_registry
+                    .temperature_sender(key)
+                    .await
+                    .send(value)
+                    .await?
+            }
+            ScriptValue::HouseMode(mode) => {
+                event_registry.house_mode_sender().await.send(mode).await?
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn signal_stream(event_registry: &EventRegistry, signal: ScriptSignal) -> SignalStream {
+    match signal {
+        ScriptSignal::Switch(key) => {
+            Box::pin(event_registry.switch_stream(key).await.map(Dynamic::from))
+        }
+        ScriptSignal::Brightness(key) => Box::pin(
+            event_registry
+                .brightness_stream(key)
+                .await
+                .map(|v| Dynamic::from(v.0 as i64)),
+        ),
+        ScriptSignal::LightColor(key) => Box::pin(
+            event_registry
+                .light_color_stream(key)
+                .await
+                .map(|v| Dynamic::from(v.0 as i64)),
+        ),
+        ScriptSignal::Temperature(key) => Box::pin(
+            event_registry
+                .temperature_stream(key)
+                .await
+                .map(|v| Dynamic::from(v as f64)),
+        ),
+        ScriptSignal::WindowOpen(key) => Box::pin(
+            event_registry
+                .window_open_stream(key)
+                .await
+                .map(Dynamic::from),
+        ),
+        ScriptSignal::Illuminance(key) => Box::pin(
+            event_registry
+                .illuminance_stream(key)
+                .await
+                .map(|v| Dynamic::from(v as i64)),
+        ),
+        ScriptSignal::ShutterPosition(key) => Box::pin(
+            event_registry
+                .shutter_position_stream(key)
+                .await
+                .map(|v| Dynamic::from(v as i64)),
+        ),
+        ScriptSignal::Logic(key) => {
+            Box::pin(event_registry.logic_stream(key).await.map(Dynamic::from))
+        }
+        ScriptSignal::Button(key) => Box::pin(
+            event_registry
+                .single_button_stream(key)
+                .await
+                .map(|state| Dynamic::from(button_state_name(state))),
+        ),
+        ScriptSignal::DualButton(key) => Box::pin(
+            event_registry
+                .dual_button_stream(key)
+                .await
+                .map(dual_button_value),
+        ),
+        ScriptSignal::HouseMode => Box::pin(
+            event_registry
+                .house_mode_stream()
+                .await
+                .map(|mode| Dynamic::from(format!("{mode:?}"))),
+        ),
+    }
+}
+
+fn button_state_name<B: Copy + Eq + std::hash::Hash>(state: ButtonState<B>) -> &'static str {
+    match state {
+        ButtonState::Released => "Released",
+        ButtonState::ShortPressStart(_) => "ShortPress",
+        ButtonState::LongPressStart(_) => "LongPress",
+        ButtonState::DoubleClickStart(_) => "DoubleClick",
+        ButtonState::TripleClickStart(_) => "TripleClick",
+    }
+}
+
+/// `#{ state: "ShortPress", button: "Up" }`, the button is missing on release
+fn dual_button_value(state: ButtonState<DualButtonLayout>) -> Dynamic {
+    let mut map = rhai::Map::new();
+    map.insert("state".into(), button_state_name(state).into());
+    let button = match state {
+        ButtonState::Released => None,
+        ButtonState::ShortPressStart(button)
+        | ButtonState::LongPressStart(button)
+        | ButtonState::DoubleClickStart(button)
+        | ButtonState::TripleClickStart(button) => Some(button),
+    };
+    if let Some(button) = button {
+        map.insert("button".into(), format!("{button:?}").into());
+    }
+    map.into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use rhai::Dynamic;
+
+    use crate::{
+        controller::script::{
+            create_engine, load_scripts, lock, script_errors, ScriptContext, ScriptSignal,
+            ScriptValue,
+        },
+        data::{
+            registry::{SwitchOutputKey, TemperatureKey},
+            wiring::Script,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_load_scripts_keeps_previous() {
+        let previous = [Script {
+            name: "light".into(),
+            source: "set_all_off();".into(),
+        }];
+        // a file is no readable directory
+        let directory = Path::new(file!());
+        assert_eq!(
+            previous.as_slice(),
+            &*load_scripts(directory, &previous).await
+        );
+        let file_name = directory.display().to_string();
+        assert!(script_errors()
+            .iter()
+            .any(|entry| *entry.script == *file_name));
+        // a missing directory contains no scripts
+        assert!(load_scripts(Path::new("missing-scripts"), &previous)
+            .await
+            .is_empty());
+        assert!(script_errors()
+            .iter()
+            .all(|entry| *entry.script != *file_name));
+    }
+
+    #[test]
+    fn test_script_subscribe_and_set() {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let engine = create_engine("test", &context);
+        let ast = engine
+            .compile(
+                r#"
+                let light = #{ Switch: #{ Light: #{ room: "1.4", idx: 0 } } };
+                subscribe(#{ Temperature: #{ CurrentTemperature: #{ room: "1.4", idx: 0 } } },
+                    |value| set(light, value > 25.0));
+                set(light, true);
+                "#,
+            )
+            .unwrap();
+        engine.run_ast(&ast).unwrap();
+        let (signal, handler) = lock(&context).subscriptions.pop().unwrap();
+        assert!(matches!(
+            signal,
+            ScriptSignal::Temperature(TemperatureKey::CurrentTemperature(_))
+        ));
+        assert!(matches!(
+            lock(&context).writes.pop(),
+            Some(ScriptValue::Switch(SwitchOutputKey::Light(_), true))
+        ));
+        let _: Dynamic = handler
+            .call(&engine, &ast, (Dynamic::from(21.5f64),))
+            .unwrap();
+        assert!(matches!(
+            lock(&context).writes.pop(),
+            Some(ScriptValue::Switch(SwitchOutputKey::Light(_), false))
+        ));
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let engine = create_engine("test", &context);
+        assert!(engine.run("loop {}").is_err());
+        // buttons are read only
+        assert!(engine
+            .run(
+                r#"set(#{ Button: #{ Button: #{ room: "1.4", device_idx: 0, sub_device_idx: 0 } } },
+                    true)"#
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,504 @@
+use std::time::Duration;
+
+use futures::stream::SelectAll;
+use log::error;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    sync::mpsc::{self, error::SendError, Sender},
+    time::{sleep, sleep_until, Instant},
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use crate::{
+    controller::manager::{ControllerHandle, ControllerState, StateSender},
+    data::{
+        registry::{ButtonState, DualButtonLayout, EventRegistry},
+        wiring::Shutter,
+    },
+    terminator::JoinHandleTerminator,
+};
+
+/// Pause between stopping the motor and running it in the other direction
+const REVERSAL_PAUSE: Duration = Duration::from_millis(500);
+/// Interval of the position updates while the motor runs
+const POSITION_UPDATE: Duration = Duration::from_secs(1);
+
+pub async fn shutter(
+    event_registry: &EventRegistry,
+    settings: &Shutter,
+    state: StateSender,
+) -> ControllerHandle {
+    let mut input_stream = SelectAll::new();
+    for input in settings.input.iter() {
+        input_stream.push(
+            event_registry
+                .dual_button_stream(*input)
+                .await
+                .map(ShutterEvent::Button),
+        );
+    }
+    let outputs = ShutterOutputs {
+        up: event_registry.switch_sender(settings.up).await,
+        down: event_registry.switch_sender(settings.down).await,
+        position: event_registry
+            .shutter_position_sender(settings.position)
+            .await,
+    };
+    let position = event_registry
+        .shutter_position_stream(settings.position)
+        .await
+        .next()
+        .await
+        .unwrap_or_default();
+    let travel_time = settings.travel_time;
+    tokio::spawn(async move {
+        if let Err(error) = shutter_task(input_stream, outputs, position, travel_time, state).await
+        {
+            error!("Failed shutter: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+#[derive(Error, Debug)]
+enum ShutterError {
+    #[error("Cannot send motor relay: {0}")]
+    Relay(#[from] SendError<bool>),
+    #[error("Cannot send position: {0}")]
+    Position(#[from] SendError<u8>),
+}
+
+/// Internal state of a shutter controller, published on every event
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ShutterState {
+    /// estimated position in percent, 0 is open
+    pub position: u8,
+    pub moving: Option<Direction>,
+    /// stops on release of the button
+    pub jog: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl From<DualButtonLayout> for Direction {
+    fn from(value: DualButtonLayout) -> Self {
+        match value {
+            DualButtonLayout::Up => Direction::Up,
+            DualButtonLayout::Down => Direction::Down,
+        }
+    }
+}
+
+struct ShutterOutputs {
+    up: Sender<bool>,
+    down: Sender<bool>,
+    position: Sender<u8>,
+}
+
+impl ShutterOutputs {
+    fn relay(&self, direction: Direction) -> &Sender<bool> {
+        match direction {
+            Direction::Up => &self.up,
+            Direction::Down => &self.down,
+        }
+    }
+}
+
+struct Movement {
+    /// counts up with every movement, events of the timer of an earlier one are ignored
+    generation: u64,
+    direction: Direction,
+    start: Instant,
+    start_position: u8,
+    /// stops on release of the button
+    jog: bool,
+}
+
+impl Movement {
+    fn position(&self, travel_time: Duration) -> u8 {
+        estimate_position(
+            self.start_position,
+            self.direction,
+            self.start.elapsed(),
+            travel_time,
+        )
+    }
+}
+
+enum ShutterEvent {
+    Button(ButtonState<DualButtonLayout>),
+    Progress(u64),
+    Stop(u64),
+}
+
+async fn shutter_task(
+    input_stream: impl Stream<Item = ShutterEvent> + Unpin,
+    outputs: ShutterOutputs,
+    mut position: u8,
+    travel_time: Duration,
+    state: StateSender,
+) -> Result<(), ShutterError> {
+    let (tx, rx) = mpsc::channel(2);
+    let mut input_stream = input_stream.merge(ReceiverStream::new(rx));
+    let mut movement = None::<Movement>;
+    let mut generation = 0;
+    let mut stop_timer = None::<JoinHandleTerminator<()>>;
+    // both relays off, the registers may still hold a value of before a restart
+    outputs.up.send(false).await?;
+    outputs.down.send(false).await?;
+    loop {
+        // every event ends up here, also the ones continuing the loop early
+        state.send_replace(ControllerState::Shutter(ShutterState {
+            position: movement
+                .as_ref()
+                .map_or(position, |m| m.position(travel_time)),
+            moving: movement.as_ref().map(|m| m.direction),
+            jog: movement.as_ref().is_some_and(|m| m.jog),
+        }));
+        let Some(event) = input_stream.next().await else {
+            break;
+        };
+        let (direction, jog) = match event {
+            ShutterEvent::Button(ButtonState::ShortPressStart(button)) => {
+                // a short press while running stops the shutter
+                if let Some(running) = movement.take() {
+                    stop_timer.take();
+                    position = stop(&outputs, running, travel_time).await?;
+                    continue;
+                }
+                (Direction::from(button), false)
+            }
+            // the further clicks of a double click would stop the movement again
+            ShutterEvent::Button(
+                ButtonState::DoubleClickStart(_) | ButtonState::TripleClickStart(_),
+            ) => continue,
+            ShutterEvent::Button(ButtonState::LongPressStart(button)) => {
+                (Direction::from(button), true)
+            }
+            ShutterEvent::Button(ButtonState::Released) => {
+                if let Some(running) = movement.take_if(|m| m.jog) {
+                    stop_timer.take();
+                    position = stop(&outputs, running, travel_time).await?;
+                }
+                continue;
+            }
+            ShutterEvent::Progress(event_generation) => {
+                if let Some(running) = movement
+                    .as_ref()
+                    .filter(|m| m.generation == event_generation)
+                {
+                    outputs.position.send(running.position(travel_time)).await?;
+                }
+                continue;
+            }
+            ShutterEvent::Stop(event_generation) => {
+                if let Some(running) = movement.take_if(|m| m.generation == event_generation) {
+                    position = stop(&outputs, running, travel_time).await?;
+                }
+                continue;
+            }
+        };
+        if let Some(running) = movement.take() {
+            let reverse = running.direction != direction;
+            position = stop(&outputs, running, travel_time).await?;
+            if reverse {
+                sleep(REVERSAL_PAUSE).await;
+            }
+        }
+        // a full travel runs the whole travel time to hit the end stop in any case
+        let run_time = if jog {
+            remaining_time(position, direction, travel_time)
+        } else {
+            travel_time
+        };
+        outputs.relay(direction).send(true).await?;
+        generation += 1;
+        let start = Instant::now();
+        movement = Some(Movement {
+            generation,
+            direction,
+            start,
+            start_position: position,
+            jog,
+        });
+        let tx = tx.clone();
+        stop_timer.replace(
+            tokio::spawn(async move {
+                let end = start + run_time;
+                let mut update = start + POSITION_UPDATE;
+                while update < end {
+                    sleep_until(update).await;
+                    if let Err(error) = tx.send(ShutterEvent::Progress(generation)).await {
+                        error!("Cannot send shutter progress: {error}");
+                        return;
+                    }
+                    update += POSITION_UPDATE;
+                }
+                sleep_until(end).await;
+                if let Err(error) = tx.send(ShutterEvent::Stop(generation)).await {
+                    error!("Cannot send shutter stop: {error}");
+                }
+            })
+            .into(),
+        );
+    }
+    Ok(())
+}
+
+/// Switches off the running motor and publishes the estimated position
+async fn stop(
+    outputs: &ShutterOutputs,
+    movement: Movement,
+    travel_time: Duration,
+) -> Result<u8, ShutterError> {
+    outputs.relay(movement.direction).send(false).await?;
+    let position = movement.position(travel_time);
+    outputs.position.send(position).await?;
+    Ok(position)
+}
+
+fn remaining_time(position: u8, direction: Direction, travel_time: Duration) -> Duration {
+    let distance = match direction {
+        Direction::Up => position,
+        Direction::Down => 100 - position.min(100),
+    };
+    travel_time * distance as u32 / 100
+}
+
+fn estimate_position(
+    start_position: u8,
+    direction: Direction,
+    elapsed: Duration,
+    travel_time: Duration,
+) -> u8 {
+    if travel_time.is_zero() {
+        return start_position;
+    }
+    let moved = (elapsed.as_millis() * 100 / travel_time.as_millis()).min(100) as u8;
+    match direction {
+        Direction::Up => start_position.saturating_sub(moved),
+        Direction::Down => start_position.saturating_add(moved).min(100),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::{
+        sync::{mpsc, watch},
+        task::JoinHandle,
+        time::{sleep_until, timeout, Instant},
+    };
+    use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+    use crate::{
+        controller::{
+            manager::ControllerState,
+            shutter::{
+                estimate_position, remaining_time, shutter_task, Direction, ShutterEvent,
+                ShutterOutputs,
+            },
+        },
+        data::registry::{ButtonState, DualButtonLayout},
+    };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+    fn short(button: DualButtonLayout) -> ShutterEvent {
+        ShutterEvent::Button(ButtonState::ShortPressStart(button))
+    }
+    fn long(button: DualButtonLayout) -> ShutterEvent {
+        ShutterEvent::Button(ButtonState::LongPressStart(button))
+    }
+    fn release() -> ShutterEvent {
+        ShutterEvent::Button(ButtonState::Released)
+    }
+    fn record<T: Send + 'static>(
+        start: Instant,
+        rx: mpsc::Receiver<T>,
+    ) -> JoinHandle<Vec<(Duration, T)>> {
+        tokio::spawn(
+            ReceiverStream::new(rx)
+                .map(move |value| (start.elapsed(), value))
+                .collect(),
+        )
+    }
+
+    /// Sends the events at their offsets to an open shutter with 10s travel time, returns the
+    /// changes of the up and down relays and the published positions of the first 30s
+    async fn run_shutter(
+        events: Vec<(Duration, ShutterEvent)>,
+    ) -> (
+        Vec<(Duration, bool)>,
+        Vec<(Duration, bool)>,
+        Vec<(Duration, u8)>,
+    ) {
+        let start = Instant::now();
+        let (up_tx, up_rx) = mpsc::channel(4);
+        let (down_tx, down_rx) = mpsc::channel(4);
+        let (position_tx, position_rx) = mpsc::channel(4);
+        let up = record(start, up_rx);
+        let down = record(start, down_rx);
+        let position = record(start, position_rx);
+        let outputs = ShutterOutputs {
+            up: up_tx,
+            down: down_tx,
+            position: position_tx,
+        };
+        let (input_tx, input_rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            for (at, event) in events {
+                sleep_until(start + at).await;
+                input_tx.send(event).await.unwrap();
+            }
+        });
+        let (state_tx, _) = watch::channel(ControllerState::Unknown);
+        // the task keeps waiting for its own timer, it ends with the timeout
+        let task = shutter_task(
+            ReceiverStream::new(input_rx),
+            outputs,
+            0,
+            ms(10000),
+            state_tx,
+        );
+        assert!(timeout(ms(30000), task).await.is_err());
+        (
+            up.await.unwrap(),
+            down.await.unwrap(),
+            position.await.unwrap(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutter_reversal() {
+        let (up, down, position) = run_shutter(vec![
+            (ms(0), short(DualButtonLayout::Down)),
+            (ms(100), release()),
+            // a second button held in the other direction
+            (ms(4200), long(DualButtonLayout::Up)),
+        ])
+        .await;
+        assert_eq!(down, [(ms(0), false), (ms(0), true), (ms(4200), false)]);
+        // the motor pauses before running the other way, only until the upper end
+        assert_eq!(up, [(ms(0), false), (ms(4700), true), (ms(8900), false)]);
+        assert_eq!(
+            position,
+            [
+                (ms(1000), 10),
+                (ms(2000), 20),
+                (ms(3000), 30),
+                (ms(4000), 40),
+                (ms(4200), 42),
+                (ms(5700), 32),
+                (ms(6700), 22),
+                (ms(7700), 12),
+                (ms(8700), 2),
+                (ms(8900), 0),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutter_jog() {
+        let (up, down, position) = run_shutter(vec![
+            (ms(0), short(DualButtonLayout::Down)),
+            (ms(800), long(DualButtonLayout::Down)),
+            (ms(2500), release()),
+            // a short press keeps running after the release
+            (ms(5000), short(DualButtonLayout::Up)),
+            (ms(5100), release()),
+        ])
+        .await;
+        assert_eq!(
+            down,
+            [
+                (ms(0), false),
+                (ms(0), true),
+                (ms(800), false),
+                (ms(800), true),
+                (ms(2500), false),
+            ]
+        );
+        assert_eq!(up, [(ms(0), false), (ms(5000), true), (ms(15000), false)]);
+        assert_eq!(
+            position[..3],
+            [(ms(800), 8), (ms(1800), 18), (ms(2500), 25)]
+        );
+        assert_eq!(Some(&(ms(15000), 0)), position.last());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutter_stale_stop() {
+        let (_, down, position) = run_shutter(vec![
+            (ms(0), long(DualButtonLayout::Down)),
+            (ms(2500), release()),
+            (ms(3000), long(DualButtonLayout::Down)),
+            // the timer of the first movement, it must not stop the second one
+            (ms(4500), ShutterEvent::Stop(1)),
+            (ms(4500), ShutterEvent::Progress(1)),
+            (ms(6500), release()),
+        ])
+        .await;
+        assert_eq!(
+            down,
+            [
+                (ms(0), false),
+                (ms(0), true),
+                (ms(2500), false),
+                (ms(3000), true),
+                (ms(6500), false),
+            ]
+        );
+        assert_eq!(
+            position,
+            [
+                (ms(1000), 10),
+                (ms(2000), 20),
+                (ms(2500), 25),
+                (ms(4000), 35),
+                (ms(5000), 45),
+                (ms(6000), 55),
+                (ms(6500), 60),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_estimate_position() {
+        let travel_time = Duration::from_secs(20);
+        assert_eq!(
+            50,
+            estimate_position(0, Direction::Down, Duration::from_secs(10), travel_time)
+        );
+        assert_eq!(
+            30,
+            estimate_position(80, Direction::Up, Duration::from_secs(10), travel_time)
+        );
+        // running longer than the travel time ends at the end stop
+        assert_eq!(
+            100,
+            estimate_position(70, Direction::Down, Duration::from_secs(30), travel_time)
+        );
+        assert_eq!(
+            0,
+            estimate_position(20, Direction::Up, Duration::from_secs(30), travel_time)
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            remaining_time(25, Direction::Up, travel_time)
+        );
+        assert_eq!(
+            Duration::from_secs(15),
+            remaining_time(25, Direction::Down, travel_time)
+        );
+    }
+}
@@ -0,0 +1,888 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::watch,
+    time::{sleep_until, Instant},
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    controller::{
+        heat::HeatProtection,
+        manager::{ControllerConfig, ControllerResult, ControllerState},
+    },
+    data::registry::{
+        BrightnessKey, ButtonState, DualButtonKey, DualButtonLayout, EventRegistry,
+        ShutterPositionKey, SingleButtonKey, SingleButtonLayout, SwitchOutputKey, TemperatureKey,
+        WindowOpenKey,
+    },
+    terminator::JoinHandleTerminator,
+};
+
+const PROTECTION: HeatProtection = HeatProtection {
+    frost_temperature: 5.0,
+    eco_temperature: 18.0,
+    alarm_temperature_change: 1.0,
+    alarm_settle_time: Duration::from_secs(24 * 3600),
+};
+
+/// Change of an observed register or event channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Output {
+    Switch(SwitchOutputKey, bool),
+    Brightness(BrightnessKey, u8),
+    WindowOpen(WindowOpenKey, bool),
+    Ring {
+        button: SingleButtonKey,
+        quiet: bool,
+    },
+    ShutterPosition(ShutterPositionKey, u8),
+}
+
+/// Controllers running against a fresh event registry, only usable on a paused clock
+///
+/// The clock advances only while all tasks are idle, so every injected input settles before the
+/// next step. Two values sent to the same register at the same instant may merge into the last one.
+pub struct Simulation {
+    event_registry: EventRegistry,
+    start: Instant,
+    outputs: Arc<Mutex<Vec<(Duration, Output)>>>,
+    recorders: Vec<JoinHandleTerminator<()>>,
+    controllers: Vec<JoinHandleTerminator<ControllerResult>>,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self {
+            event_registry: EventRegistry::new(None),
+            start: Instant::now(),
+            outputs: Default::default(),
+            recorders: Vec::new(),
+            controllers: Vec::new(),
+        }
+    }
+    pub fn event_registry(&self) -> &EventRegistry {
+        &self.event_registry
+    }
+    pub async fn start(&mut self, config: ControllerConfig) {
+        let (state, _) = watch::channel(ControllerState::default());
+        let handle = config.start(&self.event_registry, PROTECTION, state).await;
+        self.controllers.push(JoinHandleTerminator::new(handle));
+    }
+
+    pub async fn record_switch(&mut self, key: SwitchOutputKey) {
+        let mut stream = Box::pin(self.event_registry.switch_stream(key).await);
+        // only changes after the current value are recorded
+        stream.next().await;
+        self.record(stream, move |on| Output::Switch(key, on));
+    }
+    pub async fn record_brightness(&mut self, key: BrightnessKey) {
+        let mut stream = Box::pin(self.event_registry.brightness_stream(key).await);
+        stream.next().await;
+        self.record(stream, move |brightness| {
+            Output::Brightness(key, brightness.0)
+        });
+    }
+    pub async fn record_window_open(&mut self, key: WindowOpenKey) {
+        let mut stream = Box::pin(self.event_registry.window_open_stream(key).await);
+        stream.next().await;
+        self.record(stream, move |open| Output::WindowOpen(key, open));
+    }
+    pub async fn record_shutter_position(&mut self, key: ShutterPositionKey) {
+        let mut stream = Box::pin(self.event_registry.shutter_position_stream(key).await);
+        stream.next().await;
+        self.record(stream, move |position| {
+            Output::ShutterPosition(key, position)
+        });
+    }
+    pub async fn record_ring_events(&mut self) {
+        let stream = Box::pin(self.event_registry.ring_event_stream().await);
+        self.record(stream, |event| Output::Ring {
+            button: event.button,
+            quiet: event.quiet,
+        });
+    }
+    fn record<T>(
+        &mut self,
+        mut stream: impl Stream<Item = T> + Unpin + Send + 'static,
+        output: impl Fn(T) -> Output + Send + 'static,
+    ) {
+        let outputs = self.outputs.clone();
+        let start = self.start;
+        self.recorders.push(
+            tokio::spawn(async move {
+                while let Some(value) = stream.next().await {
+                    outputs
+                        .lock()
+                        .expect("Outputs lock poisoned")
+                        .push((start.elapsed(), output(value)));
+                }
+            })
+            .into(),
+        );
+    }
+
+    /// Runs all timers up to the given offset from the start
+    pub async fn advance_to(&self, at: Duration) {
+        sleep_until(self.start + at).await;
+    }
+    pub async fn single_button(
+        &self,
+        key: SingleButtonKey,
+        state: ButtonState<SingleButtonLayout>,
+    ) {
+        self.event_registry
+            .single_button_sender(key)
+            .await
+            .send(state)
+            .await
+            .expect("Button register closed");
+    }
+    /// Short press at `at`, released after `duration`
+    pub async fn press(&self, key: SingleButtonKey, at: Duration, duration: Duration) {
+        self.advance_to(at).await;
+        self.single_button(key, ButtonState::ShortPressStart(SingleButtonLayout))
+            .await;
+        self.advance_to(at + duration).await;
+        self.single_button(key, ButtonState::Released).await;
+    }
+    /// Long press at `at`, the long press starts after `long_press`, released after `duration`
+    pub async fn long_press(
+        &self,
+        key: SingleButtonKey,
+        at: Duration,
+        long_press: Duration,
+        duration: Duration,
+    ) {
+        self.advance_to(at).await;
+        self.single_button(key, ButtonState::ShortPressStart(SingleButtonLayout))
+            .await;
+        self.advance_to(at + long_press).await;
+        self.single_button(key, ButtonState::LongPressStart(SingleButtonLayout))
+            .await;
+        self.advance_to(at + duration).await;
+        self.single_button(key, ButtonState::Released).await;
+    }
+    /// Double click starting at `at`, both clicks take 100ms with 100ms between them
+    pub async fn double_click(&self, key: SingleButtonKey, at: Duration) {
+        self.press(key, at, Duration::from_millis(100)).await;
+        self.advance_to(at + Duration::from_millis(200)).await;
+        self.single_button(key, ButtonState::DoubleClickStart(SingleButtonLayout))
+            .await;
+        self.advance_to(at + Duration::from_millis(300)).await;
+        self.single_button(key, ButtonState::Released).await;
+    }
+    pub async fn dual_button(&self, key: DualButtonKey, state: ButtonState<DualButtonLayout>) {
+        self.event_registry
+            .dual_button_sender(key)
+            .await
+            .send(state)
+            .await
+            .expect("Button register closed");
+    }
+    /// Short press of one side at `at`, released after `duration`
+    pub async fn dual_press(
+        &self,
+        key: DualButtonKey,
+        button: DualButtonLayout,
+        at: Duration,
+        duration: Duration,
+    ) {
+        self.advance_to(at).await;
+        self.dual_button(key, ButtonState::ShortPressStart(button))
+            .await;
+        self.advance_to(at + duration).await;
+        self.dual_button(key, ButtonState::Released).await;
+    }
+    pub async fn temperature(&self, key: TemperatureKey, value: f32) {
+        self.event_registry
+            .temperature_sender(key)
+            .await
+            .send(value)
+            .await
+            .expect("Temperature register closed");
+    }
+
+    /// Recorded changes so far, ordered by time and by output at the same instant
+    pub fn outputs(&self) -> Vec<(Duration, Output)> {
+        let mut outputs = self.outputs.lock().expect("Outputs lock poisoned").clone();
+        outputs.sort();
+        outputs
+    }
+}
+
+mod test {
+    use std::{num::Saturating, time::Duration};
+
+    use crate::{
+        controller::{
+            manager::ControllerConfig,
+            simulation::{Output, Simulation},
+        },
+        data::{
+            registry::{
+                BrightnessKey, ButtonState, DualButtonKey, DualButtonLayout, HouseMode,
+                IlluminanceKey, LightGroupKey, SceneCommand, SceneValue, SceneValues,
+                ShutterPositionKey, SingleButtonKey, SingleButtonLayout, SwitchOutputKey,
+                TemperatureKey, WindowOpenKey,
+            },
+            wiring::{
+                default_dimm_duration, AllOffController, ConstantLight, DualInputDimmer,
+                HeatController, LightGroup, MotionDetector, PreWarning, RingController, Scene,
+                SceneButton, Shutter, SingleInputDimmer, SingleInputSwitch, WindowOpenDetection,
+            },
+            DeviceInRoom, Room, SubDeviceInRoom,
+        },
+    };
+
+    const ROOM: Room = Room { floor: 1, room: 2 };
+
+    fn device(idx: u16) -> DeviceInRoom {
+        DeviceInRoom { room: ROOM, idx }
+    }
+    fn button(idx: u16) -> SingleButtonKey {
+        SingleButtonKey::Button(SubDeviceInRoom {
+            room: ROOM,
+            device_idx: idx,
+            sub_device_idx: 0,
+        })
+    }
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dual_input_dimmer() {
+        let input = DualButtonKey(SubDeviceInRoom {
+            room: ROOM,
+            device_idx: 0,
+            sub_device_idx: 0,
+        });
+        let light = BrightnessKey::Light(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_brightness(light).await;
+        simulation
+            .start(ControllerConfig::DualInputDimmer(DualInputDimmer {
+                input: Box::new([input]),
+                output: light,
+                auto_switch_off_time: secs(60),
+                presence: Box::new([]),
+                dimm_duration: default_dimm_duration(),
+                pre_warning: None,
+                schedule: None,
+            }))
+            .await;
+
+        simulation
+            .dual_button(input, ButtonState::ShortPressStart(DualButtonLayout::Up))
+            .await;
+        simulation.advance_to(ms(100)).await;
+        simulation.dual_button(input, ButtonState::Released).await;
+        simulation.advance_to(secs(1)).await;
+        // one step every 10 ms
+        simulation
+            .dual_button(input, ButtonState::LongPressStart(DualButtonLayout::Down))
+            .await;
+        simulation.advance_to(ms(1035)).await;
+        simulation.dual_button(input, ButtonState::Released).await;
+        simulation.advance_to(secs(120)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (ms(100), Output::Brightness(light, 255)),
+                (ms(1010), Output::Brightness(light, 254)),
+                (ms(1020), Output::Brightness(light, 253)),
+                (ms(1030), Output::Brightness(light, 252)),
+                (ms(61035), Output::Brightness(light, 0)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dual_input_dimmer_pre_warning() {
+        let input = DualButtonKey(SubDeviceInRoom {
+            room: ROOM,
+            device_idx: 0,
+            sub_device_idx: 0,
+        });
+        let light = BrightnessKey::Light(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_brightness(light).await;
+        simulation
+            .start(ControllerConfig::DualInputDimmer(DualInputDimmer {
+                input: Box::new([input]),
+                output: light,
+                auto_switch_off_time: secs(60),
+                presence: Box::new([]),
+                dimm_duration: default_dimm_duration(),
+                pre_warning: Some(PreWarning {
+                    lead_time: secs(10),
+                    brightness: Some(50),
+                    flash_time: ms(500),
+                }),
+                schedule: None,
+            }))
+            .await;
+        simulation
+            .dual_press(input, DualButtonLayout::Up, secs(0), ms(100))
+            .await;
+        // a press during the warning extends the timer
+        simulation
+            .dual_press(input, DualButtonLayout::Up, secs(52), ms(100))
+            .await;
+        // a release without a press does not
+        simulation.advance_to(secs(105)).await;
+        simulation.dual_button(input, ButtonState::Released).await;
+        simulation.advance_to(secs(120)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (ms(100), Output::Brightness(light, 255)),
+                (ms(50100), Output::Brightness(light, 50)),
+                (ms(52100), Output::Brightness(light, 255)),
+                (ms(102100), Output::Brightness(light, 50)),
+                (ms(112100), Output::Brightness(light, 0)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_input_dimmer() {
+        let input = button(0);
+        let light = BrightnessKey::Light(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_brightness(light).await;
+        simulation
+            .start(ControllerConfig::SingleInputDimmer(SingleInputDimmer {
+                input: Box::new([input]),
+                output: light,
+                auto_switch_off_time: secs(60),
+                presence: Box::new([]),
+                dimm_duration: default_dimm_duration(),
+                pre_warning: None,
+            }))
+            .await;
+
+        simulation
+            .single_button(input, ButtonState::ShortPressStart(SingleButtonLayout))
+            .await;
+        simulation.advance_to(ms(500)).await;
+        simulation
+            .single_button(input, ButtonState::LongPressStart(SingleButtonLayout))
+            .await;
+        simulation.advance_to(ms(535)).await;
+        simulation.single_button(input, ButtonState::Released).await;
+        // toggles off and back on to the dimmed brightness
+        simulation.press(input, secs(1), ms(100)).await;
+        simulation.press(input, secs(2), ms(100)).await;
+        simulation.advance_to(secs(120)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (ms(510), Output::Brightness(light, 1)),
+                (ms(520), Output::Brightness(light, 2)),
+                (ms(530), Output::Brightness(light, 3)),
+                (ms(1100), Output::Brightness(light, 0)),
+                (ms(2100), Output::Brightness(light, 3)),
+                (ms(62100), Output::Brightness(light, 0)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_input_switch() {
+        let input = button(0);
+        let light = SwitchOutputKey::Light(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_switch(light).await;
+        simulation
+            .start(ControllerConfig::SingleInputSwitch(SingleInputSwitch {
+                input: Box::new([input]),
+                output: light,
+                auto_switch_off_time: secs(60),
+                presence: Box::new([]),
+                pre_warning: None,
+            }))
+            .await;
+
+        simulation.press(input, secs(0), ms(100)).await;
+        simulation.press(input, secs(10), ms(100)).await;
+        simulation.press(input, secs(20), ms(100)).await;
+        simulation.advance_to(secs(120)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (secs(0), Output::Switch(light, true)),
+                (secs(10), Output::Switch(light, false)),
+                (secs(20), Output::Switch(light, true)),
+                (secs(80), Output::Switch(light, false)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_input_switch_double_click() {
+        let input = button(0);
+        let light = SwitchOutputKey::Light(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_switch(light).await;
+        simulation
+            .start(ControllerConfig::SingleInputSwitch(SingleInputSwitch {
+                input: Box::new([input]),
+                output: light,
+                auto_switch_off_time: secs(60),
+                presence: Box::new([]),
+                pre_warning: None,
+            }))
+            .await;
+
+        // toggles once instead of switching straight back off
+        simulation.double_click(input, secs(0)).await;
+        simulation.double_click(input, secs(10)).await;
+        simulation.advance_to(secs(20)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (secs(0), Output::Switch(light, true)),
+                (secs(10), Output::Switch(light, false)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_motion_detector() {
+        let input = SingleButtonKey::MotionDetector(device(0));
+        let light = SwitchOutputKey::Light(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_switch(light).await;
+        simulation
+            .start(ControllerConfig::MotionDetector(MotionDetector::Switch {
+                input: Box::new([input]),
+                output: light,
+                switch_off_time: secs(60),
+                pre_warning: Some(PreWarning {
+                    lead_time: secs(10),
+                    brightness: None,
+                    flash_time: secs(1),
+                }),
+                schedule: None,
+                daylight: None,
+            }))
+            .await;
+
+        simulation.press(input, secs(0), secs(1)).await;
+        // restarts the switch off timer
+        simulation.press(input, secs(30), secs(1)).await;
+        simulation.advance_to(secs(100)).await;
+        simulation
+            .event_registry()
+            .house_mode_sender()
+            .await
+            .send(HouseMode::Away)
+            .await
+            .unwrap();
+        // nobody at home, no light
+        simulation.press(input, secs(110), secs(1)).await;
+        simulation.advance_to(secs(300)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (secs(0), Output::Switch(light, true)),
+                (secs(80), Output::Switch(light, false)),
+                (secs(81), Output::Switch(light, true)),
+                (secs(90), Output::Switch(light, false)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_constant_light_without_reading() {
+        let presence = SingleButtonKey::MotionDetector(device(0));
+        let light = BrightnessKey::Light(device(1));
+        let sensor = IlluminanceKey(device(2));
+        let mut simulation = Simulation::new();
+        simulation.record_brightness(light).await;
+        simulation
+            .start(ControllerConfig::ConstantLight(ConstantLight {
+                sensor,
+                output: light,
+                presence: Box::new([presence]),
+                target_lux: 300,
+                min_brightness: 10,
+                max_brightness: 200,
+                vacancy_time: secs(60),
+                ramp_step_time: ms(100),
+            }))
+            .await;
+
+        // the sensor did not report yet, the light starts at the maximum
+        simulation.press(presence, secs(0), ms(100)).await;
+        simulation.advance_to(secs(10)).await;
+        simulation
+            .event_registry()
+            .illuminance_sender(sensor)
+            .await
+            .send(3000)
+            .await
+            .unwrap();
+        simulation.advance_to(secs(120)).await;
+
+        let outputs = simulation.outputs();
+        assert_eq!((secs(0), Output::Brightness(light, 200)), outputs[0]);
+        // the first reading starts the regulation
+        assert_eq!((ms(10100), Output::Brightness(light, 199)), outputs[1]);
+        assert_eq!((ms(11600), Output::Brightness(light, 184)), outputs[16]);
+        assert_eq!(
+            Some(&(secs(60), Output::Brightness(light, 0))),
+            outputs.last()
+        );
+        assert_eq!(18, outputs.len());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heat_controller() {
+        let current = TemperatureKey::CurrentTemperature(device(0));
+        let target = TemperatureKey::TargetTemperature(device(0));
+        let heat = SwitchOutputKey::Heat(device(1));
+        let window = WindowOpenKey(device(2));
+        let contact = button(3);
+        let minutes = |minutes: u64| secs(minutes * 60);
+        let mut simulation = Simulation::new();
+        simulation.record_switch(heat).await;
+        simulation.record_window_open(window).await;
+        simulation
+            .start(ControllerConfig::HeatController(HeatController {
+                current_value_input: current,
+                target_value_input: target,
+                output: heat,
+                window_open_detection: Some(WindowOpenDetection {
+                    window_contacts: Box::new([contact]),
+                    temperature_drop: None,
+                    drop_interval: minutes(3),
+                    suspend_time: minutes(15),
+                    state_output: window,
+                }),
+            }))
+            .await;
+
+        // both temperatures start at 21°C
+        simulation.temperature(target, 22.0).await;
+        simulation.advance_to(minutes(10)).await;
+        simulation.temperature(current, 22.5).await;
+        simulation.advance_to(minutes(20)).await;
+        simulation.temperature(current, 21.0).await;
+        // closing the window resumes heating
+        simulation.press(contact, minutes(30), minutes(5)).await;
+        // the suspend time ends before the window is closed
+        simulation.press(contact, minutes(40), minutes(20)).await;
+        simulation.advance_to(minutes(70)).await;
+        // the eco temperature is below the current one
+        simulation
+            .event_registry()
+            .house_mode_sender()
+            .await
+            .send(HouseMode::Away)
+            .await
+            .unwrap();
+        simulation.advance_to(minutes(80)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (minutes(0), Output::Switch(heat, true)),
+                (minutes(10), Output::Switch(heat, false)),
+                (minutes(20), Output::Switch(heat, true)),
+                (minutes(30), Output::Switch(heat, false)),
+                (minutes(30), Output::WindowOpen(window, true)),
+                (minutes(35), Output::Switch(heat, true)),
+                (minutes(35), Output::WindowOpen(window, false)),
+                (minutes(40), Output::Switch(heat, false)),
+                (minutes(40), Output::WindowOpen(window, true)),
+                (minutes(55), Output::Switch(heat, true)),
+                (minutes(55), Output::WindowOpen(window, false)),
+                (minutes(70), Output::Switch(heat, false)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_all_off_controller() {
+        let input = button(0);
+        let dimmer = BrightnessKey::Light(device(1));
+        let switch = SwitchOutputKey::Light(device(2));
+        let excepted = SwitchOutputKey::Light(device(3));
+        let mut simulation = Simulation::new();
+        // the controller starts before any other user of the light registers
+        simulation
+            .start(ControllerConfig::AllOffController(AllOffController {
+                input,
+                group: LightGroup {
+                    key: LightGroupKey::Room(ROOM),
+                    brightness: Box::new([dimmer]),
+                    switches: Box::new([switch, excepted]),
+                },
+                exceptions: Box::new([device(3)]),
+            }))
+            .await;
+        simulation.record_brightness(dimmer).await;
+        simulation.record_switch(switch).await;
+        simulation.record_switch(excepted).await;
+        let registry = simulation.event_registry();
+        registry
+            .brightness_sender(dimmer)
+            .await
+            .send(Saturating(80))
+            .await
+            .unwrap();
+        registry
+            .switch_sender(switch)
+            .await
+            .send(true)
+            .await
+            .unwrap();
+        registry
+            .switch_sender(excepted)
+            .await
+            .send(true)
+            .await
+            .unwrap();
+
+        simulation.press(input, secs(10), ms(100)).await;
+        simulation.advance_to(secs(20)).await;
+
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (secs(0), Output::Switch(switch, true)),
+                (secs(0), Output::Switch(excepted, true)),
+                (secs(0), Output::Brightness(dimmer, 80)),
+                (secs(10), Output::Switch(switch, false)),
+                (secs(10), Output::Brightness(dimmer, 0)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scene_recall() {
+        let dimmer = BrightnessKey::Light(device(1));
+        let on = SwitchOutputKey::Light(device(2));
+        let off = SwitchOutputKey::Light(device(3));
+        let mut simulation = Simulation::new();
+        simulation.record_brightness(dimmer).await;
+        simulation.record_switch(on).await;
+        simulation.record_switch(off).await;
+        let registry = simulation.event_registry();
+        registry.switch_sender(off).await.send(true).await.unwrap();
+        simulation
+            .start(ControllerConfig::Scenes(Box::new([Scene {
+                name: "Abend".into(),
+                transition: ms(200),
+                values: SceneValues {
+                    brightness: Box::new([SceneValue {
+                        key: dimmer,
+                        value: 200,
+                    }]),
+                    light_colors: Box::new([]),
+                    switches: Box::new([
+                        SceneValue {
+                            key: on,
+                            value: true,
+                        },
+                        SceneValue {
+                            key: off,
+                            value: false,
+                        },
+                    ]),
+                },
+            }])))
+            .await;
+
+        simulation.advance_to(secs(1)).await;
+        let commands = simulation.event_registry().scene_command_sender().await;
+        commands
+            .send(SceneCommand::Recall {
+                scene: "Abend".into(),
+                transition: None,
+            })
+            .unwrap();
+        simulation.advance_to(secs(2)).await;
+
+        // the light switches on with the first and off with the last step of the transition
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (secs(0), Output::Switch(off, true)),
+                (ms(1050), Output::Switch(on, true)),
+                (ms(1050), Output::Brightness(dimmer, 50)),
+                (ms(1100), Output::Brightness(dimmer, 100)),
+                (ms(1150), Output::Brightness(dimmer, 150)),
+                (ms(1200), Output::Switch(off, false)),
+                (ms(1200), Output::Brightness(dimmer, 200)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scene_capture() {
+        let input = button(0);
+        let dimmer = BrightnessKey::Light(device(1));
+        let switch = SwitchOutputKey::Light(device(2));
+        let mut simulation = Simulation::new();
+        simulation.record_brightness(dimmer).await;
+        simulation.record_switch(switch).await;
+        simulation
+            .start(ControllerConfig::Scenes(Box::new([])))
+            .await;
+        simulation
+            .start(ControllerConfig::SceneButton(SceneButton {
+                input,
+                scene: "Lesen".into(),
+                capture: Some(LightGroupKey::Room(ROOM)),
+            }))
+            .await;
+        let registry = simulation.event_registry();
+        let brightness = registry.brightness_sender(dimmer).await;
+        let switch_sender = registry.switch_sender(switch).await;
+        brightness.send(Saturating(120)).await.unwrap();
+        switch_sender.send(true).await.unwrap();
+
+        simulation
+            .long_press(input, secs(1), ms(500), secs(1))
+            .await;
+        simulation.advance_to(secs(5)).await;
+        brightness.send(Saturating(10)).await.unwrap();
+        switch_sender.send(false).await.unwrap();
+        simulation.press(input, secs(10), ms(100)).await;
+        simulation.advance_to(secs(20)).await;
+
+        // the long press captures without recalling, the short press recalls on release
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (secs(0), Output::Switch(switch, true)),
+                (secs(0), Output::Brightness(dimmer, 120)),
+                (secs(5), Output::Switch(switch, false)),
+                (secs(5), Output::Brightness(dimmer, 10)),
+                (ms(10100), Output::Switch(switch, true)),
+                (ms(10100), Output::Brightness(dimmer, 120)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutter_reversal() {
+        let input = DualButtonKey(SubDeviceInRoom {
+            room: ROOM,
+            device_idx: 0,
+            sub_device_idx: 0,
+        });
+        let up = SwitchOutputKey::ShutterUp(device(1));
+        let down = SwitchOutputKey::ShutterDown(device(1));
+        let position = ShutterPositionKey(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_switch(up).await;
+        simulation.record_switch(down).await;
+        simulation.record_shutter_position(position).await;
+        simulation
+            .start(ControllerConfig::Shutter(Shutter {
+                input: Box::new([input]),
+                up,
+                down,
+                position,
+                travel_time: secs(10),
+            }))
+            .await;
+
+        simulation
+            .dual_press(input, DualButtonLayout::Down, secs(0), ms(100))
+            .await;
+        // reverses shortly before the end, the timer of the first travel fires during the pause
+        simulation.advance_to(ms(9800)).await;
+        simulation
+            .dual_button(input, ButtonState::ShortPressStart(DualButtonLayout::Up))
+            .await;
+        simulation
+            .dual_button(input, ButtonState::LongPressStart(DualButtonLayout::Up))
+            .await;
+        simulation.advance_to(ms(13500)).await;
+        simulation.dual_button(input, ButtonState::Released).await;
+        simulation.advance_to(secs(30)).await;
+
+        let positions = (1..=9).map(|s| (secs(s), Output::ShutterPosition(position, s as u8 * 10)));
+        // the controller switches both relays off at the start
+        let expected = [
+            (secs(0), Output::Switch(up, false)),
+            (secs(0), Output::Switch(down, true)),
+        ]
+        .into_iter()
+        .chain(positions)
+        .chain([
+            (ms(9800), Output::Switch(down, false)),
+            (ms(9800), Output::ShutterPosition(position, 98)),
+            (ms(10300), Output::Switch(up, true)),
+            (ms(11300), Output::ShutterPosition(position, 88)),
+            (ms(12300), Output::ShutterPosition(position, 78)),
+            (ms(13300), Output::ShutterPosition(position, 68)),
+            (ms(13500), Output::Switch(up, false)),
+            (ms(13500), Output::ShutterPosition(position, 66)),
+        ])
+        .collect::<Vec<_>>();
+        assert_eq!(simulation.outputs(), expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ring_controller() {
+        let input = button(0);
+        let bell = SwitchOutputKey::Bell(device(1));
+        let mut simulation = Simulation::new();
+        simulation.record_switch(bell).await;
+        simulation.record_ring_events().await;
+        simulation
+            .start(ControllerConfig::RingController(RingController {
+                input,
+                output: bell,
+                pattern: Box::new([ms(500), ms(200), ms(500)]),
+                debounce: secs(5),
+                quiet_hours: None,
+            }))
+            .await;
+
+        simulation.press(input, secs(0), ms(100)).await;
+        // within the debounce time
+        simulation.press(input, secs(3), ms(100)).await;
+        simulation.press(input, secs(6), ms(100)).await;
+        simulation.advance_to(secs(10)).await;
+
+        let ring = Output::Ring {
+            button: input,
+            quiet: false,
+        };
+        assert_eq!(
+            simulation.outputs(),
+            [
+                (ms(0), Output::Switch(bell, true)),
+                (ms(0), ring),
+                (ms(500), Output::Switch(bell, false)),
+                (ms(700), Output::Switch(bell, true)),
+                (ms(1200), Output::Switch(bell, false)),
+                (ms(6000), Output::Switch(bell, true)),
+                (ms(6000), ring),
+                (ms(6500), Output::Switch(bell, false)),
+                (ms(6700), Output::Switch(bell, true)),
+                (ms(7200), Output::Switch(bell, false)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use log::{error, info};
+use tokio::{
+    select,
+    sync::mpsc::{error::SendError, Sender},
+    time::{sleep_until, Instant},
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::controller::manager::ControllerHandle;
+use crate::data::{registry::EventRegistry, wiring::VentilationFan};
+
+pub async fn ventilation_fan(
+    event_registry: &EventRegistry,
+    settings: &VentilationFan,
+) -> ControllerHandle {
+    // the register reads zero until the sensor reported
+    let humidity = event_registry
+        .humidity_stream(settings.sensor)
+        .await
+        .filter(|humidity| *humidity > 0.0);
+    let output = event_registry.switch_sender(settings.output).await;
+    let fan = FanState::new(settings);
+    let room = settings.sensor.0.room;
+    tokio::spawn(async move {
+        if let Err(error) = ventilation_task(Box::pin(humidity), output, fan).await {
+            error!("Failed ventilation fan in {room}: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+async fn ventilation_task(
+    mut humidity: impl Stream<Item = f32> + Unpin,
+    output: Sender<bool>,
+    mut fan: FanState,
+) -> Result<(), SendError<bool>> {
+    let mut running = false;
+    output.send(running).await?;
+    loop {
+        let deadline = fan.next_deadline();
+        let reading = select! {
+            reading = humidity.next() => match reading {
+                Some(reading) => Some(reading),
+                None => break,
+            },
+            _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => None,
+        };
+        let now = Instant::now();
+        if let Some(reading) = reading {
+            fan.update_humidity(reading, now);
+        }
+        let run = fan.evaluate(now);
+        if run != running {
+            info!(
+                "Ventilation fan {} at {reading:?} %RH",
+                if run { "on" } else { "off" }
+            );
+            running = run;
+            output.send(running).await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct FanState {
+    rise: f32,
+    baseline_time: Duration,
+    run_on: Duration,
+    max_runtime: Duration,
+    /// humidity the rise is measured from, follows the readings while the fan is off
+    baseline: Option<(f32, Instant)>,
+    humid: bool,
+    running_since: Option<Instant>,
+    run_on_until: Option<Instant>,
+    /// the max runtime was reached, the fan waits until the humidity dropped
+    locked: bool,
+}
+
+impl FanState {
+    fn new(settings: &VentilationFan) -> Self {
+        Self {
+            rise: settings.rise as f32 / 10.0,
+            baseline_time: settings.baseline_time,
+            run_on: settings.run_on,
+            max_runtime: settings.max_runtime,
+            baseline: None,
+            humid: false,
+            running_since: None,
+            run_on_until: None,
+            locked: false,
+        }
+    }
+    fn update_humidity(&mut self, humidity: f32, now: Instant) {
+        let Some((baseline, updated)) = self.baseline else {
+            self.baseline = Some((humidity, now));
+            return;
+        };
+        if humidity >= baseline + self.rise {
+            self.humid = true;
+        } else if humidity < baseline + self.rise / 2.0 {
+            self.humid = false;
+        }
+        if self.humid || self.running_since.is_some() {
+            // keep the baseline from before the shower while the fan removes the humidity
+            self.baseline = Some((baseline, now));
+        } else {
+            let weight = (now.duration_since(updated).as_secs_f32()
+                / self.baseline_time.as_secs_f32().max(1.0))
+            .min(1.0);
+            self.baseline = Some((baseline + (humidity - baseline) * weight, now));
+        }
+    }
+    /// Whether the fan should run at `now`
+    fn evaluate(&mut self, now: Instant) -> bool {
+        if !self.humid {
+            self.locked = false;
+        }
+        let Some(since) = self.running_since else {
+            if self.humid && !self.locked {
+                self.running_since = Some(now);
+                return true;
+            }
+            return false;
+        };
+        if now >= since + self.max_runtime {
+            self.stop();
+            self.locked = self.humid;
+            return false;
+        }
+        if self.humid {
+            self.run_on_until = None;
+            return true;
+        }
+        let until = *self.run_on_until.get_or_insert(now + self.run_on);
+        if now >= until {
+            self.stop();
+            false
+        } else {
+            true
+        }
+    }
+    fn stop(&mut self) {
+        self.running_since = None;
+        self.run_on_until = None;
+    }
+    /// Next time the fan may switch off without a new reading
+    fn next_deadline(&self) -> Option<Instant> {
+        let max_runtime = self.running_since? + self.max_runtime;
+        Some(
+            self.run_on_until
+                .map_or(max_runtime, |until| until.min(max_runtime)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::{
+        controller::ventilation::FanState,
+        data::{
+            registry::{HumidityKey, SwitchOutputKey},
+            wiring::VentilationFan,
+        },
+    };
+
+    #[test]
+    fn test_fan_state() {
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+        let mut fan = FanState::new(&VentilationFan {
+            sensor: HumidityKey(Default::default()),
+            output: SwitchOutputKey::Fan(Default::default()),
+            rise: 100,
+            baseline_time: minutes(60),
+            run_on: minutes(10),
+            max_runtime: minutes(60),
+        });
+        let start = Instant::now();
+        let at = |m| start + minutes(m);
+
+        fan.update_humidity(50.0, at(0));
+        assert!(!fan.evaluate(at(0)));
+        fan.update_humidity(59.0, at(1));
+        assert!(!fan.evaluate(at(1)));
+        // shower
+        fan.update_humidity(70.0, at(2));
+        assert!(fan.evaluate(at(2)));
+        assert_eq!(Some(at(62)), fan.next_deadline());
+        // still above half the rise
+        fan.update_humidity(56.0, at(20));
+        assert!(fan.evaluate(at(20)));
+        // dropped, run on
+        fan.update_humidity(54.0, at(30));
+        assert!(fan.evaluate(at(30)));
+        assert_eq!(Some(at(40)), fan.next_deadline());
+        assert!(!fan.evaluate(at(40)));
+        assert_eq!(None, fan.next_deadline());
+
+        // max runtime, waits for the humidity to drop
+        fan.update_humidity(70.0, at(100));
+        assert!(fan.evaluate(at(100)));
+        assert!(!fan.evaluate(at(160)));
+        fan.update_humidity(70.0, at(161));
+        assert!(!fan.evaluate(at(161)));
+        fan.update_humidity(53.0, at(170));
+        assert!(!fan.evaluate(at(170)));
+        fan.update_humidity(70.0, at(171));
+        assert!(fan.evaluate(at(171)));
+    }
+}
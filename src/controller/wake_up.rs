@@ -0,0 +1,261 @@
+use std::{num::Saturating, time::Duration};
+
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use futures::stream::SelectAll;
+use log::{error, info};
+use thiserror::Error;
+use tokio::sync::mpsc::{error::SendError, Sender};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::controller::manager::ControllerHandle;
+use crate::data::{
+    registry::{ButtonState, EventRegistry},
+    wiring::{WakeUpLight, WakeUpOutput, Weekdays},
+};
+
+pub async fn wake_up_light(
+    event_registry: &EventRegistry,
+    settings: &WakeUpLight,
+) -> ControllerHandle {
+    let mut button_streams = SelectAll::new();
+    for key in settings.buttons.iter() {
+        button_streams.push(
+            event_registry
+                .single_button_stream(*key)
+                .await
+                .map(|state| state != ButtonState::Released),
+        );
+    }
+    let mut dual_button_streams = SelectAll::new();
+    for key in settings.dual_buttons.iter() {
+        dual_button_streams.push(
+            event_registry
+                .dual_button_stream(*key)
+                .await
+                .map(|state| state != ButtonState::Released),
+        );
+    }
+    let input_stream = event_registry
+        .clock(settings.clock)
+        .await
+        .map(WakeUpMessage::Tick)
+        .merge(
+            event_registry
+                .alarm_time_stream(settings.alarm)
+                .await
+                .map(WakeUpMessage::AlarmTime),
+        )
+        .merge(
+            button_streams
+                .merge(dual_button_streams)
+                .filter(|pressed| *pressed)
+                .map(|_| WakeUpMessage::ButtonPressed),
+        );
+    let mut outputs = Vec::with_capacity(settings.outputs.len());
+    for output in settings.outputs.iter() {
+        outputs.push(WakeUpSenders {
+            settings: *output,
+            brightness: event_registry.brightness_sender(output.brightness).await,
+            whitebalance: event_registry.light_color_sender(output.whitebalance).await,
+            sent: None,
+        });
+    }
+    let ramp = WakeUpRamp {
+        brightness: settings.brightness,
+        duration: settings.duration,
+        weekdays: settings.weekdays,
+    };
+    let room = settings.alarm.0;
+    tokio::spawn(async move {
+        if let Err(error) = wake_up_task(input_stream, outputs, ramp).await {
+            error!("Failed wake-up light in {room}: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+#[derive(Error, Debug)]
+enum WakeUpError {
+    #[error("Cannot send brightness: {0}")]
+    Brightness(#[from] SendError<Saturating<u8>>),
+    #[error("Cannot send whitebalance: {0}")]
+    Whitebalance(#[from] SendError<Saturating<u16>>),
+}
+
+enum WakeUpMessage {
+    Tick(DateTime<Tz>),
+    AlarmTime(Option<NaiveTime>),
+    ButtonPressed,
+}
+
+struct WakeUpSenders {
+    settings: WakeUpOutput,
+    brightness: Sender<Saturating<u8>>,
+    whitebalance: Sender<Saturating<u16>>,
+    /// brightness and mireds sent during the current ramp
+    sent: Option<(u8, u16)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WakeUpRamp {
+    brightness: u8,
+    duration: Duration,
+    weekdays: Weekdays,
+}
+
+async fn wake_up_task(
+    mut input: impl Stream<Item = WakeUpMessage> + Unpin,
+    mut outputs: Vec<WakeUpSenders>,
+    ramp: WakeUpRamp,
+) -> Result<(), WakeUpError> {
+    let mut alarm_time = None;
+    // alarm of the ramp currently running and of the last one cancelled by a button
+    let mut running = None;
+    let mut cancelled = None;
+    while let Some(event) = input.next().await {
+        match event {
+            WakeUpMessage::Tick(now) => {
+                let progress = alarm_time
+                    .and_then(|alarm_time| ramp.progress(now, alarm_time))
+                    .filter(|(alarm, _)| cancelled != Some(*alarm));
+                let Some((alarm, progress)) = progress else {
+                    if running.take().is_some() {
+                        info!("Wake-up light done");
+                        for output in outputs.iter_mut() {
+                            output.sent = None;
+                        }
+                    }
+                    continue;
+                };
+                if running != Some(alarm) {
+                    info!("Start wake-up light for {alarm}");
+                    running = Some(alarm);
+                }
+                for output in outputs.iter_mut() {
+                    let values = ramp.values_at(&output.settings, progress);
+                    if output.sent == Some(values) {
+                        continue;
+                    }
+                    let (brightness, mireds) = values;
+                    if output.sent.map(|(_, sent)| sent) != Some(mireds) {
+                        output.whitebalance.send(Saturating(mireds)).await?;
+                    }
+                    if output.sent.map(|(sent, _)| sent) != Some(brightness) {
+                        output.brightness.send(Saturating(brightness)).await?;
+                    }
+                    output.sent = Some(values);
+                }
+            }
+            WakeUpMessage::AlarmTime(time) => alarm_time = time,
+            WakeUpMessage::ButtonPressed => {
+                if let Some(alarm) = running.take() {
+                    info!("Wake-up light for {alarm} cancelled by a button");
+                    cancelled = Some(alarm);
+                    for output in outputs.iter_mut() {
+                        output.sent = None;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl WakeUpRamp {
+    /// Alarm of the ramp running at `now` and its progress between 0 and 1, ramps may start on
+    /// the day before the alarm
+    fn progress(&self, now: DateTime<Tz>, alarm_time: NaiveTime) -> Option<(DateTime<Tz>, f32)> {
+        let duration = chrono::Duration::from_std(self.duration).ok()?;
+        let today = now.date_naive();
+        [Some(today), today.succ_opt()]
+            .into_iter()
+            .flatten()
+            .filter(|date| self.weekdays.contains(date.weekday()))
+            .find_map(|date| {
+                let alarm = now
+                    .timezone()
+                    .from_local_datetime(&date.and_time(alarm_time))
+                    .earliest()?;
+                let start = alarm - duration;
+                (start <= now && now <= alarm).then(|| {
+                    let total = duration.num_milliseconds().max(1) as f32;
+                    (alarm, (now - start).num_milliseconds() as f32 / total)
+                })
+            })
+    }
+    /// Brightness and mireds of an output at the progress of the ramp
+    fn values_at(&self, output: &WakeUpOutput, progress: f32) -> (u8, u16) {
+        let progress = progress.clamp(0.0, 1.0);
+        let brightness = (self.brightness as f32 * progress).round() as u8;
+        let mireds = output.warm_mireds as f32
+            + (output.cold_mireds as f32 - output.warm_mireds as f32) * progress;
+        (brightness, mireds.round() as u16)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Weekday};
+    use chrono_tz::Tz;
+
+    use crate::{
+        controller::wake_up::WakeUpRamp,
+        data::{
+            registry::{BrightnessKey, LightColorKey},
+            wiring::WakeUpOutput,
+        },
+    };
+
+    #[test]
+    fn test_ramp() {
+        let ramp = WakeUpRamp {
+            brightness: 200,
+            duration: Duration::from_secs(30 * 60),
+            weekdays: [Weekday::Mon, Weekday::Tue].into_iter().collect(),
+        };
+        let tz = Tz::Europe__Zurich;
+        // monday
+        let at = |day, h, m| {
+            tz.from_local_datetime(
+                &NaiveDate::from_ymd_opt(2024, 3, day)
+                    .unwrap()
+                    .and_hms_opt(h, m, 0)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let alarm_time = NaiveTime::from_hms_opt(6, 30, 0).unwrap();
+        assert_eq!(None, ramp.progress(at(4, 5, 59), alarm_time));
+        assert_eq!(
+            Some((at(4, 6, 30), 0.0)),
+            ramp.progress(at(4, 6, 0), alarm_time)
+        );
+        assert_eq!(
+            Some((at(4, 6, 30), 0.5)),
+            ramp.progress(at(4, 6, 15), alarm_time)
+        );
+        assert_eq!(None, ramp.progress(at(4, 6, 31), alarm_time));
+        // wednesday is off
+        assert_eq!(None, ramp.progress(at(6, 6, 15), alarm_time));
+        // the ramp for tuesday starts on monday
+        let midnight = NaiveTime::from_hms_opt(0, 10, 0).unwrap();
+        assert_eq!(
+            Some((at(5, 0, 10), 0.5)),
+            ramp.progress(at(4, 23, 55), midnight)
+        );
+
+        let output = WakeUpOutput {
+            brightness: BrightnessKey::Light(Default::default()),
+            whitebalance: LightColorKey::Light(Default::default()),
+            warm_mireds: 370,
+            cold_mireds: 154,
+        };
+        assert_eq!((0, 370), ramp.values_at(&output, 0.0));
+        assert_eq!((100, 262), ramp.values_at(&output, 0.5));
+        assert_eq!((200, 154), ramp.values_at(&output, 1.0));
+    }
+}
@@ -0,0 +1,256 @@
+use std::{f64::consts::PI, num::Saturating, time::Duration};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::stream::SelectAll;
+use log::{error, info};
+use tokio::sync::mpsc::{error::SendError, Sender};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::controller::manager::ControllerHandle;
+use crate::data::{
+    registry::{EventRegistry, LightColorKey},
+    wiring::{CircadianSchedule, CircadianWhitebalance},
+};
+
+pub async fn circadian_whitebalance(
+    event_registry: &EventRegistry,
+    settings: &CircadianWhitebalance,
+) -> ControllerHandle {
+    let mut color_streams = SelectAll::new();
+    let mut outputs = Vec::with_capacity(settings.outputs.len());
+    for (idx, key) in settings.outputs.iter().enumerate() {
+        color_streams.push(
+            event_registry
+                .light_color_stream(*key)
+                .await
+                .map(move |color| CircadianMessage::ColorChanged(idx, color)),
+        );
+        outputs.push(CircadianOutput {
+            key: *key,
+            sender: event_registry.light_color_sender(*key).await,
+            initialized: false,
+            sent: None,
+            paused_on: None,
+        });
+    }
+    let input_stream = event_registry
+        .clock(settings.clock)
+        .await
+        .map(CircadianMessage::Tick)
+        .merge(color_streams);
+    let curve = CircadianCurve {
+        schedule: settings.schedule,
+        day_mireds: settings.day_mireds,
+        night_mireds: settings.night_mireds,
+        transition: settings.transition,
+    };
+    tokio::spawn(async move {
+        if let Err(error) = circadian_task(input_stream, outputs, curve).await {
+            error!("Failed circadian whitebalance: {error}");
+            return Err(error.into());
+        }
+        Ok(())
+    })
+}
+
+struct CircadianOutput {
+    key: LightColorKey,
+    sender: Sender<Saturating<u16>>,
+    initialized: bool,
+    sent: Option<u16>,
+    paused_on: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CircadianCurve {
+    schedule: CircadianSchedule,
+    day_mireds: u16,
+    night_mireds: u16,
+    transition: Duration,
+}
+
+async fn circadian_task(
+    mut input: impl Stream<Item = CircadianMessage> + Unpin,
+    mut outputs: Vec<CircadianOutput>,
+    curve: CircadianCurve,
+) -> Result<(), SendError<Saturating<u16>>> {
+    let mut today = None;
+    let mut daylight = None;
+    while let Some(event) = input.next().await {
+        match event {
+            CircadianMessage::Tick(now) => {
+                let date = now.date_naive();
+                if today != Some(date) {
+                    today = Some(date);
+                    daylight = curve.schedule.daylight(date, now.timezone());
+                }
+                let target = curve.mireds_at(now, daylight);
+                for output in outputs.iter_mut() {
+                    if output.paused_on.is_some_and(|paused_on| paused_on != date) {
+                        info!("Resume circadian whitebalance on {:?}", output.key);
+                        output.paused_on = None;
+                    }
+                    if output.paused_on.is_none() && output.sent != Some(target) {
+                        output.sent = Some(target);
+                        output.sender.send(Saturating(target)).await?;
+                    }
+                }
+            }
+            CircadianMessage::ColorChanged(idx, color) => {
+                if let Some(output) = outputs.get_mut(idx) {
+                    if !output.initialized {
+                        output.initialized = true;
+                    } else if output.sent != Some(color.0) && output.paused_on.is_none() {
+                        if let Some(date) = today {
+                            info!(
+                                "Manual whitebalance on {:?}, pause until tomorrow",
+                                output.key
+                            );
+                            output.paused_on = Some(date);
+                            output.sent = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl CircadianCurve {
+    fn mireds_at(&self, now: DateTime<Tz>, daylight: Option<(DateTime<Tz>, DateTime<Tz>)>) -> u16 {
+        let factor = daylight
+            .map(|(sunrise, sunset)| {
+                let transition = self.transition.as_secs_f32().max(1.0);
+                let since_sunrise = (now - sunrise).num_seconds() as f32 / transition;
+                let until_sunset = (sunset - now).num_seconds() as f32 / transition;
+                since_sunrise.min(until_sunset).clamp(0.0, 1.0)
+            })
+            .unwrap_or(0.0);
+        (self.night_mireds as f32 + (self.day_mireds as f32 - self.night_mireds as f32) * factor)
+            .round() as u16
+    }
+}
+
+impl CircadianSchedule {
+    fn daylight(&self, date: NaiveDate, tz: Tz) -> Option<(DateTime<Tz>, DateTime<Tz>)> {
+        match self {
+            CircadianSchedule::Fixed { sunrise, sunset } => Some((
+                tz.from_local_datetime(&date.and_time(*sunrise))
+                    .earliest()?,
+                tz.from_local_datetime(&date.and_time(*sunset)).latest()?,
+            )),
+            CircadianSchedule::Sun {
+                latitude,
+                longitude,
+            } => {
+                let (sunrise, sunset) = sun_times(
+                    date,
+                    *latitude as f64 / 1_000_000.0,
+                    *longitude as f64 / 1_000_000.0,
+                )?;
+                Some((sunrise.with_timezone(&tz), sunset.with_timezone(&tz)))
+            }
+        }
+    }
+}
+
+/// Sunrise and sunset after the sunrise equation, polar night results in an empty day
+fn sun_times(
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let days = (date - epoch).num_days() as f64;
+    let mean_solar_time = days - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+    let center =
+        1.9148 * sin_deg(anomaly) + 0.02 * sin_deg(2.0 * anomaly) + 0.0003 * sin_deg(3.0 * anomaly);
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let transit =
+        mean_solar_time + 0.0053 * sin_deg(anomaly) - 0.0069 * sin_deg(2.0 * ecliptic_longitude);
+    let declination_sin = sin_deg(ecliptic_longitude) * sin_deg(23.4397);
+    let declination_cos = declination_sin.asin().cos();
+    let hour_angle_cos = (sin_deg(-0.833) - sin_deg(latitude) * declination_sin)
+        / (cos_deg(latitude) * declination_cos);
+    let hour_angle = hour_angle_cos.clamp(-1.0, 1.0).acos() * 180.0 / PI;
+    let noon = epoch.and_hms_opt(12, 0, 0)?.and_utc();
+    let at = |julian: f64| {
+        noon.checked_add_signed(chrono::Duration::seconds((julian * 86400.0).round() as i64))
+    };
+    Some((
+        at(transit - hour_angle / 360.0)?,
+        at(transit + hour_angle / 360.0)?,
+    ))
+}
+
+fn sin_deg(value: f64) -> f64 {
+    (value * PI / 180.0).sin()
+}
+
+fn cos_deg(value: f64) -> f64 {
+    (value * PI / 180.0).cos()
+}
+
+enum CircadianMessage {
+    Tick(DateTime<Tz>),
+    ColorChanged(usize, Saturating<u16>),
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Timelike};
+    use chrono_tz::Tz;
+
+    use crate::controller::whitebalance::{sun_times, CircadianCurve};
+    use crate::data::wiring::CircadianSchedule;
+
+    #[test]
+    fn test_sun_times() {
+        let (sunrise, sunset) = sun_times(
+            NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(),
+            47.3769,
+            8.5417,
+        )
+        .unwrap();
+        let sunrise = sunrise.with_timezone(&Tz::Europe__Zurich);
+        let sunset = sunset.with_timezone(&Tz::Europe__Zurich);
+        // published times for Zurich: 05:29 and 21:26
+        assert_eq!(5, sunrise.hour());
+        assert!((25..=33).contains(&sunrise.minute()));
+        assert_eq!(21, sunset.hour());
+        assert!((22..=30).contains(&sunset.minute()));
+    }
+
+    #[test]
+    fn test_curve() {
+        let curve = CircadianCurve {
+            schedule: CircadianSchedule::Fixed {
+                sunrise: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+                sunset: NaiveTime::from_hms_opt(19, 0, 0).unwrap(),
+            },
+            day_mireds: 200,
+            night_mireds: 370,
+            transition: Duration::from_secs(3600),
+        };
+        let tz = Tz::Europe__Zurich;
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let daylight = curve.schedule.daylight(date, tz);
+        let at = |h, m| {
+            curve.mireds_at(
+                tz.from_local_datetime(&date.and_hms_opt(h, m, 0).unwrap())
+                    .unwrap(),
+                daylight,
+            )
+        };
+        assert_eq!(370, at(5, 0));
+        assert_eq!(285, at(7, 30));
+        assert_eq!(200, at(12, 0));
+        assert_eq!(285, at(18, 30));
+        assert_eq!(370, at(22, 0));
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     array,
     borrow::Cow,
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::{Debug, Display, Formatter, Write},
     io,
     net::IpAddr,
@@ -11,7 +11,6 @@ use std::{
 };
 
 use chrono::{DateTime, Local};
-use chrono_tz::Tz;
 use google_sheets4::{
     api::{BatchUpdateValuesRequest, CellData, SpreadsheetMethods, ValueRange},
     hyper_rustls::{self, HttpsConnector},
@@ -19,7 +18,7 @@ use google_sheets4::{
     hyper_util::client::legacy::connect::HttpConnector,
     yup_oauth2, Sheets,
 };
-use log::{debug, info};
+use log::{debug, info, warn};
 use serde::Deserialize;
 use thiserror::Error;
 use tinkerforge_async::{base58::Uid, ip_connection::Version, DeviceIdentifier};
@@ -27,22 +26,30 @@ use tinkerforge_async::{base58::Uid, ip_connection::Version, DeviceIdentifier};
 use crate::{
     data::{
         registry::{
-            BrightnessKey, ClockKey, ClockKeyResolution, DualButtonKey, LightColorKey,
-            SingleButtonKey, SwitchOutputKey, TemperatureKey,
+            AlarmTimeKey, BrightnessKey, ClockKey, ClockKeyResolution, DualButtonKey, HumidityKey,
+            IlluminanceKey, LightColorKey, LightGroupKey, SceneValue, SceneValues,
+            ShutterPositionKey, SingleButtonKey, SwitchOutputKey, TemperatureKey, WindowOpenKey,
         },
         settings::{GoogleError, GoogleSheet, CONFIG},
         state::{BrickletConnectionData, BrickletMetadata, ConnectionState, State},
         wiring::{
-            ButtonSetting, Controllers, DmxConfigEntry, DmxSettings, DualInputDimmer,
-            DualInputSwitch, HeatController, IoSettings, MotionDetector, MotionDetectorSettings,
-            Orientation, RelayChannelEntry, RelaySettings, RingController, ScreenSettings,
-            TemperatureSettings, TinkerforgeDevices, Wiring,
+            default_dimm_duration, AllOffController, AmbientLightSettings, ButtonSetting,
+            ButtonTiming, CircadianSchedule, CircadianWhitebalance, ConstantLight, Controllers,
+            DaylightThreshold, DimmCurve, DmxConfigEntry, DmxSettings, DualInputDimmer,
+            DualInputSwitch, FlashLight, HeatController, HouseModeButton, HumiditySettings,
+            IoSettings, LightGroup, MotionDetector, MotionDetectorSettings, Orientation,
+            PreWarning, PresenceSimulation, QuietHours, RelayChannelEntry, RelaySettings,
+            RingController, Scene, SceneButton, ScreenSettings, Shutter, SingleInputDimmer,
+            SingleInputSwitch, TemperatureSettings, TimeSchedule, TimeWindow, TinkerforgeDevices,
+            Transition, VentilationFan, WakeUpLight, WakeUpOutput, WindowOpenDetection, Wiring,
         },
         DeviceInRoom, Room, SubDeviceInRoom,
     },
     util::kelvin_2_mireds,
 };
 
+const DEFAULT_PRE_WARNING_FLASH_TIME: Duration = Duration::from_millis(500);
+
 #[derive(Error, Debug)]
 pub enum GoogleDataError {
     #[error("Error accessing file")]
@@ -59,6 +66,15 @@ pub enum GoogleDataError {
     HeaderNotFound(HeaderError, Box<str>),
 }
 
+struct LightTemplate {
+    kind: LightTemplateTypes,
+    transition: Transition,
+    curve: DimmCurve,
+    dimm_duration: Duration,
+    /// per channel in 1/10 W
+    nominal_power: Option<u16>,
+}
+
 enum LightTemplateTypes {
     Switch,
     Dimm,
@@ -68,6 +84,39 @@ enum LightTemplateTypes {
     },
 }
 
+/// Registers of a light a scene can address
+#[derive(Clone, Copy)]
+enum LightOutputs {
+    Switch(SwitchOutputKey),
+    Dimm(BrightnessKey),
+    DimmWhitebalance(BrightnessKey, LightColorKey),
+}
+
+/// Scene values collected from the rows of the scene sheet
+#[derive(Default)]
+struct SceneValueLists {
+    brightness: Vec<SceneValue<BrightnessKey, u8>>,
+    light_colors: Vec<SceneValue<LightColorKey, u16>>,
+    switches: Vec<SceneValue<SwitchOutputKey, bool>>,
+}
+
+impl From<SceneValueLists> for SceneValues {
+    fn from(mut lists: SceneValueLists) -> Self {
+        lists.brightness.sort();
+        lists.light_colors.sort();
+        lists.switches.sort();
+        // lights may share a whitebalance register, every register gets one value
+        lists.brightness.dedup_by_key(|v| v.key);
+        lists.light_colors.dedup_by_key(|v| v.key);
+        lists.switches.dedup_by_key(|v| v.key);
+        SceneValues {
+            brightness: lists.brightness.into_boxed_slice(),
+            light_colors: lists.light_colors.into_boxed_slice(),
+            switches: lists.switches.into_boxed_slice(),
+        }
+    }
+}
+
 enum ButtonStyle {
     Single,
     Dual,
@@ -76,6 +125,7 @@ enum ButtonStyle {
 struct ButtonTemplateTypes {
     style: ButtonStyle,
     sub_devices: Box<[Box<str>]>,
+    timing: ButtonTiming,
 }
 
 struct ParserContext<'a> {
@@ -129,7 +179,10 @@ pub async fn read_sheet_data(state: Option<&State>) -> Result<Option<Wiring>, Go
         builder.parse_motion_detectors(&context).await?;
         builder.parse_controllers(&context).await?;
         builder.parse_lights(&context).await?;
+        builder.parse_scenes(&context).await?;
         builder.parse_relays(&context).await?;
+        builder.parse_shutters(&context).await?;
+        builder.parse_fans(&context).await?;
 
         builder.update_available_devices(&context).await?;
 
@@ -148,6 +201,8 @@ struct GoogleSheetWireBuilder {
     lcd_screens: BTreeMap<Uid, ScreenSettings>,
     temperature_sensors: BTreeMap<Uid, TemperatureSettings>,
     motion_detector_sensors: BTreeMap<Uid, MotionDetectorSettings>,
+    ambient_light_sensors: BTreeMap<Uid, AmbientLightSettings>,
+    humidity_sensors: BTreeMap<Uid, HumiditySettings>,
     relays: BTreeMap<Uid, RelaySettings>,
     endpoints: Vec<IpAddr>,
 
@@ -156,6 +211,18 @@ struct GoogleSheetWireBuilder {
     motion_detectors: Vec<MotionDetector>,
     heat_controllers: Vec<HeatController>,
     ring_controllers: Vec<RingController>,
+    single_input_dimmers: Vec<SingleInputDimmer>,
+    single_input_switches: Vec<SingleInputSwitch>,
+    constant_lights: Vec<ConstantLight>,
+    /// lights staying on, by name of the all off button
+    all_off_exceptions: HashMap<Box<str>, Vec<DeviceInRoom>>,
+    scenes: Vec<Scene>,
+    shutters: Vec<Shutter>,
+    ventilation_fans: Vec<VentilationFan>,
+    presence_simulation_switches: Vec<SwitchOutputKey>,
+    presence_simulation_dimmers: Vec<BrightnessKey>,
+    circadian_outputs: BTreeSet<LightColorKey>,
+    wake_up_outputs: BTreeMap<Room, Vec<WakeUpOutput>>,
 
     single_button_adresses: HashMap<Box<str>, SingleButtonKey>,
     dual_button_adresses: HashMap<Box<str>, DualButtonKey>,
@@ -163,6 +230,9 @@ struct GoogleSheetWireBuilder {
     touchscreen_whitebalance_addresses: HashMap<Box<str>, LightColorKey>,
     touchscreen_brightness_addresses: HashMap<Box<str>, BrightnessKey>,
     motion_detector_adresses: HashMap<Box<str>, SingleButtonKey>,
+    motion_detector_daylight: HashMap<SingleButtonKey, DaylightThreshold>,
+    motion_detector_illuminance: HashMap<SingleButtonKey, IlluminanceKey>,
+    light_outputs: HashMap<DeviceInRoom, LightOutputs>,
 
     endpoint_names: HashMap<IpAddr, Box<str>>,
 
@@ -459,6 +529,8 @@ impl GoogleSheetWireBuilder {
             lcd_screens,
             temperature_sensors,
             motion_detector_sensors,
+            ambient_light_sensors,
+            humidity_sensors,
             relays,
             mut endpoints,
             mut dual_input_dimmers,
@@ -466,15 +538,118 @@ impl GoogleSheetWireBuilder {
             mut motion_detectors,
             mut heat_controllers,
             mut ring_controllers,
+            mut single_input_dimmers,
+            mut single_input_switches,
+            mut constant_lights,
+            mut all_off_exceptions,
+            mut scenes,
+            mut shutters,
+            mut ventilation_fans,
+            mut presence_simulation_switches,
+            mut presence_simulation_dimmers,
+            circadian_outputs,
+            wake_up_outputs,
+            single_button_adresses,
+            dual_button_adresses,
+            light_outputs,
             ..
         } = self;
 
+        let mut all_off_controllers = CONFIG
+            .all_off
+            .iter()
+            .filter_map(|settings| {
+                let input = single_button_adresses.get(settings.button());
+                if input.is_none() {
+                    warn!("All off button {} not found", settings.button());
+                }
+                let mut exceptions = all_off_exceptions
+                    .remove(settings.button())
+                    .unwrap_or_default();
+                exceptions.sort();
+                input.map(|input| AllOffController {
+                    input: *input,
+                    group: light_group(settings.group(), &light_outputs),
+                    exceptions: exceptions.into_boxed_slice(),
+                })
+            })
+            .collect::<Vec<_>>();
+        for (button, devices) in all_off_exceptions {
+            warn!("All off button {button} of exceptions {devices:?} not configured");
+        }
+        let mut scene_buttons = CONFIG
+            .scene_buttons
+            .iter()
+            .filter_map(|settings| {
+                let input = single_button_adresses.get(settings.button());
+                if input.is_none() {
+                    warn!("Scene button {} not found", settings.button());
+                }
+                input.map(|input| SceneButton {
+                    input: *input,
+                    scene: settings.scene().into(),
+                    capture: settings.capture(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut house_mode_buttons = CONFIG
+            .house_mode_buttons
+            .iter()
+            .filter_map(|settings| {
+                let input = single_button_adresses.get(settings.button());
+                if input.is_none() {
+                    warn!("House mode button {} not found", settings.button());
+                }
+                input.map(|input| HouseModeButton {
+                    input: *input,
+                    mode: settings.mode(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let wake_up_lights = wake_up_outputs
+            .into_iter()
+            .map(|(room, outputs)| {
+                wake_up_light(
+                    room,
+                    outputs,
+                    &single_button_adresses,
+                    &dual_button_adresses,
+                )
+            })
+            .collect();
+        presence_simulation_switches.sort();
+        presence_simulation_dimmers.sort();
+        let presence_simulations: Box<[PresenceSimulation]> =
+            if presence_simulation_switches.is_empty() && presence_simulation_dimmers.is_empty() {
+                Box::new([])
+            } else {
+                Box::new([presence_simulation(
+                    presence_simulation_switches.into_boxed_slice(),
+                    presence_simulation_dimmers.into_boxed_slice(),
+                )])
+            };
+
         dual_input_dimmers.sort();
         dual_input_switches.sort();
         motion_detectors.sort();
         heat_controllers.sort();
         ring_controllers.sort();
+        single_input_dimmers.sort();
+        single_input_switches.sort();
+        constant_lights.sort();
+        all_off_controllers.sort();
+        scenes.sort();
+        scene_buttons.sort();
+        house_mode_buttons.sort();
+        shutters.sort();
+        ventilation_fans.sort();
         endpoints.sort();
+        let window_contacts = heat_controllers
+            .iter()
+            .flat_map(|c| c.window_open_detection.iter())
+            .flat_map(|d| d.window_contacts.iter())
+            .copied()
+            .collect::<HashSet<_>>();
         Wiring {
             controllers: Controllers {
                 dual_input_dimmers: dual_input_dimmers.into_boxed_slice(),
@@ -482,6 +657,26 @@ impl GoogleSheetWireBuilder {
                 motion_detectors: motion_detectors.into_boxed_slice(),
                 heat_controllers: heat_controllers.into_boxed_slice(),
                 ring_controllers: ring_controllers.into_boxed_slice(),
+                single_input_dimmers: single_input_dimmers.into_boxed_slice(),
+                single_input_switches: single_input_switches.into_boxed_slice(),
+                constant_lights: constant_lights.into_boxed_slice(),
+                all_off_controllers: all_off_controllers.into_boxed_slice(),
+                scenes: scenes.into_boxed_slice(),
+                scene_buttons: scene_buttons.into_boxed_slice(),
+                house_mode_buttons: house_mode_buttons.into_boxed_slice(),
+                presence_simulations,
+                shutters: shutters.into_boxed_slice(),
+                logic_blocks: Default::default(),
+                wake_up_lights,
+                ventilation_fans: ventilation_fans.into_boxed_slice(),
+                scripts: Default::default(),
+                circadian_whitebalances: if circadian_outputs.is_empty() {
+                    Box::new([])
+                } else {
+                    Box::new([circadian_whitebalance(
+                        circadian_outputs.into_iter().collect(),
+                    )])
+                },
             },
             tinkerforge_devices: TinkerforgeDevices {
                 endpoints: endpoints.into_boxed_slice(),
@@ -501,6 +696,7 @@ impl GoogleSheetWireBuilder {
                 io_bricklets: io_bricklets
                     .into_iter()
                     .map(|(uid, mut settings)| {
+                        hold_window_contacts(&mut settings, &window_contacts);
                         settings.sort();
                         (
                             uid,
@@ -513,6 +709,8 @@ impl GoogleSheetWireBuilder {
                 motion_detectors: motion_detector_sensors,
                 relays,
                 temperature_sensors,
+                ambient_light_sensors,
+                humidity_sensors,
             },
         }
     }
@@ -522,56 +720,63 @@ impl GoogleSheetWireBuilder {
         context: &'a ParserContext<'a>,
     ) -> Result<(), GoogleDataError> {
         let relay_configs = context.config.relays();
+        let logic_output_columns = relay_configs.logic_output().into_iter().collect::<Vec<_>>();
         let mut relay_channels = HashMap::<_, Vec<_>>::new();
         let mut device_ids_of_rooms = HashMap::<_, Vec<_>>::new();
 
-        for (room, idx, uid, channel, temperature, ring_button, old_state) in GoogleTable::connect(
-            &context.spreadsheet_methods,
-            [
-                relay_configs.room_id(),
-                relay_configs.idx(),
-                relay_configs.device_address(),
-                relay_configs.device_channel(),
-                relay_configs.temperature_sensor(),
-                relay_configs.ring_button(),
-                relay_configs.state(),
-            ],
-            [],
-            context.config.spreadsheet_id(),
-            relay_configs.sheet(),
-            relay_configs.range(),
-        )
-        .await?
-        .filter_map(
-            |([room, idx, address, channel, sensor, button, state], _)| {
-                if let (Some(room), Some(address), Some(channel)) = (
-                    room.get_content().map(Room::from_str).and_then(Result::ok),
-                    address
-                        .get_content()
-                        .map(Uid::from_str)
-                        .and_then(Result::ok),
-                    channel.get_integer().map(|v| v as u8),
-                ) {
-                    Some((
-                        room,
-                        DeviceIdxCell(idx),
-                        address,
-                        channel,
-                        sensor
-                            .get_content()
-                            .and_then(|k| self.heat_outputs_addresses.get(k))
-                            .copied(),
-                        button
+        for (room, idx, uid, channel, temperature, ring_button, logic_output, old_state) in
+            GoogleTable::connect(
+                &context.spreadsheet_methods,
+                [
+                    relay_configs.room_id(),
+                    relay_configs.idx(),
+                    relay_configs.device_address(),
+                    relay_configs.device_channel(),
+                    relay_configs.temperature_sensor(),
+                    relay_configs.ring_button(),
+                    relay_configs.state(),
+                ],
+                [&logic_output_columns],
+                context.config.spreadsheet_id(),
+                relay_configs.sheet(),
+                relay_configs.range(),
+            )
+            .await?
+            .filter_map(
+                |([room, idx, address, channel, sensor, button, state], [logic_output])| {
+                    if let (Some(room), Some(address), Some(channel)) = (
+                        room.get_content().map(Room::from_str).and_then(Result::ok),
+                        address
                             .get_content()
-                            .and_then(|k| self.single_button_adresses.get(k))
-                            .copied(),
-                        state,
-                    ))
-                } else {
-                    None
-                }
-            },
-        ) {
+                            .map(Uid::from_str)
+                            .and_then(Result::ok),
+                        channel.get_integer().map(|v| v as u8),
+                    ) {
+                        Some((
+                            room,
+                            DeviceIdxCell(idx),
+                            address,
+                            channel,
+                            sensor
+                                .get_content()
+                                .and_then(|k| self.heat_outputs_addresses.get(k))
+                                .copied(),
+                            button
+                                .get_content()
+                                .and_then(|k| self.single_button_adresses.get(k))
+                                .copied(),
+                            logic_output
+                                .first()
+                                .and_then(GoogleCellData::get_content)
+                                .is_some_and(|v| !v.is_empty()),
+                            state,
+                        ))
+                    } else {
+                        None
+                    }
+                },
+            )
+        {
             if let Some(temp_input) = temperature {
                 relay_channels
                     .entry(uid)
@@ -579,9 +784,10 @@ impl GoogleSheetWireBuilder {
                     .push(RelayChannelEntry {
                         channel,
                         input: temp_input,
+                        interlock: None,
                     });
-            } else if let Some(ring_button) = ring_button {
-                device_ids_of_rooms.entry(room).or_default().push(RingRow {
+            } else if ring_button.is_some() || logic_output {
+                device_ids_of_rooms.entry(room).or_default().push(RelayRow {
                     uid,
                     channel,
                     idx,
@@ -590,40 +796,218 @@ impl GoogleSheetWireBuilder {
             }
             update_state_new(|v| self.updates.push(v), context.state, &old_state, uid);
         }
-        struct RingRow<'a> {
+        struct RelayRow<'a> {
             idx: DeviceIdxCell<'a>,
-            ring_button: SingleButtonKey,
+            ring_button: Option<SingleButtonKey>,
             uid: Uid,
             channel: u8,
         }
-        impl<'a> DeviceIdxAccessNew<'a> for RingRow<'a> {
+        impl<'a> DeviceIdxAccessNew<'a> for RelayRow<'a> {
             fn id_cell<'b>(&'b mut self) -> &'b mut DeviceIdxCell<'a> {
                 &mut self.idx
             }
         }
-        let ring_rows = fill_device_idx(|v| self.updates.push(v), device_ids_of_rooms);
-        for (device, row) in ring_rows {
-            let index = SwitchOutputKey::Bell(device);
-            self.ring_controllers.push(RingController {
-                input: row.ring_button,
-                output: index,
-            });
+        let relay_rows = fill_device_idx(|v| self.updates.push(v), device_ids_of_rooms);
+        for (device, row) in relay_rows {
+            // the rows without a bell button are marked as outputs of logic blocks
+            let index = if let Some(ring_button) = row.ring_button {
+                let index = SwitchOutputKey::Bell(device);
+                self.ring_controllers.push(ring_controller(
+                    ring_button,
+                    index,
+                    &self.light_outputs,
+                ));
+                index
+            } else {
+                SwitchOutputKey::Relay(device)
+            };
             relay_channels
                 .entry(row.uid)
                 .or_default()
                 .push(RelayChannelEntry {
                     channel: row.channel,
                     input: index,
+                    interlock: None,
                 });
         }
 
         for (uid, channels) in relay_channels {
-            self.relays.insert(
+            self.add_relay_channels(uid, channels);
+        }
+        Ok(())
+    }
+
+    fn add_relay_channels(&mut self, uid: Uid, channels: Vec<RelayChannelEntry>) {
+        let settings = self.relays.entry(uid).or_insert_with(|| RelaySettings {
+            entries: Box::new([]),
+        });
+        let mut entries = settings.entries.to_vec();
+        entries.extend(channels);
+        entries.sort();
+        settings.entries = entries.into_boxed_slice();
+    }
+
+    async fn parse_shutters<'a>(
+        &mut self,
+        context: &'a ParserContext<'a>,
+    ) -> Result<(), GoogleDataError> {
+        let Some(shutter_config) = context.config.shutters() else {
+            return Ok(());
+        };
+        for ([room, idx, address, up_channel, down_channel, button, travel_time], _) in
+            GoogleTable::connect(
+                &context.spreadsheet_methods,
+                [
+                    shutter_config.room_id(),
+                    shutter_config.idx(),
+                    shutter_config.device_address(),
+                    shutter_config.up_channel(),
+                    shutter_config.down_channel(),
+                    shutter_config.button(),
+                    shutter_config.travel_time(),
+                ],
+                [],
+                context.config.spreadsheet_id(),
+                shutter_config.sheet(),
+                shutter_config.range(),
+            )
+            .await?
+        {
+            let (Some(room), Some(idx), Some(uid), Some(up_channel), Some(down_channel)) = (
+                room.get_content().map(Room::from_str).and_then(Result::ok),
+                idx.get_integer(),
+                address
+                    .get_content()
+                    .map(Uid::from_str)
+                    .and_then(Result::ok),
+                up_channel.get_integer().map(|v| v as u8),
+                down_channel.get_integer().map(|v| v as u8),
+            ) else {
+                continue;
+            };
+            let device = DeviceInRoom {
+                room,
+                idx: idx as u16,
+            };
+            let Some(travel_time) = travel_time
+                .get_integer()
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| Duration::from_secs(seconds as u64))
+            else {
+                warn!("Shutter {device:?}: no travel time");
+                continue;
+            };
+            let up = SwitchOutputKey::ShutterUp(device);
+            let down = SwitchOutputKey::ShutterDown(device);
+            self.add_relay_channels(
                 uid,
-                RelaySettings {
-                    entries: channels.into_boxed_slice(),
+                vec![
+                    RelayChannelEntry {
+                        channel: up_channel,
+                        input: up,
+                        interlock: Some(down_channel),
+                    },
+                    RelayChannelEntry {
+                        channel: down_channel,
+                        input: down,
+                        interlock: Some(up_channel),
+                    },
+                ],
+            );
+            let input = button
+                .get_content()
+                .filter(|name| !name.is_empty())
+                .and_then(|name| {
+                    let input = self.dual_button_adresses.get(name);
+                    if input.is_none() {
+                        warn!("Shutter {device:?}: button {name} not found");
+                    }
+                    input
+                })
+                .copied();
+            self.shutters.push(Shutter {
+                input: input.into_iter().collect(),
+                up,
+                down,
+                position: ShutterPositionKey(device),
+                travel_time,
+            });
+        }
+        Ok(())
+    }
+
+    async fn parse_fans<'a>(
+        &mut self,
+        context: &'a ParserContext<'a>,
+    ) -> Result<(), GoogleDataError> {
+        let Some(fan_config) = context.config.fans() else {
+            return Ok(());
+        };
+        let settings = &CONFIG.ventilation;
+        for ([room, idx, address, channel, sensor_address], _) in GoogleTable::connect(
+            &context.spreadsheet_methods,
+            [
+                fan_config.room_id(),
+                fan_config.idx(),
+                fan_config.device_address(),
+                fan_config.channel(),
+                fan_config.humidity_sensor(),
+            ],
+            [],
+            context.config.spreadsheet_id(),
+            fan_config.sheet(),
+            fan_config.range(),
+        )
+        .await?
+        {
+            let (Some(room), Some(idx), Some(uid), Some(channel)) = (
+                room.get_content().map(Room::from_str).and_then(Result::ok),
+                idx.get_integer(),
+                address
+                    .get_content()
+                    .map(Uid::from_str)
+                    .and_then(Result::ok),
+                channel.get_integer().map(|v| v as u8),
+            ) else {
+                continue;
+            };
+            let device = DeviceInRoom {
+                room,
+                idx: idx as u16,
+            };
+            let Some(sensor_uid) = sensor_address
+                .get_content()
+                .map(Uid::from_str)
+                .and_then(Result::ok)
+            else {
+                warn!("Fan {device:?}: no humidity sensor");
+                continue;
+            };
+            let sensor = HumidityKey(device);
+            self.humidity_sensors.insert(
+                sensor_uid,
+                HumiditySettings {
+                    output: sensor,
+                    temperature: Some(TemperatureKey::CurrentTemperature(device)),
                 },
             );
+            let output = SwitchOutputKey::Fan(device);
+            self.add_relay_channels(
+                uid,
+                vec![RelayChannelEntry {
+                    channel,
+                    input: output,
+                    interlock: None,
+                }],
+            );
+            self.ventilation_fans.push(VentilationFan {
+                sensor,
+                output,
+                rise: settings.rise(),
+                baseline_time: settings.baseline_time(),
+                run_on: settings.run_on(),
+                max_runtime: settings.max_runtime(),
+            });
         }
         Ok(())
     }
@@ -641,6 +1025,11 @@ impl GoogleSheetWireBuilder {
             enable_heatcontrol: bool,
             enable_whitebalance_control: bool,
             enable_brighness_control: bool,
+            window_contacts: Box<[Box<str>]>,
+            window_drop_detection: bool,
+            scene: Option<Box<str>>,
+            house_mode: bool,
+            wake_up: bool,
         }
         impl<'a> DeviceIdxAccessNew<'a> for ControllerRow<'a> {
             fn id_cell<'b>(&'b mut self) -> &'b mut DeviceIdxCell<'a> {
@@ -648,6 +1037,14 @@ impl GoogleSheetWireBuilder {
             }
         }
         let controllers = context.config.room_controllers();
+        let window_contact_columns = controllers.window_contact().into_iter().collect::<Vec<_>>();
+        let window_drop_detection_columns = controllers
+            .window_drop_detection()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let scene_columns = controllers.scene().into_iter().collect::<Vec<_>>();
+        let house_mode_columns = controllers.house_mode().into_iter().collect::<Vec<_>>();
+        let wake_up_columns = controllers.wake_up().into_iter().collect::<Vec<_>>();
         let mut device_ids_of_rooms = HashMap::<_, Vec<_>>::new();
 
         for (
@@ -662,6 +1059,11 @@ impl GoogleSheetWireBuilder {
             enable_brighness_control,
             touchscreen_state,
             temperature_state,
+            window_contacts,
+            window_drop_detection,
+            scene,
+            house_mode,
+            wake_up,
         ) in GoogleTable::connect(
             &context.spreadsheet_methods,
             [
@@ -677,7 +1079,13 @@ impl GoogleSheetWireBuilder {
                 controllers.touchscreen_state(),
                 controllers.temperature_state(),
             ],
-            [],
+            [
+                &window_contact_columns,
+                &window_drop_detection_columns,
+                &scene_columns,
+                &house_mode_columns,
+                &wake_up_columns,
+            ],
             context.config.spreadsheet_id(),
             controllers.sheet(),
             controllers.range(),
@@ -698,7 +1106,7 @@ impl GoogleSheetWireBuilder {
                     touchscreen_state,
                     temperature_state,
                 ],
-                _,
+                [window_contacts, window_drop_detection, scene, house_mode, wake_up],
             )| {
                 if let (Some(room), Some(controller_id), Some(orientation)) = (
                     room.get_content().map(Room::from_str).and_then(Result::ok),
@@ -735,6 +1143,33 @@ impl GoogleSheetWireBuilder {
                             .unwrap_or(false),
                         touchscreen_state,
                         temperature_state,
+                        window_contacts
+                            .iter()
+                            .filter_map(|cell| cell.get_content())
+                            .filter(|s| !s.is_empty())
+                            .map(<&str>::into)
+                            .collect::<Vec<_>>()
+                            .into_boxed_slice(),
+                        window_drop_detection
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| !v.is_empty())
+                            .unwrap_or(false),
+                        scene
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .filter(|s| !s.is_empty())
+                            .map(<&str>::into),
+                        house_mode
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| !v.is_empty())
+                            .unwrap_or(false),
+                        wake_up
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| !v.is_empty())
+                            .unwrap_or(false),
                     ))
                 } else {
                     None
@@ -753,6 +1188,11 @@ impl GoogleSheetWireBuilder {
                     enable_heatcontrol,
                     enable_whitebalance_control,
                     enable_brighness_control,
+                    window_contacts,
+                    window_drop_detection,
+                    scene,
+                    house_mode,
+                    wake_up,
                 });
             if let Some(uid) = touchscreen {
                 update_state_new(
@@ -773,6 +1213,7 @@ impl GoogleSheetWireBuilder {
         }
         let controller_rows = fill_device_idx(|s| self.updates.push(s), device_ids_of_rooms);
         for (device_idx, row) in controller_rows {
+            let mut window_open_key = None;
             let current_temperature_key = if let Some(uid) = row.temp_sensor {
                 let output = TemperatureKey::CurrentTemperature(device_idx);
                 self.temperature_sensors
@@ -785,10 +1226,34 @@ impl GoogleSheetWireBuilder {
                 if let Some(current_value_input) = current_temperature_key {
                     let target_value_input = TemperatureKey::TargetTemperature(device_idx);
                     let output = SwitchOutputKey::Heat(device_idx);
+                    let mut window_contacts = row
+                        .window_contacts
+                        .iter()
+                        .flat_map(|name| self.single_button_adresses.get(name))
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .into_boxed_slice();
+                    window_contacts.sort();
+                    // rooms without contacts detect open windows only if the sheet asks for it
+                    let window_open_detection =
+                        (!window_contacts.is_empty() || row.window_drop_detection).then(|| {
+                            let state_output = WindowOpenKey(device_idx);
+                            window_open_key = Some(state_output);
+                            WindowOpenDetection {
+                                window_contacts,
+                                temperature_drop: row
+                                    .window_drop_detection
+                                    .then(|| CONFIG.heating.window_temperature_drop()),
+                                drop_interval: CONFIG.heating.window_drop_interval(),
+                                suspend_time: CONFIG.heating.window_suspend_time(),
+                                state_output,
+                            }
+                        });
                     self.heat_controllers.push(HeatController {
                         current_value_input,
                         target_value_input,
                         output,
+                        window_open_detection,
                     });
                     self.heat_outputs_addresses.insert(row.id.clone(), output);
                     Some(target_value_input)
@@ -820,12 +1285,16 @@ impl GoogleSheetWireBuilder {
                         orientation: row.orientation,
                         clock_key: Some(ClockKey {
                             resolution: ClockKeyResolution::Minutes,
-                            tz: Tz::Europe__Zurich,
+                            tz: CONFIG.server.timezone(),
                         }),
                         current_temperature_key,
                         adjust_temperature_key,
                         light_color_key,
                         brightness_key,
+                        window_open_key,
+                        scene: row.scene,
+                        house_mode: row.house_mode,
+                        alarm_time_key: row.wake_up.then_some(AlarmTimeKey(device_idx.room)),
                     },
                 );
             }
@@ -842,6 +1311,8 @@ impl GoogleSheetWireBuilder {
             id: Box<str>,
             device_address: Uid,
             idx: DeviceIdxCell<'a>,
+            ambient_light_sensor: Option<Uid>,
+            daylight_lux: Option<u32>,
         }
         impl<'a> DeviceIdxAccessNew<'a> for MotionDetectorRow<'a> {
             fn id_cell<'b>(&'b mut self) -> &'b mut DeviceIdxCell<'a> {
@@ -850,37 +1321,61 @@ impl GoogleSheetWireBuilder {
         }
 
         let md_config = context.config.motion_detectors();
+        let ambient_light_columns = md_config
+            .ambient_light_sensor()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let daylight_lux_columns = md_config.daylight_lux().into_iter().collect::<Vec<_>>();
         let mut device_ids_of_rooms = HashMap::<_, Vec<_>>::new();
 
-        for (room, device_address, id, idx, state_cell) in GoogleTable::connect(
-            &context.spreadsheet_methods,
-            [
-                md_config.room_id(),
-                md_config.device_address(),
-                md_config.id(),
-                md_config.idx(),
-                md_config.state(),
-            ],
-            [],
-            context.config.spreadsheet_id(),
-            md_config.sheet(),
-            md_config.range(),
-        )
-        .await?
-        .filter_map(|([room, address, id, idx, state], _)| {
-            if let (Some(room), Some(address), Some(id)) = (
-                room.get_content().map(Room::from_str).and_then(Result::ok),
-                address
-                    .get_content()
-                    .map(Uid::from_str)
-                    .and_then(Result::ok),
-                id.get_content().map(Into::<Box<str>>::into),
-            ) {
-                Some((room, address, id, DeviceIdxCell(idx), state))
-            } else {
-                None
-            }
-        }) {
+        for (room, device_address, id, idx, state_cell, ambient_light_sensor, daylight_lux) in
+            GoogleTable::connect(
+                &context.spreadsheet_methods,
+                [
+                    md_config.room_id(),
+                    md_config.device_address(),
+                    md_config.id(),
+                    md_config.idx(),
+                    md_config.state(),
+                ],
+                [&ambient_light_columns, &daylight_lux_columns],
+                context.config.spreadsheet_id(),
+                md_config.sheet(),
+                md_config.range(),
+            )
+            .await?
+            .filter_map(
+                |([room, address, id, idx, state], [ambient_light_sensor, daylight_lux])| {
+                    if let (Some(room), Some(address), Some(id)) = (
+                        room.get_content().map(Room::from_str).and_then(Result::ok),
+                        address
+                            .get_content()
+                            .map(Uid::from_str)
+                            .and_then(Result::ok),
+                        id.get_content().map(Into::<Box<str>>::into),
+                    ) {
+                        Some((
+                            room,
+                            address,
+                            id,
+                            DeviceIdxCell(idx),
+                            state,
+                            ambient_light_sensor
+                                .first()
+                                .and_then(GoogleCellData::get_content)
+                                .map(Uid::from_str)
+                                .and_then(Result::ok),
+                            daylight_lux
+                                .first()
+                                .and_then(GoogleCellData::get_integer)
+                                .map(|lux| lux.max(0) as u32),
+                        ))
+                    } else {
+                        None
+                    }
+                },
+            )
+        {
             device_ids_of_rooms
                 .entry(room)
                 .or_default()
@@ -888,6 +1383,8 @@ impl GoogleSheetWireBuilder {
                     id,
                     device_address,
                     idx,
+                    ambient_light_sensor,
+                    daylight_lux,
                 });
             update_state_new(
                 |v| self.updates.push(v),
@@ -897,11 +1394,31 @@ impl GoogleSheetWireBuilder {
             );
         }
         let motion_detector_rows = fill_device_idx(|v| self.updates.push(v), device_ids_of_rooms);
+        let illuminance_keys = ambient_light_keys(
+            motion_detector_rows
+                .iter()
+                .filter_map(|(device_idx, row)| Some((*device_idx, row.ambient_light_sensor?))),
+        );
+        for (sensor_address, sensor) in &illuminance_keys {
+            self.ambient_light_sensors
+                .insert(*sensor_address, AmbientLightSettings { output: *sensor });
+        }
 
         for (device_idx, row) in motion_detector_rows {
             let key = SingleButtonKey::MotionDetector(device_idx);
             self.motion_detector_sensors
                 .insert(row.device_address, MotionDetectorSettings { output: key });
+            if let Some(sensor) = row
+                .ambient_light_sensor
+                .and_then(|address| illuminance_keys.get(&address))
+                .copied()
+            {
+                self.motion_detector_illuminance.insert(key, sensor);
+                if let Some(lux) = row.daylight_lux {
+                    self.motion_detector_daylight
+                        .insert(key, DaylightThreshold { sensor, lux });
+                }
+            }
             self.motion_detector_adresses.insert(row.id, key);
         }
         Ok(())
@@ -912,8 +1429,35 @@ impl GoogleSheetWireBuilder {
         context: &'a ParserContext<'a>,
     ) -> Result<(), GoogleDataError> {
         let light_templates = context.config.light_templates();
+        let fade_in_columns = light_templates
+            .fade_in_column()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let fade_out_columns = light_templates
+            .fade_out_column()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let color_transition_columns = light_templates
+            .color_transition_column()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let dimm_curve_columns = light_templates
+            .dimm_curve_column()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let dimm_duration_columns = light_templates
+            .dimm_duration_column()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let wattage_columns = light_templates
+            .wattage_column()
+            .into_iter()
+            .collect::<Vec<_>>();
         let mut light_template_map = HashMap::new();
-        for ([name, discriminator, warm, cold], _) in GoogleTable::connect(
+        for (
+            [name, discriminator, warm, cold],
+            [fade_in, fade_out, color_transition, dimm_curve, dimm_duration, wattage],
+        ) in GoogleTable::connect(
             &context.spreadsheet_methods,
             [
                 light_templates.name_column(),
@@ -921,7 +1465,14 @@ impl GoogleSheetWireBuilder {
                 light_templates.temperature_warm_column(),
                 light_templates.temperature_cold_column(),
             ],
-            [],
+            [
+                &fade_in_columns,
+                &fade_out_columns,
+                &color_transition_columns,
+                &dimm_curve_columns,
+                &dimm_duration_columns,
+                &wattage_columns,
+            ],
             context.config.spreadsheet_id(),
             light_templates.sheet(),
             light_templates.range(),
@@ -932,27 +1483,59 @@ impl GoogleSheetWireBuilder {
                 name.get_content().map(Into::<Box<str>>::into),
                 discriminator.get_content(),
             ) {
-                if discriminator == "Switch" {
-                    light_template_map.insert(name, LightTemplateTypes::Switch);
+                let kind = if discriminator == "Switch" {
+                    LightTemplateTypes::Switch
                 } else if discriminator == "Dimm" {
-                    light_template_map.insert(name, LightTemplateTypes::Dimm);
+                    LightTemplateTypes::Dimm
                 } else if discriminator == "DimmWhitebalance" {
                     if let (Some(warm_temperature), Some(cold_temperature)) =
                         (warm.get_integer(), cold.get_integer())
                     {
-                        light_template_map.insert(
-                            name,
-                            LightTemplateTypes::DimmWhitebalance {
-                                warm_temperature: kelvin_2_mireds(warm_temperature as u16),
-                                cold_temperature: kelvin_2_mireds(cold_temperature as u16),
-                            },
-                        );
+                        LightTemplateTypes::DimmWhitebalance {
+                            warm_temperature: kelvin_2_mireds(warm_temperature as u16),
+                            cold_temperature: kelvin_2_mireds(cold_temperature as u16),
+                        }
+                    } else {
+                        continue;
                     }
-                }
+                } else {
+                    continue;
+                };
+                let duration_millis = |cells: &[GoogleCellData]| {
+                    cells
+                        .first()
+                        .and_then(GoogleCellData::get_integer)
+                        .map(|ms| Duration::from_millis(ms.max(0) as u64))
+                };
+                let curve = match dimm_curve.first().and_then(GoogleCellData::get_content) {
+                    Some("Gamma") => DimmCurve::Gamma,
+                    Some("Log") | Some("Logarithmic") => DimmCurve::Logarithmic,
+                    _ => DimmCurve::Linear,
+                };
+                light_template_map.insert(
+                    name,
+                    LightTemplate {
+                        kind,
+                        transition: Transition {
+                            fade_in: duration_millis(&fade_in).unwrap_or_default(),
+                            fade_out: duration_millis(&fade_out).unwrap_or_default(),
+                            color: duration_millis(&color_transition).unwrap_or_default(),
+                        },
+                        curve,
+                        dimm_duration: duration_millis(&dimm_duration)
+                            .filter(|d| !d.is_zero())
+                            .unwrap_or_else(default_dimm_duration),
+                        nominal_power: wattage
+                            .first()
+                            .and_then(GoogleCellData::get_number)
+                            .filter(|watts| *watts > 0.0)
+                            .map(|watts| (watts * 10.0).round() as u16),
+                    },
+                );
             }
         }
         struct LightRowContent<'a> {
-            light_template: &'a LightTemplateTypes,
+            light_template: &'a LightTemplate,
             device_id_in_room: DeviceIdxCell<'a>,
             device_address: Uid,
             bus_start_address: u16,
@@ -960,6 +1543,13 @@ impl GoogleSheetWireBuilder {
             presence_detectors: Box<[Box<str>]>,
             touchscreen_whitebalance: Option<Box<str>>,
             touchscreen_brightness: Option<Box<str>>,
+            circadian: bool,
+            pre_warning: Option<PreWarning>,
+            schedule: Option<TimeSchedule>,
+            constant_light_lux: Option<u32>,
+            all_off_exception: Box<[Box<str>]>,
+            presence_simulation: bool,
+            wake_up: bool,
         }
         impl<'a> DeviceIdxAccessNew<'a> for LightRowContent<'a> {
             fn id_cell<'b>(&'b mut self) -> &'b mut DeviceIdxCell<'a> {
@@ -977,11 +1567,35 @@ impl GoogleSheetWireBuilder {
             .iter()
             .map(|c| c.as_ref())
             .collect::<Vec<_>>();
+        let circadian_columns = light_config.circadian().into_iter().collect::<Vec<_>>();
+        let pre_warning_columns = light_config.pre_warning().into_iter().collect::<Vec<_>>();
+        let pre_warning_brightness_columns = light_config
+            .pre_warning_brightness()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let pre_warning_flash_columns = light_config
+            .pre_warning_flash()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let schedule_columns = light_config.schedule().into_iter().collect::<Vec<_>>();
+        let constant_light_columns = light_config
+            .constant_light()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let all_off_exception_columns = light_config
+            .all_off_exception()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let presence_simulation_columns = light_config
+            .presence_simulation()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let wake_up_columns = light_config.wake_up().into_iter().collect::<Vec<_>>();
         let mut device_ids_of_rooms = HashMap::<_, Vec<_>>::new();
 
         for (
             [room, light_idx, template, address, start_channel, whitebalance, brightness, old_state],
-            [buttons, presence_detectors],
+            [buttons, presence_detectors, circadian, pre_warning, pre_warning_brightness, pre_warning_flash, schedule, constant_light, all_off_exception, presence_simulation, wake_up],
         ) in GoogleTable::connect(
             &context.spreadsheet_methods,
             [
@@ -994,7 +1608,19 @@ impl GoogleSheetWireBuilder {
                 light_config.touchscreen_brightness(),
                 light_config.state(),
             ],
-            [&button_columns, &presence_detector_columns],
+            [
+                &button_columns,
+                &presence_detector_columns,
+                &circadian_columns,
+                &pre_warning_columns,
+                &pre_warning_brightness_columns,
+                &pre_warning_flash_columns,
+                &schedule_columns,
+                &constant_light_columns,
+                &all_off_exception_columns,
+                &presence_simulation_columns,
+                &wake_up_columns,
+            ],
             context.config.spreadsheet_id(),
             light_config.sheet(),
             light_config.range(),
@@ -1053,6 +1679,59 @@ impl GoogleSheetWireBuilder {
                         presence_detectors,
                         touchscreen_whitebalance,
                         touchscreen_brightness,
+                        circadian: circadian
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| !v.is_empty())
+                            .unwrap_or(false),
+                        pre_warning: pre_warning
+                            .first()
+                            .and_then(GoogleCellData::get_integer)
+                            .filter(|seconds| *seconds > 0)
+                            .map(|seconds| PreWarning {
+                                lead_time: Duration::from_secs(seconds as u64),
+                                brightness: pre_warning_brightness
+                                    .first()
+                                    .and_then(GoogleCellData::get_integer)
+                                    .map(|percent| (percent.clamp(0, 100) * 255 / 100) as u8),
+                                flash_time: pre_warning_flash
+                                    .first()
+                                    .and_then(GoogleCellData::get_integer)
+                                    .filter(|millis| *millis > 0)
+                                    .map(|millis| Duration::from_millis(millis as u64))
+                                    .unwrap_or(DEFAULT_PRE_WARNING_FLASH_TIME),
+                            }),
+                        schedule: schedule
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .filter(|name| !name.is_empty())
+                            .and_then(light_schedule),
+                        constant_light_lux: constant_light
+                            .first()
+                            .and_then(GoogleCellData::get_integer)
+                            .filter(|lux| *lux > 0)
+                            .map(|lux| lux as u32),
+                        all_off_exception: all_off_exception
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| {
+                                v.split(',')
+                                    .map(str::trim)
+                                    .filter(|button| !button.is_empty())
+                                    .map(Box::from)
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        presence_simulation: presence_simulation
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| !v.is_empty())
+                            .unwrap_or(false),
+                        wake_up: wake_up
+                            .first()
+                            .and_then(GoogleCellData::get_content)
+                            .map(|v| !v.is_empty())
+                            .unwrap_or(false),
                     });
                 update_state_new(
                     |v| self.updates.push(v),
@@ -1066,6 +1745,12 @@ impl GoogleSheetWireBuilder {
         let light_device_rows = fill_device_idx(|v| self.updates.push(v), device_ids_of_rooms);
 
         for (device_idx, light_row) in light_device_rows {
+            for button in light_row.all_off_exception.iter() {
+                self.all_off_exceptions
+                    .entry(button.clone())
+                    .or_default()
+                    .push(device_idx);
+            }
             let dmx_bricklet_settings = self
                 .dmx_bricklets
                 .entry(light_row.device_address)
@@ -1080,6 +1765,20 @@ impl GoogleSheetWireBuilder {
                 .collect::<Vec<_>>()
                 .into_boxed_slice();
             manual_buttons.sort();
+            let mut single_buttons = light_row
+                .manual_buttons
+                .iter()
+                .flat_map(|name| self.single_button_adresses.get(name))
+                .copied()
+                .collect::<Vec<_>>()
+                .into_boxed_slice();
+            single_buttons.sort();
+            if !manual_buttons.is_empty() && !single_buttons.is_empty() {
+                // two controllers with separate switch off timers would fight over the output
+                warn!("Light {device_idx:?} has dual and single buttons, ignoring the single ones");
+                single_buttons = Box::new([]);
+            }
+            let has_buttons = !manual_buttons.is_empty() || !single_buttons.is_empty();
             let mut presence_detectors = light_row
                 .presence_detectors
                 .iter()
@@ -1088,45 +1787,104 @@ impl GoogleSheetWireBuilder {
                 .collect::<Vec<_>>()
                 .into_boxed_slice();
             presence_detectors.sort();
-            let auto_switch_off_time = if manual_buttons.is_empty() {
+            let auto_switch_off_time = if !has_buttons {
                 Duration::from_secs(2 * 60)
             } else if presence_detectors.is_empty() {
                 Duration::from_secs(2 * 3600)
             } else {
                 Duration::from_secs(3600)
             };
-            match template {
+            let pre_warning = light_row.pre_warning;
+            let schedule = light_row.schedule;
+            let daylight = presence_detectors
+                .iter()
+                .find_map(|key| self.motion_detector_daylight.get(key))
+                .copied();
+            let constant_light_sensor = light_row.constant_light_lux.and_then(|target_lux| {
+                presence_detectors
+                    .iter()
+                    .find_map(|key| self.motion_detector_illuminance.get(key))
+                    .map(|sensor| (*sensor, target_lux))
+            });
+            let transition = template.transition;
+            let curve = template.curve;
+            let dimm_duration = template.dimm_duration;
+            let nominal_power = template.nominal_power;
+            if light_row.wake_up
+                && !matches!(template.kind, LightTemplateTypes::DimmWhitebalance { .. })
+            {
+                warn!("Wake-up light {device_idx:?} needs a template with whitebalance");
+            }
+            match &template.kind {
                 LightTemplateTypes::Switch => {
                     let register = SwitchOutputKey::Light(device_idx);
+                    self.light_outputs
+                        .insert(device_idx, LightOutputs::Switch(register));
+                    if light_row.presence_simulation {
+                        self.presence_simulation_switches.push(register);
+                    }
                     dmx_bricklet_settings.push(DmxConfigEntry::Switch {
                         register,
                         channel: light_row.bus_start_address,
+                        nominal_power,
                     });
-                    if manual_buttons.is_empty() {
+                    if !has_buttons {
                         if !presence_detectors.is_empty() {
                             self.motion_detectors.push(MotionDetector::Switch {
                                 input: presence_detectors,
                                 output: register,
                                 switch_off_time: auto_switch_off_time,
+                                pre_warning,
+                                schedule: schedule.clone(),
+                                daylight,
                             });
                         }
                     } else {
-                        self.dual_input_switches.push(DualInputSwitch {
-                            input: manual_buttons,
-                            output: register,
-                            auto_switch_off_time,
-                            presence: presence_detectors,
-                        });
+                        if !manual_buttons.is_empty() {
+                            self.dual_input_switches.push(DualInputSwitch {
+                                input: manual_buttons,
+                                output: register,
+                                auto_switch_off_time,
+                                presence: presence_detectors.clone(),
+                                pre_warning,
+                            });
+                        }
+                        if !single_buttons.is_empty() {
+                            self.single_input_switches.push(SingleInputSwitch {
+                                input: single_buttons,
+                                output: register,
+                                auto_switch_off_time,
+                                presence: presence_detectors,
+                                pre_warning,
+                            });
+                        }
                     }
                 }
                 LightTemplateTypes::Dimm => {
                     let register = BrightnessKey::Light(device_idx);
+                    self.light_outputs
+                        .insert(device_idx, LightOutputs::Dimm(register));
+                    if light_row.presence_simulation {
+                        self.presence_simulation_dimmers.push(register);
+                    }
                     dmx_bricklet_settings.push(DmxConfigEntry::Dimm {
                         register,
                         channel: light_row.bus_start_address,
+                        transition,
+                        curve,
+                        nominal_power,
                     });
-                    if manual_buttons.is_empty() {
-                        if !presence_detectors.is_empty() {
+                    if let Some((sensor, target_lux)) = constant_light_sensor {
+                        self.constant_lights.push(constant_light(
+                            sensor,
+                            register,
+                            presence_detectors.clone(),
+                            target_lux,
+                        ));
+                    }
+                    if !has_buttons {
+                        // the constant light switches by presence itself
+                        if !presence_detectors.is_empty() && constant_light_sensor.is_none() {
                             self.motion_detectors.push(MotionDetector::Dimmer {
                                 input: presence_detectors,
                                 output: register,
@@ -1135,15 +1893,33 @@ impl GoogleSheetWireBuilder {
                                     .and_then(|k| self.touchscreen_brightness_addresses.get(&k))
                                     .copied(),
                                 switch_off_time: auto_switch_off_time,
+                                pre_warning,
+                                schedule: schedule.clone(),
+                                daylight,
                             });
                         }
                     } else {
-                        self.dual_input_dimmers.push(DualInputDimmer {
-                            input: manual_buttons,
-                            output: register,
-                            auto_switch_off_time,
-                            presence: presence_detectors,
-                        })
+                        if !manual_buttons.is_empty() {
+                            self.dual_input_dimmers.push(DualInputDimmer {
+                                input: manual_buttons,
+                                output: register,
+                                auto_switch_off_time,
+                                presence: presence_detectors.clone(),
+                                dimm_duration,
+                                pre_warning,
+                                schedule: schedule.clone(),
+                            });
+                        }
+                        if !single_buttons.is_empty() {
+                            self.single_input_dimmers.push(SingleInputDimmer {
+                                input: single_buttons,
+                                output: register,
+                                auto_switch_off_time,
+                                presence: presence_detectors,
+                                dimm_duration,
+                                pre_warning,
+                            });
+                        }
                     }
                 }
                 LightTemplateTypes::DimmWhitebalance {
@@ -1161,6 +1937,17 @@ impl GoogleSheetWireBuilder {
                     } else {
                         LightColorKey::Light(device_in_room)
                     };
+                    self.light_outputs.insert(
+                        device_in_room,
+                        LightOutputs::DimmWhitebalance(
+                            output_brightness_register,
+                            whitebalance_register,
+                        ),
+                    );
+                    if light_row.presence_simulation {
+                        self.presence_simulation_dimmers
+                            .push(output_brightness_register);
+                    }
                     dmx_bricklet_settings.push(DmxConfigEntry::DimmWhitebalance {
                         brightness_register: output_brightness_register,
                         whitebalance_register,
@@ -1168,9 +1955,35 @@ impl GoogleSheetWireBuilder {
                         cold_channel: light_row.bus_start_address + 1,
                         warm_mireds: *warm_temperature,
                         cold_mireds: *cold_temperature,
+                        transition,
+                        curve,
+                        nominal_power,
                     });
-                    if manual_buttons.is_empty() {
-                        if !presence_detectors.is_empty() {
+                    if light_row.circadian {
+                        self.circadian_outputs.insert(whitebalance_register);
+                    }
+                    if light_row.wake_up {
+                        self.wake_up_outputs
+                            .entry(device_in_room.room)
+                            .or_default()
+                            .push(WakeUpOutput {
+                                brightness: output_brightness_register,
+                                whitebalance: whitebalance_register,
+                                warm_mireds: *warm_temperature,
+                                cold_mireds: *cold_temperature,
+                            });
+                    }
+                    if let Some((sensor, target_lux)) = constant_light_sensor {
+                        self.constant_lights.push(constant_light(
+                            sensor,
+                            output_brightness_register,
+                            presence_detectors.clone(),
+                            target_lux,
+                        ));
+                    }
+                    if !has_buttons {
+                        // the constant light switches by presence itself
+                        if !presence_detectors.is_empty() && constant_light_sensor.is_none() {
                             self.motion_detectors.push(MotionDetector::Dimmer {
                                 input: presence_detectors,
                                 output: output_brightness_register,
@@ -1179,15 +1992,33 @@ impl GoogleSheetWireBuilder {
                                     .and_then(|k| self.touchscreen_brightness_addresses.get(&k))
                                     .copied(),
                                 switch_off_time: auto_switch_off_time,
+                                pre_warning,
+                                schedule: schedule.clone(),
+                                daylight,
                             });
                         }
                     } else {
-                        self.dual_input_dimmers.push(DualInputDimmer {
-                            input: manual_buttons,
-                            output: output_brightness_register,
-                            auto_switch_off_time,
-                            presence: presence_detectors,
-                        });
+                        if !manual_buttons.is_empty() {
+                            self.dual_input_dimmers.push(DualInputDimmer {
+                                input: manual_buttons,
+                                output: output_brightness_register,
+                                auto_switch_off_time,
+                                presence: presence_detectors.clone(),
+                                dimm_duration,
+                                pre_warning,
+                                schedule: schedule.clone(),
+                            });
+                        }
+                        if !single_buttons.is_empty() {
+                            self.single_input_dimmers.push(SingleInputDimmer {
+                                input: single_buttons,
+                                output: output_brightness_register,
+                                auto_switch_off_time,
+                                presence: presence_detectors,
+                                dimm_duration,
+                                pre_warning,
+                            });
+                        }
                     }
                 }
             }
@@ -1196,25 +2027,140 @@ impl GoogleSheetWireBuilder {
         Ok(())
     }
 
+    async fn parse_scenes<'a>(
+        &mut self,
+        context: &'a ParserContext<'a>,
+    ) -> Result<(), GoogleDataError> {
+        let Some(scene_config) = context.config.scenes() else {
+            return Ok(());
+        };
+        let whitebalance_columns = scene_config.whitebalance().into_iter().collect::<Vec<_>>();
+        let transition_columns = scene_config.transition().into_iter().collect::<Vec<_>>();
+        let mut scenes = BTreeMap::<Box<str>, (Duration, SceneValueLists)>::new();
+        for ([name, room, light_idx, brightness], [whitebalance, transition]) in
+            GoogleTable::connect(
+                &context.spreadsheet_methods,
+                [
+                    scene_config.name(),
+                    scene_config.room_id(),
+                    scene_config.light_idx(),
+                    scene_config.brightness(),
+                ],
+                [&whitebalance_columns, &transition_columns],
+                context.config.spreadsheet_id(),
+                scene_config.sheet(),
+                scene_config.range(),
+            )
+            .await?
+        {
+            let (Some(name), Some(room), Some(idx)) = (
+                name.get_content().filter(|name| !name.is_empty()),
+                room.get_content().map(Room::from_str).and_then(Result::ok),
+                light_idx.get_integer(),
+            ) else {
+                continue;
+            };
+            let device = DeviceInRoom {
+                room,
+                idx: idx as u16,
+            };
+            let Some(outputs) = self.light_outputs.get(&device) else {
+                warn!("Scene {name}: no light {device:?}");
+                continue;
+            };
+            let (scene_transition, values) = scenes.entry(name.into()).or_default();
+            if let Some(millis) = transition.first().and_then(GoogleCellData::get_integer) {
+                *scene_transition =
+                    (*scene_transition).max(Duration::from_millis(millis.max(0) as u64));
+            }
+            let brightness = brightness
+                .get_integer()
+                .map(|percent| (percent.clamp(0, 100) * 255 / 100) as u8);
+            let whitebalance = whitebalance
+                .first()
+                .and_then(GoogleCellData::get_integer)
+                .filter(|kelvin| *kelvin > 0)
+                .and_then(|kelvin| {
+                    let kelvin = u16::try_from(kelvin);
+                    if kelvin.is_err() {
+                        warn!("Scene {name}: whitebalance of {device:?} out of range");
+                    }
+                    kelvin.ok()
+                })
+                .map(kelvin_2_mireds);
+            match *outputs {
+                LightOutputs::Switch(key) => {
+                    if let Some(brightness) = brightness {
+                        values.switches.push(SceneValue {
+                            key,
+                            value: brightness > 0,
+                        });
+                    }
+                }
+                LightOutputs::Dimm(key) => {
+                    if let Some(value) = brightness {
+                        values.brightness.push(SceneValue { key, value });
+                    }
+                }
+                LightOutputs::DimmWhitebalance(brightness_key, light_color_key) => {
+                    if let Some(value) = brightness {
+                        values.brightness.push(SceneValue {
+                            key: brightness_key,
+                            value,
+                        });
+                    }
+                    if let Some(value) = whitebalance {
+                        values.light_colors.push(SceneValue {
+                            key: light_color_key,
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+        for (name, (transition, values)) in scenes {
+            self.scenes.push(Scene {
+                name,
+                transition,
+                values: values.into(),
+            });
+        }
+        Ok(())
+    }
+
     async fn parse_buttons<'a>(
         &mut self,
         context: &'a ParserContext<'a>,
     ) -> Result<(), GoogleDataError> {
         let mut button_template_map = HashMap::<Box<str>, _>::new();
         let button_templates = context.config.button_templates();
-        for ([name, sub_device, discriminator], _) in GoogleTable::connect(
-            &context.spreadsheet_methods,
-            [
-                button_templates.name(),
-                button_templates.sub_devices(),
-                button_templates.discriminator(),
-            ],
-            [],
-            context.config.spreadsheet_id(),
-            button_templates.sheet(),
-            button_templates.range(),
-        )
-        .await?
+        let long_press_columns = button_templates
+            .long_press()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let multi_click_columns = button_templates
+            .multi_click()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let max_press_columns = button_templates.max_press().into_iter().collect::<Vec<_>>();
+        for ([name, sub_device, discriminator], [long_press, multi_click, max_press]) in
+            GoogleTable::connect(
+                &context.spreadsheet_methods,
+                [
+                    button_templates.name(),
+                    button_templates.sub_devices(),
+                    button_templates.discriminator(),
+                ],
+                [
+                    &long_press_columns,
+                    &multi_click_columns,
+                    &max_press_columns,
+                ],
+                context.config.spreadsheet_id(),
+                button_templates.sheet(),
+                button_templates.range(),
+            )
+            .await?
         {
             if let (Some(name), Some(sub_devices), Some(discriminator)) = (
                 name.get_content().map(<&str>::into),
@@ -1233,7 +2179,27 @@ impl GoogleSheetWireBuilder {
                 } else {
                     None
                 } {
-                    button_template_map.insert(name, ButtonTemplateTypes { style, sub_devices });
+                    let default_timing = ButtonTiming::default();
+                    let duration_millis = |cells: &[GoogleCellData], default: Duration| {
+                        cells
+                            .first()
+                            .and_then(GoogleCellData::get_integer)
+                            .map(|ms| Duration::from_millis(ms.max(0) as u64))
+                            .unwrap_or(default)
+                    };
+                    let timing = ButtonTiming {
+                        long_press: duration_millis(&long_press, default_timing.long_press),
+                        multi_click: duration_millis(&multi_click, default_timing.multi_click),
+                        max_press: duration_millis(&max_press, default_timing.max_press),
+                    };
+                    button_template_map.insert(
+                        name,
+                        ButtonTemplateTypes {
+                            style,
+                            sub_devices,
+                            timing,
+                        },
+                    );
                 }
             }
         }
@@ -1337,6 +2303,7 @@ impl GoogleSheetWireBuilder {
                         io_bricklet_settings.push(ButtonSetting::Single {
                             button: current_input_idx,
                             output,
+                            timing: button_row.button_template.timing,
                         });
                         self.single_button_adresses
                             .insert(device_key.into(), output);
@@ -1348,6 +2315,7 @@ impl GoogleSheetWireBuilder {
                             up_button: current_input_idx + 1,
                             down_button: current_input_idx,
                             output,
+                            timing: button_row.button_template.timing,
                         });
                         self.dual_button_adresses.insert(device_key.into(), output);
                         current_input_idx += 2;
@@ -1359,6 +2327,225 @@ impl GoogleSheetWireBuilder {
     }
 }
 
+fn light_schedule(name: &str) -> Option<TimeSchedule> {
+    let Some(settings) = CONFIG.light_schedule(name) else {
+        warn!("Unknown light schedule: {name}");
+        return None;
+    };
+    Some(TimeSchedule {
+        clock: ClockKey {
+            resolution: ClockKeyResolution::Minutes,
+            tz: CONFIG.server.timezone(),
+        },
+        windows: settings
+            .windows()
+            .iter()
+            .map(|window| TimeWindow {
+                start: window.start(),
+                end: window.end(),
+                brightness: window
+                    .brightness_percent()
+                    .map(|percent| (percent.min(100) as u16 * 255 / 100) as u8),
+                switch_off_time: window.switch_off_time(),
+            })
+            .collect(),
+    })
+}
+
+fn constant_light(
+    sensor: IlluminanceKey,
+    output: BrightnessKey,
+    presence: Box<[SingleButtonKey]>,
+    target_lux: u32,
+) -> ConstantLight {
+    let settings = &CONFIG.constant_light;
+    ConstantLight {
+        sensor,
+        output,
+        presence,
+        target_lux,
+        min_brightness: settings.min_brightness(),
+        max_brightness: settings.max_brightness(),
+        vacancy_time: settings.vacancy_time(),
+        ramp_step_time: settings.ramp_step_time(),
+    }
+}
+
+fn ring_controller(
+    input: SingleButtonKey,
+    output: SwitchOutputKey,
+    light_outputs: &HashMap<DeviceInRoom, LightOutputs>,
+) -> RingController {
+    let settings = &CONFIG.doorbell;
+    let flash = settings.flash_light().and_then(|device| {
+        let light = light_outputs.get(&device);
+        if light.is_none() {
+            warn!("Doorbell: no flash light {device:?}");
+        }
+        light.map(|light| match *light {
+            LightOutputs::Switch(key) => FlashLight::Switch { key },
+            LightOutputs::Dimm(key) | LightOutputs::DimmWhitebalance(key, _) => {
+                FlashLight::Dimmer { key }
+            }
+        })
+    });
+    RingController {
+        input,
+        output,
+        pattern: settings.pattern(),
+        debounce: settings.debounce(),
+        quiet_hours: settings.quiet_hours().map(|(start, end)| QuietHours {
+            clock: ClockKey {
+                resolution: ClockKeyResolution::Minutes,
+                tz: CONFIG.server.timezone(),
+            },
+            start,
+            end,
+            flash,
+        }),
+    }
+}
+
+fn presence_simulation(
+    switches: Box<[SwitchOutputKey]>,
+    dimmers: Box<[BrightnessKey]>,
+) -> PresenceSimulation {
+    let settings = &CONFIG.presence_simulation;
+    PresenceSimulation {
+        clock: ClockKey {
+            resolution: ClockKeyResolution::Minutes,
+            tz: CONFIG.server.timezone(),
+        },
+        switches,
+        dimmers,
+        brightness: settings.brightness(),
+        start: settings.start(),
+        end: settings.end(),
+        min_on_time: settings.min_on_time(),
+        max_on_time: settings.max_on_time(),
+        max_off_time: settings.max_off_time(),
+    }
+}
+
+fn circadian_whitebalance(outputs: Box<[LightColorKey]>) -> CircadianWhitebalance {
+    let settings = &CONFIG.circadian;
+    CircadianWhitebalance {
+        clock: ClockKey {
+            resolution: ClockKeyResolution::Minutes,
+            tz: CONFIG.server.timezone(),
+        },
+        outputs,
+        schedule: if let Some((latitude, longitude)) = settings.location() {
+            CircadianSchedule::Sun {
+                latitude: (latitude * 1_000_000.0).round() as i32,
+                longitude: (longitude * 1_000_000.0).round() as i32,
+            }
+        } else {
+            CircadianSchedule::Fixed {
+                sunrise: settings.sunrise(),
+                sunset: settings.sunset(),
+            }
+        },
+        day_mireds: kelvin_2_mireds(settings.day_temperature()),
+        night_mireds: kelvin_2_mireds(settings.night_temperature()),
+        transition: settings.transition(),
+    }
+}
+
+/// Illuminance register of every ambient light sensor, named after the first motion detector
+/// using it, so detectors sharing a sensor share the register
+fn ambient_light_keys(
+    detectors: impl Iterator<Item = (DeviceInRoom, Uid)>,
+) -> BTreeMap<Uid, IlluminanceKey> {
+    let mut keys = BTreeMap::<Uid, IlluminanceKey>::new();
+    for (device_idx, sensor_address) in detectors {
+        let key = keys
+            .entry(sensor_address)
+            .or_insert(IlluminanceKey(device_idx));
+        key.0 = key.0.min(device_idx);
+    }
+    keys
+}
+
+/// Light outputs of a group, sorted to keep the wiring comparable
+/// A window contact stays pressed as long as the window is open, it has no forced release
+fn hold_window_contacts(
+    settings: &mut [ButtonSetting],
+    window_contacts: &HashSet<SingleButtonKey>,
+) {
+    for setting in settings {
+        if let ButtonSetting::Single { output, timing, .. } = setting {
+            if window_contacts.contains(output) {
+                timing.max_press = Duration::ZERO;
+            }
+        }
+    }
+}
+
+fn light_group(
+    key: LightGroupKey,
+    light_outputs: &HashMap<DeviceInRoom, LightOutputs>,
+) -> LightGroup {
+    let mut brightness = Vec::new();
+    let mut switches = Vec::new();
+    for (device, outputs) in light_outputs {
+        if !key.contains(device) {
+            continue;
+        }
+        match outputs {
+            LightOutputs::Switch(switch) => switches.push(*switch),
+            LightOutputs::Dimm(dimm) | LightOutputs::DimmWhitebalance(dimm, _) => {
+                brightness.push(*dimm)
+            }
+        }
+    }
+    brightness.sort();
+    switches.sort();
+    LightGroup {
+        key,
+        brightness: brightness.into_boxed_slice(),
+        switches: switches.into_boxed_slice(),
+    }
+}
+
+/// Wake-up light of a room, every button of the room cancels it
+fn wake_up_light(
+    room: Room,
+    mut outputs: Vec<WakeUpOutput>,
+    single_buttons: &HashMap<Box<str>, SingleButtonKey>,
+    dual_buttons: &HashMap<Box<str>, DualButtonKey>,
+) -> WakeUpLight {
+    let settings = &CONFIG.wake_up;
+    outputs.sort();
+    let mut buttons = single_buttons
+        .values()
+        .filter(|key| matches!(key, SingleButtonKey::Button(button) if button.room == room))
+        .copied()
+        .collect::<Vec<_>>();
+    buttons.sort();
+    buttons.dedup();
+    let mut dual_buttons = dual_buttons
+        .values()
+        .filter(|key| key.0.room == room)
+        .copied()
+        .collect::<Vec<_>>();
+    dual_buttons.sort();
+    dual_buttons.dedup();
+    WakeUpLight {
+        clock: ClockKey {
+            resolution: ClockKeyResolution::Seconds,
+            tz: CONFIG.server.timezone(),
+        },
+        alarm: AlarmTimeKey(room),
+        outputs: outputs.into_boxed_slice(),
+        brightness: settings.brightness(),
+        duration: settings.duration(),
+        weekdays: settings.weekdays(),
+        buttons: buttons.into_boxed_slice(),
+        dual_buttons: dual_buttons.into_boxed_slice(),
+    }
+}
+
 fn show_bool(value: bool) -> serde_json::Value {
     if value {
         "x".into()
@@ -1829,7 +3016,15 @@ mod test {
     use env_logger::Env;
     use log::{error, info};
 
-    use crate::data::google_data::{read_sheet_data, CellCoordinates};
+    use std::collections::HashMap;
+
+    use crate::data::{
+        google_data::{
+            ambient_light_keys, light_group, read_sheet_data, CellCoordinates, LightOutputs,
+        },
+        registry::{BrightnessKey, IlluminanceKey, LightColorKey, LightGroupKey, SwitchOutputKey},
+        DeviceInRoom,
+    };
 
     #[test]
     fn format_coordinates() {
@@ -1847,6 +3042,78 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_shared_ambient_light_sensor() {
+        let device = |idx| DeviceInRoom {
+            room: "1.4".parse().unwrap(),
+            idx,
+        };
+        let shared = "EHc".parse().unwrap();
+        let single = "EHd".parse().unwrap();
+        let keys = ambient_light_keys(
+            [
+                (device(2), shared),
+                (device(1), shared),
+                (device(3), single),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(2, keys.len());
+        assert_eq!(Some(&IlluminanceKey(device(1))), keys.get(&shared));
+        assert_eq!(Some(&IlluminanceKey(device(3))), keys.get(&single));
+    }
+
+    #[test]
+    fn test_light_group() {
+        let device = |room: &str, idx| DeviceInRoom {
+            room: room.parse().unwrap(),
+            idx,
+        };
+        let outputs = HashMap::from([
+            (
+                device("1.4", 0),
+                LightOutputs::Switch(SwitchOutputKey::Light(device("1.4", 0))),
+            ),
+            (
+                device("1.4", 1),
+                LightOutputs::Dimm(BrightnessKey::Light(device("1.4", 1))),
+            ),
+            (
+                device("1.2", 0),
+                LightOutputs::DimmWhitebalance(
+                    BrightnessKey::Light(device("1.2", 0)),
+                    LightColorKey::Light(device("1.2", 0)),
+                ),
+            ),
+            (
+                device("2.1", 0),
+                LightOutputs::Switch(SwitchOutputKey::Light(device("2.1", 0))),
+            ),
+        ]);
+        let room = light_group(LightGroupKey::Room("1.4".parse().unwrap()), &outputs);
+        assert_eq!(
+            [BrightnessKey::Light(device("1.4", 1))].as_slice(),
+            room.brightness.as_ref()
+        );
+        assert_eq!(
+            [SwitchOutputKey::Light(device("1.4", 0))].as_slice(),
+            room.switches.as_ref()
+        );
+        let floor = light_group(LightGroupKey::Floor(1), &outputs);
+        assert_eq!(
+            [
+                BrightnessKey::Light(device("1.2", 0)),
+                BrightnessKey::Light(device("1.4", 1))
+            ]
+            .as_slice(),
+            floor.brightness.as_ref()
+        );
+        assert_eq!(1, floor.switches.len());
+        let house = light_group(LightGroupKey::House, &outputs);
+        assert_eq!(2, house.brightness.len());
+        assert_eq!(2, house.switches.len());
+    }
+
     #[tokio::test]
     async fn test_read_sheet() {
         env_logger::init_from_env(Env::default().filter_or("LOG_LEVEL", "info"));
@@ -15,6 +15,7 @@ mod register;
 pub mod registry;
 pub mod settings;
 pub mod state;
+pub mod usage;
 pub mod wiring;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Ord, PartialOrd)]
@@ -49,6 +49,9 @@ impl<T: Clone + Sync + Send + 'static + PartialEq> Register<T> {
     pub fn sender(&self) -> mpsc::Sender<T> {
         self.tx.clone()
     }
+    pub fn subscribe(&self) -> watch::Receiver<T> {
+        self.rx.clone()
+    }
     pub fn current_value(&self) -> T {
         self.rx.borrow().clone()
     }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Timelike, Utc};
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
 use chrono_tz::Tz;
 use log::error;
 use serde::{Deserialize, Serialize};
@@ -6,10 +6,18 @@ use std::{
     cmp::Ordering, collections::HashMap, future::Future, hash::Hash, num::Saturating,
     ops::DerefMut, sync::Arc, time::Duration,
 };
-use tokio::{sync::mpsc::Sender, sync::Mutex, time::sleep};
-use tokio_stream::Stream;
+use tokio::{
+    sync::{broadcast, mpsc::Sender, Mutex},
+    time::sleep,
+};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 
-use crate::data::{register::Register, DeviceInRoom, SubDeviceInRoom};
+use crate::data::{
+    register::Register,
+    usage::{OutputUsageInfo, UsageOutput, UsageSnapshot, UsageTracker},
+    wiring::LightGroup,
+    DeviceInRoom, Room, SubDeviceInRoom,
+};
 
 pub trait TypedKey {
     type Value;
@@ -66,8 +74,170 @@ pub enum SwitchOutputKey {
     Light(DeviceInRoom),
     Heat(DeviceInRoom),
     Bell(DeviceInRoom),
+    /// relay without a dedicated controller
+    Relay(DeviceInRoom),
+    /// motor relays of a shutter, never restored from a snapshot
+    ShutterUp(DeviceInRoom),
+    ShutterDown(DeviceInRoom),
+    /// ventilation fan switched by the humidity
+    Fan(DeviceInRoom),
 }
 
+/// Intermediate signal between logic blocks, not persisted
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct LogicKey(pub u16);
+
+/// Estimated position of a shutter in percent, 0 is open and 100 closed
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct ShutterPositionKey(pub DeviceInRoom);
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct WindowOpenKey(pub DeviceInRoom);
+
+/// Wake-up time of a room, none while the alarm is off
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct AlarmTimeKey(pub Room);
+
+/// All lights of a room, a floor or the whole house
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub enum LightGroupKey {
+    House,
+    Floor(i16),
+    Room(Room),
+}
+
+impl LightGroupKey {
+    /// a room narrows down a floor, neither selects the whole house
+    pub fn from_location(room: Option<Room>, floor: Option<i16>) -> Self {
+        match (room, floor) {
+            (Some(room), _) => LightGroupKey::Room(room),
+            (None, Some(floor)) => LightGroupKey::Floor(floor),
+            (None, None) => LightGroupKey::House,
+        }
+    }
+    pub fn contains(&self, device: &DeviceInRoom) -> bool {
+        match self {
+            LightGroupKey::House => true,
+            LightGroupKey::Floor(floor) => device.room.floor == *floor,
+            LightGroupKey::Room(room) => device.room == *room,
+        }
+    }
+}
+
+/// Senders to every light of a group
+pub struct LightGroupSenders {
+    pub brightness: Vec<(BrightnessKey, Sender<Saturating<u8>>)>,
+    pub switches: Vec<(SwitchOutputKey, Sender<bool>)>,
+}
+
+impl LightGroupSenders {
+    /// sets the brightness of every dimmer, switches turn on above zero
+    pub async fn send_brightness(&self, brightness: Saturating<u8>) -> usize {
+        let mut count = 0;
+        for (key, sender) in &self.brightness {
+            match sender.send(brightness).await {
+                Ok(()) => count += 1,
+                Err(error) => error!("Cannot send brightness to {key:?}: {error}"),
+            }
+        }
+        for (key, sender) in &self.switches {
+            match sender.send(brightness.0 > 0).await {
+                Ok(()) => count += 1,
+                Err(error) => error!("Cannot switch {key:?}: {error}"),
+            }
+        }
+        count
+    }
+}
+
+/// Target values of a scene
+#[derive(Clone, Debug, Default, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct SceneValues {
+    #[serde(default)]
+    pub brightness: Box<[SceneValue<BrightnessKey, u8>]>,
+    /// whitebalance in mireds
+    #[serde(default)]
+    pub light_colors: Box<[SceneValue<LightColorKey, u16>]>,
+    #[serde(default)]
+    pub switches: Box<[SceneValue<SwitchOutputKey, bool>]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
+pub struct SceneValue<K, V> {
+    pub key: K,
+    pub value: V,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SceneCommand {
+    /// applies a scene, the transition overrides the one of the scene
+    Recall {
+        scene: Box<str>,
+        transition: Option<Duration>,
+    },
+    /// stores the current values of all lights of a group as scene
+    Capture {
+        scene: Box<str>,
+        group: LightGroupKey,
+    },
+}
+
+/// Published on every accepted press of a doorbell button
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RingEvent {
+    pub button: SingleButtonKey,
+    pub bell: SwitchOutputKey,
+    pub time: DateTime<Utc>,
+    /// the bell stayed silent because of quiet hours
+    pub quiet: bool,
+}
+
+/// Sender of an output together with its value at the time of the lookup
+pub struct SceneOutput<T> {
+    pub sender: Sender<T>,
+    pub current: T,
+    pub target: T,
+}
+
+/// All outputs of a scene, collected under one lock
+pub struct SceneOutputs {
+    pub brightness: Vec<SceneOutput<Saturating<u8>>>,
+    pub light_colors: Vec<SceneOutput<Saturating<u16>>>,
+    pub switches: Vec<SceneOutput<bool>>,
+}
+
+/// Presence of the residents, controllers save energy while nobody is at home
+#[derive(
+    Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize, Ord, PartialOrd,
+)]
+pub enum HouseMode {
+    #[default]
+    Home,
+    /// motion detectors stay off, heating at eco temperature, presence simulation runs
+    Away,
+    /// like away, heating only protects against frost
+    Vacation,
+}
+
+impl HouseMode {
+    /// Mode following in the cycle of the LCD button
+    pub fn next(self) -> Self {
+        match self {
+            HouseMode::Home => HouseMode::Away,
+            HouseMode::Away => HouseMode::Vacation,
+            HouseMode::Vacation => HouseMode::Home,
+        }
+    }
+}
+
+/// Illuminance in lux measured by an ambient light sensor
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct IlluminanceKey(pub DeviceInRoom);
+
+/// Relative humidity in percent measured by a humidity sensor
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
+pub struct HumidityKey(pub DeviceInRoom);
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
 pub struct DualButtonKey(pub SubDeviceInRoom);
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
@@ -81,6 +251,10 @@ pub enum ButtonState<B: Copy + Clone + Eq + Hash> {
     Released,
     ShortPressStart(B),
     LongPressStart(B),
+    /// second press within the multi click interval of the button template
+    DoubleClickStart(B),
+    /// third and any further press within the multi click interval
+    TripleClickStart(B),
 }
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Ord, PartialOrd)]
 pub enum DualButtonLayout {
@@ -138,6 +312,16 @@ pub struct ValueSnapshots {
     light_colors: HashMap<LightColorKey, u16>,
     brightness: HashMap<BrightnessKey, u8>,
     output_switch: HashMap<SwitchOutputKey, bool>,
+    #[serde(default)]
+    scenes: HashMap<Box<str>, SceneValues>,
+    #[serde(default)]
+    house_mode: HouseMode,
+    #[serde(default)]
+    shutter_positions: HashMap<ShutterPositionKey, u8>,
+    #[serde(default)]
+    usage: UsageSnapshot,
+    #[serde(default)]
+    alarm_times: HashMap<AlarmTimeKey, NaiveTime>,
 }
 
 struct InnerEventRegistry {
@@ -149,6 +333,17 @@ struct InnerEventRegistry {
     dual_buttons: HashMap<DualButtonKey, Register<ButtonState<DualButtonLayout>>>,
     buttons: HashMap<SingleButtonKey, Register<ButtonState<SingleButtonLayout>>>,
     output_switch: HashMap<SwitchOutputKey, Register<bool>>,
+    window_open: HashMap<WindowOpenKey, Register<bool>>,
+    illuminance: HashMap<IlluminanceKey, Register<u32>>,
+    humidity: HashMap<HumidityKey, Register<f32>>,
+    scene_commands: broadcast::Sender<SceneCommand>,
+    ring_events: broadcast::Sender<RingEvent>,
+    captured_scenes: HashMap<Box<str>, SceneValues>,
+    house_mode: Register<HouseMode>,
+    shutter_positions: HashMap<ShutterPositionKey, Register<u8>>,
+    logic: HashMap<LogicKey, Register<bool>>,
+    usage: UsageTracker,
+    alarm_times: HashMap<AlarmTimeKey, Register<Option<NaiveTime>>>,
 }
 
 impl InnerEventRegistry {
@@ -174,6 +369,19 @@ impl InnerEventRegistry {
                 .iter()
                 .map(|(key, register)| (*key, register.current_value()))
                 .collect(),
+            scenes: self.captured_scenes.clone(),
+            house_mode: self.house_mode.current_value(),
+            shutter_positions: self
+                .shutter_positions
+                .iter()
+                .map(|(key, register)| (*key, register.current_value()))
+                .collect(),
+            usage: self.usage.snapshot(),
+            alarm_times: self
+                .alarm_times
+                .iter()
+                .filter_map(|(key, register)| Some((*key, register.current_value()?)))
+                .collect(),
         }
     }
     fn temperature_register(&mut self, key: TemperatureKey) -> &mut Register<f32> {
@@ -199,12 +407,21 @@ impl InnerEventRegistry {
         })
     }
     fn brightness_register(&mut self, key: BrightnessKey) -> &mut Register<Saturating<u8>> {
+        let usage = &self.usage;
         self.brightness_color.entry(key).or_insert_with(|| {
             let option = self.default_values.brightness.get(&key).copied();
-            Register::new(Saturating(option.unwrap_or(match key {
+            let register = Register::new(Saturating(option.unwrap_or(match key {
                 BrightnessKey::Light(_) => 0,
                 BrightnessKey::TouchscreenController(_) => 255,
-            })))
+            })));
+            if let BrightnessKey::Light(_) = key {
+                usage.track(
+                    UsageOutput::Light(key),
+                    register.subscribe(),
+                    |brightness| brightness.0 as f64 / u8::MAX as f64,
+                );
+            }
+            register
         })
     }
     fn dual_button_register(
@@ -221,16 +438,59 @@ impl InnerEventRegistry {
     }
 
     fn switch_register(&mut self, key: SwitchOutputKey) -> &mut Register<bool> {
+        let usage = &self.usage;
         self.output_switch.entry(key).or_insert_with(|| {
+            let register = match key {
+                // a restored motor relay would run without a controller stopping it
+                SwitchOutputKey::ShutterUp(_) | SwitchOutputKey::ShutterDown(_) => {
+                    Register::new(false)
+                }
+                _ => Register::new(
+                    self.default_values
+                        .output_switch
+                        .get(&key)
+                        .copied()
+                        .unwrap_or_default(),
+                ),
+            };
+            usage.track(UsageOutput::Switch(key), register.subscribe(), |on| {
+                if *on {
+                    1.0
+                } else {
+                    0.0
+                }
+            });
+            register
+        })
+    }
+    fn logic_register(&mut self, key: LogicKey) -> &mut Register<bool> {
+        self.logic.entry(key).or_default()
+    }
+    fn shutter_position_register(&mut self, key: ShutterPositionKey) -> &mut Register<u8> {
+        self.shutter_positions.entry(key).or_insert_with(|| {
             Register::new(
                 self.default_values
-                    .output_switch
+                    .shutter_positions
                     .get(&key)
                     .copied()
                     .unwrap_or_default(),
             )
         })
     }
+    fn alarm_time_register(&mut self, key: AlarmTimeKey) -> &mut Register<Option<NaiveTime>> {
+        self.alarm_times
+            .entry(key)
+            .or_insert_with(|| Register::new(self.default_values.alarm_times.get(&key).copied()))
+    }
+    fn window_open_register(&mut self, key: WindowOpenKey) -> &mut Register<bool> {
+        self.window_open.entry(key).or_default()
+    }
+    fn illuminance_register(&mut self, key: IlluminanceKey) -> &mut Register<u32> {
+        self.illuminance.entry(key).or_default()
+    }
+    fn humidity_register(&mut self, key: HumidityKey) -> &mut Register<f32> {
+        self.humidity.entry(key).or_default()
+    }
     fn clock(&mut self, clock_key: ClockKey) -> &mut Register<DateTime<Tz>> {
         self.clock_registers
             .entry(clock_key)
@@ -275,6 +535,9 @@ impl EventRegistry {
     pub async fn take_snapshot(&self) -> ValueSnapshots {
         self.inner.lock().await.take_snapshot()
     }
+    pub async fn output_usage(&self) -> Vec<OutputUsageInfo> {
+        self.inner.lock().await.usage.usage()
+    }
     pub async fn clock(&self, key: ClockKey) -> impl Stream<Item = DateTime<Tz>> {
         self.inner
             .lock()
@@ -403,12 +666,265 @@ impl EventRegistry {
             .switch_register(switch_output_key)
             .sender()
     }
+    /// senders to the lights of the group, except the listed devices
+    pub async fn light_group_senders(
+        &self,
+        group: &LightGroup,
+        exceptions: &[DeviceInRoom],
+    ) -> LightGroupSenders {
+        let mut inner = self.inner.lock().await;
+        let excepted = |device: &DeviceInRoom| exceptions.contains(device);
+        LightGroupSenders {
+            brightness: group
+                .brightness
+                .iter()
+                .filter(|key| !matches!(key, BrightnessKey::Light(device) if excepted(device)))
+                .map(|key| (*key, inner.brightness_register(*key).sender()))
+                .collect(),
+            switches: group
+                .switches
+                .iter()
+                .filter(|key| !matches!(key, SwitchOutputKey::Light(device) if excepted(device)))
+                .map(|key| (*key, inner.switch_register(*key).sender()))
+                .collect(),
+        }
+    }
+    pub async fn scene_command_stream(&self) -> impl Stream<Item = SceneCommand> {
+        BroadcastStream::new(self.inner.lock().await.scene_commands.subscribe())
+            .filter_map(Result::ok)
+    }
+    pub async fn scene_command_sender(&self) -> broadcast::Sender<SceneCommand> {
+        self.inner.lock().await.scene_commands.clone()
+    }
+    pub async fn ring_event_stream(&self) -> impl Stream<Item = RingEvent> {
+        BroadcastStream::new(self.inner.lock().await.ring_events.subscribe()).filter_map(Result::ok)
+    }
+    pub async fn ring_event_sender(&self) -> broadcast::Sender<RingEvent> {
+        self.inner.lock().await.ring_events.clone()
+    }
+    /// current values of all lights of a group
+    pub async fn capture_scene(&self, group: LightGroupKey) -> SceneValues {
+        let inner = self.inner.lock().await;
+        let mut brightness = inner
+            .brightness_color
+            .iter()
+            .filter_map(|(key, register)| match key {
+                BrightnessKey::Light(device) if group.contains(device) => Some(SceneValue {
+                    key: *key,
+                    value: register.current_value().0,
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        brightness.sort();
+        let mut light_colors = inner
+            .light_color_registers
+            .iter()
+            .filter_map(|(key, register)| match key {
+                LightColorKey::Light(device) | LightColorKey::TouchscreenController(device)
+                    if group.contains(device) =>
+                {
+                    Some(SceneValue {
+                        key: *key,
+                        value: register.current_value().0,
+                    })
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        light_colors.sort();
+        let mut switches = inner
+            .output_switch
+            .iter()
+            .filter_map(|(key, register)| match key {
+                SwitchOutputKey::Light(device) if group.contains(device) => Some(SceneValue {
+                    key: *key,
+                    value: register.current_value(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        switches.sort();
+        SceneValues {
+            brightness: brightness.into_boxed_slice(),
+            light_colors: light_colors.into_boxed_slice(),
+            switches: switches.into_boxed_slice(),
+        }
+    }
+    pub async fn store_scene(&self, name: Box<str>, values: SceneValues) {
+        self.inner.lock().await.captured_scenes.insert(name, values);
+    }
+    pub async fn captured_scene(&self, name: &str) -> Option<SceneValues> {
+        self.inner.lock().await.captured_scenes.get(name).cloned()
+    }
+    pub async fn scene_outputs(&self, values: &SceneValues) -> SceneOutputs {
+        let mut inner = self.inner.lock().await;
+        SceneOutputs {
+            brightness: values
+                .brightness
+                .iter()
+                .map(|SceneValue { key, value }| {
+                    let register = inner.brightness_register(*key);
+                    SceneOutput {
+                        sender: register.sender(),
+                        current: register.current_value(),
+                        target: Saturating(*value),
+                    }
+                })
+                .collect(),
+            light_colors: values
+                .light_colors
+                .iter()
+                .map(|SceneValue { key, value }| {
+                    let register = inner.light_color_register(*key);
+                    SceneOutput {
+                        sender: register.sender(),
+                        current: register.current_value(),
+                        target: Saturating(*value),
+                    }
+                })
+                .collect(),
+            switches: values
+                .switches
+                .iter()
+                .map(|SceneValue { key, value }| {
+                    let register = inner.switch_register(*key);
+                    SceneOutput {
+                        sender: register.sender(),
+                        current: register.current_value(),
+                        target: *value,
+                    }
+                })
+                .collect(),
+        }
+    }
+    pub async fn house_mode_stream(&self) -> impl Stream<Item = HouseMode> {
+        self.inner.lock().await.house_mode.stream().await
+    }
+    pub async fn house_mode_sender(&self) -> Sender<HouseMode> {
+        self.inner.lock().await.house_mode.sender()
+    }
+    pub async fn house_mode(&self) -> HouseMode {
+        self.inner.lock().await.house_mode.current_value()
+    }
+    pub async fn window_open_stream(
+        &self,
+        window_open_key: WindowOpenKey,
+    ) -> impl Stream<Item = bool> {
+        self.inner
+            .lock()
+            .await
+            .window_open_register(window_open_key)
+            .stream()
+            .await
+    }
+    pub async fn window_open_sender(&self, window_open_key: WindowOpenKey) -> Sender<bool> {
+        self.inner
+            .lock()
+            .await
+            .window_open_register(window_open_key)
+            .sender()
+    }
+    pub async fn illuminance_stream(
+        &self,
+        illuminance_key: IlluminanceKey,
+    ) -> impl Stream<Item = u32> {
+        self.inner
+            .lock()
+            .await
+            .illuminance_register(illuminance_key)
+            .stream()
+            .await
+    }
+    pub async fn illuminance_sender(&self, illuminance_key: IlluminanceKey) -> Sender<u32> {
+        self.inner
+            .lock()
+            .await
+            .illuminance_register(illuminance_key)
+            .sender()
+    }
+    pub async fn humidity_stream(&self, humidity_key: HumidityKey) -> impl Stream<Item = f32> {
+        self.inner
+            .lock()
+            .await
+            .humidity_register(humidity_key)
+            .stream()
+            .await
+    }
+    pub async fn humidity_sender(&self, humidity_key: HumidityKey) -> Sender<f32> {
+        self.inner
+            .lock()
+            .await
+            .humidity_register(humidity_key)
+            .sender()
+    }
+    pub async fn logic_stream(&self, logic_key: LogicKey) -> impl Stream<Item = bool> {
+        self.inner
+            .lock()
+            .await
+            .logic_register(logic_key)
+            .stream()
+            .await
+    }
+    pub async fn logic_sender(&self, logic_key: LogicKey) -> Sender<bool> {
+        self.inner.lock().await.logic_register(logic_key).sender()
+    }
+    pub async fn shutter_position_stream(
+        &self,
+        position_key: ShutterPositionKey,
+    ) -> impl Stream<Item = u8> {
+        self.inner
+            .lock()
+            .await
+            .shutter_position_register(position_key)
+            .stream()
+            .await
+    }
+    pub async fn shutter_position_sender(&self, position_key: ShutterPositionKey) -> Sender<u8> {
+        self.inner
+            .lock()
+            .await
+            .shutter_position_register(position_key)
+            .sender()
+    }
+    pub async fn alarm_time_stream(
+        &self,
+        alarm_time_key: AlarmTimeKey,
+    ) -> impl Stream<Item = Option<NaiveTime>> {
+        self.inner
+            .lock()
+            .await
+            .alarm_time_register(alarm_time_key)
+            .stream()
+            .await
+    }
+    pub async fn alarm_time_sender(
+        &self,
+        alarm_time_key: AlarmTimeKey,
+    ) -> Sender<Option<NaiveTime>> {
+        self.inner
+            .lock()
+            .await
+            .alarm_time_register(alarm_time_key)
+            .sender()
+    }
+    pub async fn alarm_time(&self, alarm_time_key: AlarmTimeKey) -> Option<NaiveTime> {
+        self.inner
+            .lock()
+            .await
+            .alarm_time_register(alarm_time_key)
+            .current_value()
+    }
 }
 
 impl InnerEventRegistry {
     fn new(default_values: Option<ValueSnapshots>) -> Self {
+        let default_values = default_values.unwrap_or_default();
         Self {
-            default_values: default_values.unwrap_or_default(),
+            captured_scenes: default_values.scenes.clone(),
+            house_mode: Register::new(default_values.house_mode),
+            usage: UsageTracker::new(&default_values.usage),
+            default_values,
             clock_registers: Default::default(),
             temperature_registers: Default::default(),
             light_color_registers: Default::default(),
@@ -416,6 +932,14 @@ impl InnerEventRegistry {
             dual_buttons: Default::default(),
             buttons: Default::default(),
             output_switch: Default::default(),
+            window_open: Default::default(),
+            illuminance: Default::default(),
+            humidity: Default::default(),
+            shutter_positions: Default::default(),
+            logic: Default::default(),
+            alarm_times: Default::default(),
+            scene_commands: broadcast::channel(16).0,
+            ring_events: broadcast::channel(16).0,
         }
     }
 }
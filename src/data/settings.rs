@@ -1,6 +1,9 @@
 use std::io;
 use std::net::{IpAddr, Ipv6Addr};
+use std::time::Duration;
 
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
 use config::{Config, ConfigError, Environment, File};
 use google_sheets4::yup_oauth2;
 use lazy_static::lazy_static;
@@ -8,6 +11,12 @@ use log::warn;
 use serde::Deserialize;
 use thiserror::Error;
 
+use crate::data::{
+    registry::{HouseMode, LightGroupKey},
+    wiring::Weekdays,
+    DeviceInRoom, Room,
+};
+
 #[derive(Deserialize, Debug)]
 pub struct ServerSettings {
     port: Option<u16>,
@@ -15,6 +24,111 @@ pub struct ServerSettings {
     bind_address: Option<IpAddr>,
     setup_file: Option<Box<str>>,
     state_file: Option<Box<str>>,
+    logic_file: Option<Box<str>>,
+    timezone: Option<Tz>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct HeatingSettings {
+    frost_temperature: Option<f32>,
+    eco_temperature: Option<f32>,
+    alarm_temperature_change: Option<f32>,
+    alarm_settle_minutes: Option<u64>,
+    window_temperature_drop: Option<f32>,
+    window_drop_minutes: Option<u64>,
+    window_suspend_minutes: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CircadianSettings {
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    sunrise: Option<NaiveTime>,
+    sunset: Option<NaiveTime>,
+    day_temperature: Option<u16>,
+    night_temperature: Option<u16>,
+    transition_minutes: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ConstantLightSettings {
+    min_brightness_percent: Option<u8>,
+    max_brightness_percent: Option<u8>,
+    vacancy_minutes: Option<u64>,
+    ramp_step_millis: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LightScheduleSettings {
+    name: Box<str>,
+    windows: Box<[TimeWindowSettings]>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TimeWindowSettings {
+    start: NaiveTime,
+    end: NaiveTime,
+    brightness_percent: Option<u8>,
+    switch_off_minutes: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AllOffSettings {
+    button: Box<str>,
+    floor: Option<i16>,
+    room: Option<Room>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PresenceSimulationSettings {
+    start: Option<NaiveTime>,
+    end: Option<NaiveTime>,
+    min_on_minutes: Option<u64>,
+    max_on_minutes: Option<u64>,
+    max_off_minutes: Option<u64>,
+    brightness_percent: Option<u8>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DoorbellSettings {
+    pattern_millis: Option<Box<[u64]>>,
+    debounce_seconds: Option<u64>,
+    quiet_start: Option<NaiveTime>,
+    quiet_end: Option<NaiveTime>,
+    flash_room: Option<Room>,
+    flash_idx: Option<u16>,
+    webhook: Option<Box<str>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct WakeUpSettings {
+    duration_minutes: Option<u64>,
+    brightness_percent: Option<u8>,
+    weekdays: Option<Box<[Weekday]>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct VentilationSettings {
+    rise_percent: Option<f32>,
+    baseline_minutes: Option<u64>,
+    run_on_minutes: Option<u64>,
+    max_runtime_minutes: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HouseModeButtonSettings {
+    button: Box<str>,
+    mode: HouseMode,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SceneButtonSettings {
+    button: Box<str>,
+    scene: Box<str>,
+    #[serde(default)]
+    capture: bool,
+    floor: Option<i16>,
+    room: Option<Room>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -57,6 +171,9 @@ pub struct GoogleSheet {
     motion_detectors: GoogleMotionDetectors,
     relays: GoogleRelay,
     available_bricklets: GoogleAvailableBricklets,
+    scenes: Option<GoogleScenes>,
+    shutters: Option<GoogleShutters>,
+    fans: Option<GoogleFans>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -88,6 +205,9 @@ pub struct GoogleButtonTemplate {
     name: Box<str>,
     discriminator: Box<str>,
     sub_devices: Box<str>,
+    long_press: Option<Box<str>>,
+    multi_click: Option<Box<str>>,
+    max_press: Option<Box<str>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -98,6 +218,13 @@ pub struct GoogleLightTemplateData {
     discriminator_column: Box<str>,
     temperature_warm_column: Box<str>,
     temperature_cold_column: Box<str>,
+    fade_in_column: Option<Box<str>>,
+    fade_out_column: Option<Box<str>>,
+    color_transition_column: Option<Box<str>>,
+    dimm_curve_column: Option<Box<str>>,
+    dimm_duration_column: Option<Box<str>>,
+    /// nominal power per channel in W
+    wattage_column: Option<Box<str>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -115,6 +242,16 @@ pub struct GoogleLightData {
     touchscreen_whitebalance: Box<str>,
     touchscreen_brightness: Box<str>,
     state: Box<str>,
+    circadian: Option<Box<str>>,
+    pre_warning: Option<Box<str>>,
+    pre_warning_brightness: Option<Box<str>>,
+    pre_warning_flash: Option<Box<str>>,
+    schedule: Option<Box<str>>,
+    constant_light: Option<Box<str>>,
+    /// comma separated names of the all off buttons leaving the light on
+    all_off_exception: Option<Box<str>>,
+    presence_simulation: Option<Box<str>>,
+    wake_up: Option<Box<str>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -132,6 +269,48 @@ pub struct GoogleRoomController {
     enable_brightness_control: Box<str>,
     touchscreen_state: Box<str>,
     temperature_state: Box<str>,
+    window_contact: Option<Box<str>>,
+    /// non-empty cell detects an open window by a fast temperature drop
+    window_drop_detection: Option<Box<str>>,
+    scene: Option<Box<str>>,
+    house_mode: Option<Box<str>>,
+    wake_up: Option<Box<str>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleScenes {
+    sheet: Box<str>,
+    range: Box<str>,
+    name: Box<str>,
+    room_id: Box<str>,
+    light_idx: Box<str>,
+    brightness: Box<str>,
+    whitebalance: Option<Box<str>>,
+    transition: Option<Box<str>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleShutters {
+    sheet: Box<str>,
+    range: Box<str>,
+    room_id: Box<str>,
+    idx: Box<str>,
+    device_address: Box<str>,
+    up_channel: Box<str>,
+    down_channel: Box<str>,
+    button: Box<str>,
+    travel_time: Box<str>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleFans {
+    sheet: Box<str>,
+    range: Box<str>,
+    room_id: Box<str>,
+    idx: Box<str>,
+    device_address: Box<str>,
+    channel: Box<str>,
+    humidity_sensor: Box<str>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -143,6 +322,8 @@ pub struct GoogleMotionDetectors {
     id: Box<str>,
     idx: Box<str>,
     state: Box<str>,
+    ambient_light_sensor: Option<Box<str>>,
+    daylight_lux: Option<Box<str>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -156,6 +337,8 @@ pub struct GoogleRelay {
     device_channel: Box<str>,
     temperature_sensor: Box<str>,
     ring_button: Box<str>,
+    /// non-empty cell makes the relay an output for logic blocks
+    logic_output: Option<Box<str>>,
     state: Box<str>,
 }
 
@@ -243,6 +426,16 @@ impl GoogleSheet {
     pub fn available_bricklets(&self) -> &GoogleAvailableBricklets {
         &self.available_bricklets
     }
+
+    pub fn scenes(&self) -> Option<&GoogleScenes> {
+        self.scenes.as_ref()
+    }
+    pub fn shutters(&self) -> Option<&GoogleShutters> {
+        self.shutters.as_ref()
+    }
+    pub fn fans(&self) -> Option<&GoogleFans> {
+        self.fans.as_ref()
+    }
 }
 
 impl GoogleEndpointData {
@@ -283,6 +476,24 @@ impl GoogleLightTemplateData {
     pub fn temperature_cold_column(&self) -> &str {
         &self.temperature_cold_column
     }
+    pub fn fade_in_column(&self) -> Option<&str> {
+        self.fade_in_column.as_deref()
+    }
+    pub fn fade_out_column(&self) -> Option<&str> {
+        self.fade_out_column.as_deref()
+    }
+    pub fn color_transition_column(&self) -> Option<&str> {
+        self.color_transition_column.as_deref()
+    }
+    pub fn dimm_curve_column(&self) -> Option<&str> {
+        self.dimm_curve_column.as_deref()
+    }
+    pub fn dimm_duration_column(&self) -> Option<&str> {
+        self.dimm_duration_column.as_deref()
+    }
+    pub fn wattage_column(&self) -> Option<&str> {
+        self.wattage_column.as_deref()
+    }
 }
 
 impl GoogleLightData {
@@ -327,6 +538,34 @@ impl GoogleLightData {
     pub fn state(&self) -> &str {
         &self.state
     }
+    pub fn circadian(&self) -> Option<&str> {
+        self.circadian.as_deref()
+    }
+    pub fn pre_warning(&self) -> Option<&str> {
+        self.pre_warning.as_deref()
+    }
+    pub fn pre_warning_brightness(&self) -> Option<&str> {
+        self.pre_warning_brightness.as_deref()
+    }
+    /// milliseconds a switched light is off to flash
+    pub fn pre_warning_flash(&self) -> Option<&str> {
+        self.pre_warning_flash.as_deref()
+    }
+    pub fn schedule(&self) -> Option<&str> {
+        self.schedule.as_deref()
+    }
+    pub fn constant_light(&self) -> Option<&str> {
+        self.constant_light.as_deref()
+    }
+    pub fn all_off_exception(&self) -> Option<&str> {
+        self.all_off_exception.as_deref()
+    }
+    pub fn presence_simulation(&self) -> Option<&str> {
+        self.presence_simulation.as_deref()
+    }
+    pub fn wake_up(&self) -> Option<&str> {
+        self.wake_up.as_deref()
+    }
 }
 
 impl GoogleButtonData {
@@ -376,6 +615,15 @@ impl GoogleButtonTemplate {
     pub fn sub_devices(&self) -> &str {
         &self.sub_devices
     }
+    pub fn long_press(&self) -> Option<&str> {
+        self.long_press.as_deref()
+    }
+    pub fn multi_click(&self) -> Option<&str> {
+        self.multi_click.as_deref()
+    }
+    pub fn max_press(&self) -> Option<&str> {
+        self.max_press.as_deref()
+    }
 }
 
 impl GoogleRoomController {
@@ -422,6 +670,102 @@ impl GoogleRoomController {
     pub fn temperature_state(&self) -> &str {
         &self.temperature_state
     }
+    pub fn window_contact(&self) -> Option<&str> {
+        self.window_contact.as_deref()
+    }
+    pub fn window_drop_detection(&self) -> Option<&str> {
+        self.window_drop_detection.as_deref()
+    }
+    pub fn scene(&self) -> Option<&str> {
+        self.scene.as_deref()
+    }
+    pub fn house_mode(&self) -> Option<&str> {
+        self.house_mode.as_deref()
+    }
+    pub fn wake_up(&self) -> Option<&str> {
+        self.wake_up.as_deref()
+    }
+}
+
+impl GoogleScenes {
+    pub fn sheet(&self) -> &str {
+        &self.sheet
+    }
+    pub fn range(&self) -> &str {
+        &self.range
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+    pub fn light_idx(&self) -> &str {
+        &self.light_idx
+    }
+    pub fn brightness(&self) -> &str {
+        &self.brightness
+    }
+    pub fn whitebalance(&self) -> Option<&str> {
+        self.whitebalance.as_deref()
+    }
+    pub fn transition(&self) -> Option<&str> {
+        self.transition.as_deref()
+    }
+}
+
+impl GoogleShutters {
+    pub fn sheet(&self) -> &str {
+        &self.sheet
+    }
+    pub fn range(&self) -> &str {
+        &self.range
+    }
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+    pub fn idx(&self) -> &str {
+        &self.idx
+    }
+    pub fn device_address(&self) -> &str {
+        &self.device_address
+    }
+    pub fn up_channel(&self) -> &str {
+        &self.up_channel
+    }
+    pub fn down_channel(&self) -> &str {
+        &self.down_channel
+    }
+    pub fn button(&self) -> &str {
+        &self.button
+    }
+    pub fn travel_time(&self) -> &str {
+        &self.travel_time
+    }
+}
+
+impl GoogleFans {
+    pub fn sheet(&self) -> &str {
+        &self.sheet
+    }
+    pub fn range(&self) -> &str {
+        &self.range
+    }
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+    pub fn idx(&self) -> &str {
+        &self.idx
+    }
+    pub fn device_address(&self) -> &str {
+        &self.device_address
+    }
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+    pub fn humidity_sensor(&self) -> &str {
+        &self.humidity_sensor
+    }
 }
 
 impl GoogleMotionDetectors {
@@ -446,6 +790,12 @@ impl GoogleMotionDetectors {
     pub fn state(&self) -> &str {
         &self.state
     }
+    pub fn ambient_light_sensor(&self) -> Option<&str> {
+        self.ambient_light_sensor.as_deref()
+    }
+    pub fn daylight_lux(&self) -> Option<&str> {
+        self.daylight_lux.as_deref()
+    }
 }
 
 impl GoogleRelay {
@@ -473,6 +823,9 @@ impl GoogleRelay {
     pub fn ring_button(&self) -> &str {
         &self.ring_button
     }
+    pub fn logic_output(&self) -> Option<&str> {
+        self.logic_output.as_deref()
+    }
 
     pub fn idx(&self) -> &str {
         &self.idx
@@ -536,6 +889,17 @@ pub struct Settings {
     pub server: ServerSettings,
     pub tinkerforge: Tinkerforge,
     pub google_sheet: Option<GoogleSheet>,
+    pub heating: HeatingSettings,
+    pub circadian: CircadianSettings,
+    pub light_schedules: Box<[LightScheduleSettings]>,
+    pub constant_light: ConstantLightSettings,
+    pub all_off: Box<[AllOffSettings]>,
+    pub scene_buttons: Box<[SceneButtonSettings]>,
+    pub house_mode_buttons: Box<[HouseModeButtonSettings]>,
+    pub presence_simulation: PresenceSimulationSettings,
+    pub doorbell: DoorbellSettings,
+    pub wake_up: WakeUpSettings,
+    pub ventilation: VentilationSettings,
 }
 
 const DEFAULT_IP_ADDRESS: IpAddr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
@@ -556,12 +920,251 @@ impl ServerSettings {
             .map(Box::as_ref)
             .unwrap_or("setup.yaml")
     }
+    /// logic blocks added to the wiring, re-read on every configuration fetch
+    pub fn logic_file(&self) -> Option<&str> {
+        self.logic_file.as_deref()
+    }
     pub fn state_file(&self) -> &str {
         self.state_file
             .as_ref()
             .map(Box::as_ref)
             .unwrap_or("state.ron")
     }
+    /// local time of the clocks driving schedules and the circadian whitebalance
+    pub fn timezone(&self) -> Tz {
+        self.timezone.unwrap_or(Tz::Europe__Zurich)
+    }
+}
+
+impl HeatingSettings {
+    pub fn frost_temperature(&self) -> f32 {
+        self.frost_temperature.unwrap_or(7.0)
+    }
+    /// upper limit of the heating target while nobody is at home
+    pub fn eco_temperature(&self) -> f32 {
+        self.eco_temperature.unwrap_or(17.0)
+    }
+    pub fn alarm_temperature_change(&self) -> f32 {
+        self.alarm_temperature_change.unwrap_or(1.5)
+    }
+    pub fn alarm_settle_time(&self) -> Duration {
+        Duration::from_secs(self.alarm_settle_minutes.unwrap_or(45) * 60)
+    }
+    /// temperature drop in 1/100 °C within the drop interval that counts as an open window
+    pub fn window_temperature_drop(&self) -> u16 {
+        (self.window_temperature_drop.unwrap_or(0.5) * 100.0).round() as u16
+    }
+    pub fn window_drop_interval(&self) -> Duration {
+        Duration::from_secs(self.window_drop_minutes.unwrap_or(3) * 60)
+    }
+    /// heating stays off this long after a window was opened, unless its contact closes before
+    pub fn window_suspend_time(&self) -> Duration {
+        Duration::from_secs(self.window_suspend_minutes.unwrap_or(20) * 60)
+    }
+}
+
+impl CircadianSettings {
+    /// latitude and longitude in degrees, if configured the sun position defines the day
+    pub fn location(&self) -> Option<(f64, f64)> {
+        self.latitude.zip(self.longitude)
+    }
+    pub fn sunrise(&self) -> NaiveTime {
+        self.sunrise
+            .unwrap_or(NaiveTime::from_hms_opt(7, 0, 0).expect("Invalid default sunrise"))
+    }
+    pub fn sunset(&self) -> NaiveTime {
+        self.sunset
+            .unwrap_or(NaiveTime::from_hms_opt(19, 0, 0).expect("Invalid default sunset"))
+    }
+    /// whitebalance in kelvin during the day
+    pub fn day_temperature(&self) -> u16 {
+        self.day_temperature.unwrap_or(5000)
+    }
+    /// whitebalance in kelvin during the night
+    pub fn night_temperature(&self) -> u16 {
+        self.night_temperature.unwrap_or(2700)
+    }
+    pub fn transition(&self) -> Duration {
+        Duration::from_secs(self.transition_minutes.unwrap_or(60) * 60)
+    }
+}
+
+impl Settings {
+    pub fn light_schedule(&self, name: &str) -> Option<&LightScheduleSettings> {
+        self.light_schedules
+            .iter()
+            .find(|schedule| schedule.name.as_ref() == name)
+    }
+}
+
+impl ConstantLightSettings {
+    pub fn min_brightness(&self) -> u8 {
+        percent_to_brightness(self.min_brightness_percent.unwrap_or(10))
+    }
+    pub fn max_brightness(&self) -> u8 {
+        percent_to_brightness(self.max_brightness_percent.unwrap_or(100))
+    }
+    pub fn vacancy_time(&self) -> Duration {
+        Duration::from_secs(self.vacancy_minutes.unwrap_or(10) * 60)
+    }
+    pub fn ramp_step_time(&self) -> Duration {
+        Duration::from_millis(self.ramp_step_millis.unwrap_or(200))
+    }
+}
+
+fn percent_to_brightness(percent: u8) -> u8 {
+    (percent.min(100) as u16 * 255 / 100) as u8
+}
+
+impl AllOffSettings {
+    /// name of the button in the button sheet
+    pub fn button(&self) -> &str {
+        &self.button
+    }
+    pub fn group(&self) -> LightGroupKey {
+        LightGroupKey::from_location(self.room, self.floor)
+    }
+}
+
+impl PresenceSimulationSettings {
+    pub fn start(&self) -> NaiveTime {
+        self.start
+            .unwrap_or(NaiveTime::from_hms_opt(18, 0, 0).expect("Invalid default start"))
+    }
+    pub fn end(&self) -> NaiveTime {
+        self.end
+            .unwrap_or(NaiveTime::from_hms_opt(23, 30, 0).expect("Invalid default end"))
+    }
+    pub fn min_on_time(&self) -> Duration {
+        Duration::from_secs(self.min_on_minutes.unwrap_or(10) * 60)
+    }
+    pub fn max_on_time(&self) -> Duration {
+        Duration::from_secs(self.max_on_minutes.unwrap_or(60) * 60)
+    }
+    pub fn max_off_time(&self) -> Duration {
+        Duration::from_secs(self.max_off_minutes.unwrap_or(30) * 60)
+    }
+    pub fn brightness(&self) -> u8 {
+        percent_to_brightness(self.brightness_percent.unwrap_or(80))
+    }
+}
+
+impl DoorbellSettings {
+    /// alternating on and off times of the bell, empty if the bell follows the button
+    pub fn pattern(&self) -> Box<[Duration]> {
+        self.pattern_millis
+            .iter()
+            .flatten()
+            .map(|millis| Duration::from_millis(*millis))
+            .collect()
+    }
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce_seconds.unwrap_or(3))
+    }
+    /// start and end of the time the bell stays silent
+    pub fn quiet_hours(&self) -> Option<(NaiveTime, NaiveTime)> {
+        self.quiet_start.zip(self.quiet_end)
+    }
+    /// light flashed during quiet hours
+    pub fn flash_light(&self) -> Option<DeviceInRoom> {
+        self.flash_room.map(|room| DeviceInRoom {
+            room,
+            idx: self.flash_idx.unwrap_or(0),
+        })
+    }
+    /// url every ring is posted to as json
+    pub fn webhook(&self) -> Option<&str> {
+        self.webhook.as_deref()
+    }
+}
+
+impl WakeUpSettings {
+    /// time from off to the target brightness, ending at the alarm time
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_minutes.unwrap_or(30) * 60)
+    }
+    pub fn brightness(&self) -> u8 {
+        percent_to_brightness(self.brightness_percent.unwrap_or(100))
+    }
+    /// days the alarm is on, monday to friday if not configured
+    pub fn weekdays(&self) -> Weekdays {
+        match &self.weekdays {
+            Some(days) => days.iter().copied().collect(),
+            None => [
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+}
+
+impl VentilationSettings {
+    /// rise of the humidity above the baseline in 1/10 %RH that switches a fan on
+    pub fn rise(&self) -> u16 {
+        (self.rise_percent.unwrap_or(10.0) * 10.0).round() as u16
+    }
+    /// time constant of the baseline following the humidity while a fan is off
+    pub fn baseline_time(&self) -> Duration {
+        Duration::from_secs(self.baseline_minutes.unwrap_or(60) * 60)
+    }
+    pub fn run_on(&self) -> Duration {
+        Duration::from_secs(self.run_on_minutes.unwrap_or(10) * 60)
+    }
+    pub fn max_runtime(&self) -> Duration {
+        Duration::from_secs(self.max_runtime_minutes.unwrap_or(90) * 60)
+    }
+}
+
+impl HouseModeButtonSettings {
+    /// name of the button in the button sheet
+    pub fn button(&self) -> &str {
+        &self.button
+    }
+    pub fn mode(&self) -> HouseMode {
+        self.mode
+    }
+}
+
+impl SceneButtonSettings {
+    /// name of the button in the button sheet
+    pub fn button(&self) -> &str {
+        &self.button
+    }
+    pub fn scene(&self) -> &str {
+        &self.scene
+    }
+    /// lights captured into the scene by a long press
+    pub fn capture(&self) -> Option<LightGroupKey> {
+        self.capture
+            .then(|| LightGroupKey::from_location(self.room, self.floor))
+    }
+}
+
+impl LightScheduleSettings {
+    pub fn windows(&self) -> &[TimeWindowSettings] {
+        &self.windows
+    }
+}
+
+impl TimeWindowSettings {
+    pub fn start(&self) -> NaiveTime {
+        self.start
+    }
+    pub fn end(&self) -> NaiveTime {
+        self.end
+    }
+    /// brightness in percent, the light template default if missing
+    pub fn brightness_percent(&self) -> Option<u8> {
+        self.brightness_percent
+    }
+    pub fn switch_off_time(&self) -> Duration {
+        Duration::from_secs(self.switch_off_minutes * 60)
+    }
 }
 
 fn create_settings() -> Result<Settings, ConfigError> {
@@ -577,9 +1180,27 @@ fn create_settings() -> Result<Settings, ConfigError> {
         server: cfg.get("server")?,
         tinkerforge: cfg.get("tinkerforge")?,
         google_sheet: cfg.get("google-sheet")?,
+        heating: optional_section(cfg.get("heating"))?,
+        circadian: optional_section(cfg.get("circadian"))?,
+        light_schedules: optional_section(cfg.get("light_schedules"))?,
+        constant_light: optional_section(cfg.get("constant_light"))?,
+        all_off: optional_section(cfg.get("all_off"))?,
+        scene_buttons: optional_section(cfg.get("scene_buttons"))?,
+        house_mode_buttons: optional_section(cfg.get("house_mode_buttons"))?,
+        presence_simulation: optional_section(cfg.get("presence_simulation"))?,
+        doorbell: optional_section(cfg.get("doorbell"))?,
+        wake_up: optional_section(cfg.get("wake_up"))?,
+        ventilation: optional_section(cfg.get("ventilation"))?,
     })
 }
 
+fn optional_section<T: Default>(value: Result<T, ConfigError>) -> Result<T, ConfigError> {
+    match value {
+        Err(ConfigError::NotFound(_)) => Ok(T::default()),
+        value => value,
+    }
+}
+
 lazy_static! {
     pub static ref CONFIG: Settings = create_settings().expect("Cannot load config.yaml");
 }
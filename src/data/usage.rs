@@ -0,0 +1,257 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use prometheus::{register_gauge_vec, register_int_gauge_vec, GaugeVec, IntGaugeVec};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, time::Instant};
+
+use crate::data::registry::{BrightnessKey, SwitchOutputKey};
+
+/// running time of outputs switched on is added to the persisted usage at most this often
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+lazy_static! {
+    static ref ON_TIME_GAUGE: GaugeVec = register_gauge_vec!(
+        "output_on_seconds",
+        "Time outputs were switched on, weighted by the brightness for lights",
+        &["output"]
+    )
+    .expect("Cannot register on time gauge");
+    static ref CYCLES_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "output_switch_cycles",
+        "Times outputs were switched on",
+        &["output"]
+    )
+    .expect("Cannot register switch cycles gauge");
+}
+
+/// Accumulated usage of an output over its whole life
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct OutputUsage {
+    /// time switched on, weighted by the brightness for lights
+    pub on_seconds: f64,
+    /// switches from off to on
+    pub cycles: u64,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct UsageSnapshot {
+    switches: HashMap<SwitchOutputKey, OutputUsage>,
+    lights: HashMap<BrightnessKey, OutputUsage>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize)]
+pub enum UsageOutput {
+    Switch(SwitchOutputKey),
+    Light(BrightnessKey),
+}
+
+impl UsageOutput {
+    fn label(&self) -> String {
+        match self {
+            UsageOutput::Switch(key) => format!("{key:?}"),
+            UsageOutput::Light(key) => format!("{key:?}"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct OutputUsageInfo {
+    pub output: UsageOutput,
+    /// current relative power between 0 and 1
+    pub level: f64,
+    #[serde(flatten)]
+    pub usage: OutputUsage,
+}
+
+struct TrackedOutput {
+    usage: OutputUsage,
+    /// unknown until the first value of the register
+    level: Option<f64>,
+    since: Instant,
+}
+
+impl TrackedOutput {
+    fn new(usage: OutputUsage, now: Instant) -> Self {
+        Self {
+            usage,
+            level: None,
+            since: now,
+        }
+    }
+    fn update(&mut self, level: f64, now: Instant) {
+        self.flush(now);
+        if self.level == Some(0.0) && level > 0.0 {
+            self.usage.cycles += 1;
+        }
+        self.level = Some(level);
+    }
+    fn flush(&mut self, now: Instant) {
+        self.usage = self.current(now);
+        self.since = now;
+    }
+    /// Usage including the running time since the last flush
+    fn current(&self, now: Instant) -> OutputUsage {
+        OutputUsage {
+            on_seconds: self.usage.on_seconds
+                + self.level.unwrap_or_default() * now.duration_since(self.since).as_secs_f64(),
+            cycles: self.usage.cycles,
+        }
+    }
+}
+
+struct InnerUsageTracker {
+    outputs: HashMap<UsageOutput, TrackedOutput>,
+    last_flush: Instant,
+}
+
+/// On-time and switching cycles of all switch and brightness registers
+#[derive(Clone)]
+pub struct UsageTracker {
+    inner: Arc<Mutex<InnerUsageTracker>>,
+}
+
+impl UsageTracker {
+    pub fn new(restored: &UsageSnapshot) -> Self {
+        let now = Instant::now();
+        let outputs = restored
+            .switches
+            .iter()
+            .map(|(key, usage)| (UsageOutput::Switch(*key), *usage))
+            .chain(
+                restored
+                    .lights
+                    .iter()
+                    .map(|(key, usage)| (UsageOutput::Light(*key), *usage)),
+            )
+            .map(|(output, usage)| (output, TrackedOutput::new(usage, now)))
+            .collect();
+        Self {
+            inner: Arc::new(Mutex::new(InnerUsageTracker {
+                outputs,
+                last_flush: now,
+            })),
+        }
+    }
+    /// Follows the values of a register until it is dropped
+    pub fn track<T: Send + Sync + 'static>(
+        &self,
+        output: UsageOutput,
+        mut receiver: watch::Receiver<T>,
+        level: fn(&T) -> f64,
+    ) {
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let level = level(&receiver.borrow_and_update());
+                tracker.update(output, level);
+                if receiver.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    fn update(&self, output: UsageOutput, level: f64) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().expect("Usage lock poisoned");
+        let tracked = inner
+            .outputs
+            .entry(output)
+            .or_insert_with(|| TrackedOutput::new(OutputUsage::default(), now));
+        tracked.update(level, now);
+        publish_metrics(output, tracked.usage);
+    }
+    /// Persisted state, the running time of active outputs is only added every few minutes to
+    /// keep the snapshot from changing all the time
+    pub fn snapshot(&self) -> UsageSnapshot {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().expect("Usage lock poisoned");
+        let flush = now.duration_since(inner.last_flush) >= PERSIST_INTERVAL;
+        if flush {
+            inner.last_flush = now;
+        }
+        let mut snapshot = UsageSnapshot::default();
+        for (output, tracked) in inner.outputs.iter_mut() {
+            if flush {
+                tracked.flush(now);
+            }
+            publish_metrics(*output, tracked.current(now));
+            match output {
+                UsageOutput::Switch(key) => snapshot.switches.insert(*key, tracked.usage),
+                UsageOutput::Light(key) => snapshot.lights.insert(*key, tracked.usage),
+            };
+        }
+        snapshot
+    }
+    pub fn usage(&self) -> Vec<OutputUsageInfo> {
+        let now = Instant::now();
+        self.inner
+            .lock()
+            .expect("Usage lock poisoned")
+            .outputs
+            .iter()
+            .map(|(output, tracked)| OutputUsageInfo {
+                output: *output,
+                level: tracked.level.unwrap_or_default(),
+                usage: tracked.current(now),
+            })
+            .collect()
+    }
+}
+
+fn publish_metrics(output: UsageOutput, usage: OutputUsage) {
+    let label = output.label();
+    ON_TIME_GAUGE
+        .with_label_values(&[&label])
+        .set(usage.on_seconds);
+    CYCLES_GAUGE
+        .with_label_values(&[&label])
+        .set(usage.cycles as i64);
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::data::usage::{OutputUsage, TrackedOutput};
+
+    #[test]
+    fn test_tracked_output() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut output = TrackedOutput::new(
+            OutputUsage {
+                on_seconds: 100.0,
+                cycles: 7,
+            },
+            start,
+        );
+        // restored as on, no new cycle
+        output.update(1.0, at(0));
+        output.update(0.0, at(10));
+        output.update(0.5, at(20));
+        output.update(0.5, at(30));
+        assert_eq!(
+            OutputUsage {
+                on_seconds: 120.0,
+                cycles: 8,
+            },
+            output.current(at(40))
+        );
+        output.flush(at(40));
+        output.update(0.0, at(50));
+        assert_eq!(
+            OutputUsage {
+                on_seconds: 125.0,
+                cycles: 8,
+            },
+            output.current(at(60))
+        );
+    }
+}
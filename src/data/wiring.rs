@@ -1,12 +1,17 @@
-use std::{collections::BTreeMap, net::IpAddr, time::Duration};
+use std::{cmp::Ordering, collections::BTreeMap, net::IpAddr, time::Duration};
 
+use chrono::{NaiveTime, Weekday};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 use tinkerforge_async::base58::Uid;
 
-use crate::data::registry::{
-    BrightnessKey, ClockKey, DualButtonKey, LightColorKey, SingleButtonKey, SwitchOutputKey,
-    TemperatureKey,
+use crate::data::{
+    registry::{
+        AlarmTimeKey, BrightnessKey, ClockKey, DualButtonKey, HouseMode, HumidityKey,
+        IlluminanceKey, LightColorKey, LightGroupKey, LogicKey, SceneValues, ShutterPositionKey,
+        SingleButtonKey, SwitchOutputKey, TemperatureKey, WindowOpenKey,
+    },
+    DeviceInRoom,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
@@ -22,6 +27,35 @@ pub struct Controllers {
     pub motion_detectors: Box<[MotionDetector]>,
     pub heat_controllers: Box<[HeatController]>,
     pub ring_controllers: Box<[RingController]>,
+    #[serde(default)]
+    pub circadian_whitebalances: Box<[CircadianWhitebalance]>,
+    #[serde(default)]
+    pub single_input_dimmers: Box<[SingleInputDimmer]>,
+    #[serde(default)]
+    pub single_input_switches: Box<[SingleInputSwitch]>,
+    #[serde(default)]
+    pub constant_lights: Box<[ConstantLight]>,
+    #[serde(default)]
+    pub all_off_controllers: Box<[AllOffController]>,
+    #[serde(default)]
+    pub scenes: Box<[Scene]>,
+    #[serde(default)]
+    pub scene_buttons: Box<[SceneButton]>,
+    #[serde(default)]
+    pub house_mode_buttons: Box<[HouseModeButton]>,
+    #[serde(default)]
+    pub presence_simulations: Box<[PresenceSimulation]>,
+    #[serde(default)]
+    pub shutters: Box<[Shutter]>,
+    #[serde(default)]
+    pub logic_blocks: Box<[LogicBlock]>,
+    #[serde(default)]
+    pub wake_up_lights: Box<[WakeUpLight]>,
+    #[serde(default)]
+    pub ventilation_fans: Box<[VentilationFan]>,
+    /// read from the script directory on every config fetch, not stored in the setup file
+    #[serde(skip)]
+    pub scripts: Box<[Script]>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
@@ -30,6 +64,40 @@ pub struct DualInputDimmer {
     pub output: BrightnessKey,
     pub auto_switch_off_time: Duration,
     pub presence: Box<[SingleButtonKey]>,
+    /// Long press time to dimm over the full range
+    #[serde(default = "default_dimm_duration")]
+    pub dimm_duration: Duration,
+    #[serde(default)]
+    pub pre_warning: Option<PreWarning>,
+    /// switch off time depending on the time of day, manual presses keep their brightness
+    #[serde(default)]
+    pub schedule: Option<TimeSchedule>,
+}
+
+pub fn default_dimm_duration() -> Duration {
+    Duration::from_millis(2550)
+}
+
+/// Toggles by short press, dimms by long press alternating the direction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct SingleInputDimmer {
+    pub input: Box<[SingleButtonKey]>,
+    pub output: BrightnessKey,
+    pub auto_switch_off_time: Duration,
+    pub presence: Box<[SingleButtonKey]>,
+    #[serde(default = "default_dimm_duration")]
+    pub dimm_duration: Duration,
+    #[serde(default)]
+    pub pre_warning: Option<PreWarning>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct SingleInputSwitch {
+    pub input: Box<[SingleButtonKey]>,
+    pub output: SwitchOutputKey,
+    pub auto_switch_off_time: Duration,
+    pub presence: Box<[SingleButtonKey]>,
+    #[serde(default)]
+    pub pre_warning: Option<PreWarning>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct DualInputSwitch {
@@ -37,6 +105,8 @@ pub struct DualInputSwitch {
     pub output: SwitchOutputKey,
     pub auto_switch_off_time: Duration,
     pub presence: Box<[SingleButtonKey]>,
+    #[serde(default)]
+    pub pre_warning: Option<PreWarning>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub enum MotionDetector {
@@ -44,24 +114,387 @@ pub enum MotionDetector {
         input: Box<[SingleButtonKey]>,
         output: SwitchOutputKey,
         switch_off_time: Duration,
+        #[serde(default)]
+        pre_warning: Option<PreWarning>,
+        #[serde(default)]
+        schedule: Option<TimeSchedule>,
+        #[serde(default)]
+        daylight: Option<DaylightThreshold>,
     },
     Dimmer {
         input: Box<[SingleButtonKey]>,
         output: BrightnessKey,
         brightness: Option<BrightnessKey>,
         switch_off_time: Duration,
+        #[serde(default)]
+        pre_warning: Option<PreWarning>,
+        #[serde(default)]
+        schedule: Option<TimeSchedule>,
+        #[serde(default)]
+        daylight: Option<DaylightThreshold>,
     },
 }
+/// Presence does not switch on the light while the ambient light is brighter than `lux`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub struct DaylightThreshold {
+    pub sensor: IlluminanceKey,
+    pub lux: u32,
+}
+/// Announces an automatic switch off, any button press or presence keeps the light on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub struct PreWarning {
+    /// how long before the switch off the warning starts
+    pub lead_time: Duration,
+    /// dimmers are dimmed down to this level, without a level they flash like switches
+    pub brightness: Option<u8>,
+    /// time the output is switched off to flash
+    pub flash_time: Duration,
+}
+/// Time windows overriding the default brightness and switch off time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct TimeSchedule {
+    pub clock: ClockKey,
+    /// the first window containing the current time wins
+    pub windows: Box<[TimeWindow]>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub struct TimeWindow {
+    /// windows with the end before the start span midnight
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub brightness: Option<u8>,
+    pub switch_off_time: Duration,
+}
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct HeatController {
     pub current_value_input: TemperatureKey,
     pub target_value_input: TemperatureKey,
     pub output: SwitchOutputKey,
+    #[serde(default)]
+    pub window_open_detection: Option<WindowOpenDetection>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct WindowOpenDetection {
+    /// contacts signaling an opened window by a press
+    pub window_contacts: Box<[SingleButtonKey]>,
+    /// temperature drop in 1/100 °C within `drop_interval` that indicates an open window
+    pub temperature_drop: Option<u16>,
+    pub drop_interval: Duration,
+    pub suspend_time: Duration,
+    pub state_output: WindowOpenKey,
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct RingController {
     pub input: SingleButtonKey,
     pub output: SwitchOutputKey,
+    /// alternating on and off times of the bell, without a pattern the bell follows the button
+    #[serde(default)]
+    pub pattern: Box<[Duration]>,
+    /// presses within this time after a ring are ignored
+    #[serde(default)]
+    pub debounce: Duration,
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+}
+/// Time of the day the bell stays silent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct QuietHours {
+    pub clock: ClockKey,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// light flashed instead of ringing the bell
+    #[serde(default)]
+    pub flash: Option<FlashLight>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum FlashLight {
+    Switch { key: SwitchOutputKey },
+    Dimmer { key: BrightnessKey },
+}
+/// Holds the illuminance of a room while it is occupied, manual dimming pauses it until the room
+/// is vacant
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct ConstantLight {
+    pub sensor: IlluminanceKey,
+    pub output: BrightnessKey,
+    pub presence: Box<[SingleButtonKey]>,
+    pub target_lux: u32,
+    pub min_brightness: u8,
+    pub max_brightness: u8,
+    /// time without presence until the light is switched off
+    pub vacancy_time: Duration,
+    /// time per brightness step while adjusting
+    pub ramp_step_time: Duration,
+}
+/// Light outputs of a room, a floor or the whole house
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct LightGroup {
+    pub key: LightGroupKey,
+    #[serde(default)]
+    pub brightness: Box<[BrightnessKey]>,
+    #[serde(default)]
+    pub switches: Box<[SwitchOutputKey]>,
+}
+/// Switches off all lights of a group by a button press
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct AllOffController {
+    pub input: SingleButtonKey,
+    pub group: LightGroup,
+    /// lights of the group staying on
+    #[serde(default)]
+    pub exceptions: Box<[DeviceInRoom]>,
+}
+/// Named set of target values applied together
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct Scene {
+    pub name: Box<str>,
+    /// brightness and whitebalance move linearly to the target values during this time
+    #[serde(default)]
+    pub transition: Duration,
+    pub values: SceneValues,
+}
+/// Recalls a scene by a short press, a long press captures the current values of a group
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct SceneButton {
+    pub input: SingleButtonKey,
+    pub scene: Box<str>,
+    #[serde(default)]
+    pub capture: Option<LightGroupKey>,
+}
+/// Toggles between home and the configured mode by a short press
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct HouseModeButton {
+    pub input: SingleButtonKey,
+    pub mode: HouseMode,
+}
+/// Switches lights at random times in the evening while nobody is at home
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct PresenceSimulation {
+    pub clock: ClockKey,
+    #[serde(default)]
+    pub switches: Box<[SwitchOutputKey]>,
+    #[serde(default)]
+    pub dimmers: Box<[BrightnessKey]>,
+    /// brightness of the dimmers while on
+    pub brightness: u8,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub min_on_time: Duration,
+    pub max_on_time: Duration,
+    pub max_off_time: Duration,
+}
+/// Drives the motor relays of a shutter, a short press travels to the end, a long press jogs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct Shutter {
+    pub input: Box<[DualButtonKey]>,
+    pub up: SwitchOutputKey,
+    pub down: SwitchOutputKey,
+    pub position: ShutterPositionKey,
+    /// run time from open to closed, the motor is stopped after it
+    pub travel_time: Duration,
+}
+/// Runs a fan while the humidity is above its slowly following baseline, mainly for bathrooms
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct VentilationFan {
+    pub sensor: HumidityKey,
+    pub output: SwitchOutputKey,
+    /// rise above the baseline in 1/10 %RH that switches the fan on, below half of it the humidity
+    /// counts as dropped
+    pub rise: u16,
+    /// time constant of the baseline following the humidity while the fan is off
+    pub baseline_time: Duration,
+    /// the fan keeps running this long after the humidity dropped
+    pub run_on: Duration,
+    /// the fan stops after this time and waits for the humidity to drop
+    pub max_runtime: Duration,
+}
+/// Rhai script subscribing to registers and writing to them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct Script {
+    pub name: Box<str>,
+    pub source: Box<str>,
+}
+/// Generic building block connecting registers without a dedicated controller
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub enum LogicBlock {
+    Gate {
+        operation: GateOperation,
+        inputs: Box<[BoolSignal]>,
+        output: BoolOutput,
+    },
+    /// follows the input after the on delay when it switches on and the off delay when it switches off
+    Delay {
+        input: BoolSignal,
+        output: BoolOutput,
+        #[serde(default)]
+        on_delay: Duration,
+        #[serde(default)]
+        off_delay: Duration,
+    },
+    /// inverts the output on every rising edge of the input
+    Toggle {
+        input: BoolSignal,
+        output: BoolOutput,
+    },
+    /// a rising edge of set switches on, of reset off, reset wins while both are on
+    SetReset {
+        set: BoolSignal,
+        reset: BoolSignal,
+        output: BoolOutput,
+    },
+    /// on at or above the threshold, off below the threshold minus the hysteresis
+    Threshold {
+        input: NumberSignal,
+        output: BoolOutput,
+        threshold: LogicValue,
+        #[serde(default)]
+        hysteresis: LogicValue,
+        #[serde(default)]
+        invert: bool,
+    },
+    /// maps the input range linearly onto the output range, values outside are clamped
+    Map {
+        input: NumberSignal,
+        output: NumberSignal,
+        input_min: LogicValue,
+        input_max: LogicValue,
+        output_min: LogicValue,
+        output_max: LogicValue,
+    },
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum GateOperation {
+    And,
+    Or,
+    Xor,
+    /// on while none of the inputs is on
+    Not,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum BoolSignal {
+    Switch {
+        key: SwitchOutputKey,
+    },
+    WindowOpen {
+        key: WindowOpenKey,
+    },
+    Logic {
+        key: LogicKey,
+    },
+    /// on while the button is pressed
+    Button {
+        key: SingleButtonKey,
+    },
+    /// on while the brightness is above zero
+    Brightness {
+        key: BrightnessKey,
+    },
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum BoolOutput {
+    Switch { key: SwitchOutputKey },
+    Logic { key: LogicKey },
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum NumberSignal {
+    Temperature {
+        key: TemperatureKey,
+    },
+    Brightness {
+        key: BrightnessKey,
+    },
+    /// whitebalance in mireds
+    LightColor {
+        key: LightColorKey,
+    },
+    Illuminance {
+        key: IlluminanceKey,
+    },
+    ShutterPosition {
+        key: ShutterPositionKey,
+    },
+}
+/// Number in a logic block, totally ordered to keep the wiring sortable
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(transparent)]
+pub struct LogicValue(pub f32);
+impl PartialEq for LogicValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for LogicValue {}
+impl PartialOrd for LogicValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for LogicValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+/// Moves the whitebalance of lights along the day, warm at night and cold at day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct CircadianWhitebalance {
+    pub clock: ClockKey,
+    pub outputs: Box<[LightColorKey]>,
+    pub schedule: CircadianSchedule,
+    pub day_mireds: u16,
+    pub night_mireds: u16,
+    pub transition: Duration,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub enum CircadianSchedule {
+    Fixed {
+        sunrise: NaiveTime,
+        sunset: NaiveTime,
+    },
+    /// coordinates in millionths of a degree
+    Sun { latitude: i32, longitude: i32 },
+}
+/// Ramps the lights of a room from off and warm up to the alarm time, any button of the room
+/// cancels the running ramp
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Ord, PartialOrd)]
+pub struct WakeUpLight {
+    pub clock: ClockKey,
+    pub alarm: AlarmTimeKey,
+    pub outputs: Box<[WakeUpOutput]>,
+    /// brightness reached at the alarm time
+    pub brightness: u8,
+    /// the ramp ends at the alarm time
+    pub duration: Duration,
+    /// days the alarm time is on
+    pub weekdays: Weekdays,
+    #[serde(default)]
+    pub buttons: Box<[SingleButtonKey]>,
+    #[serde(default)]
+    pub dual_buttons: Box<[DualButtonKey]>,
+}
+/// Light ramped from its warmest to its coldest whitebalance
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+pub struct WakeUpOutput {
+    pub brightness: BrightnessKey,
+    pub whitebalance: LightColorKey,
+    pub warm_mireds: u16,
+    pub cold_mireds: u16,
+}
+/// Set of weekdays, bit 0 is monday
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+#[serde(transparent)]
+pub struct Weekdays(pub u8);
+impl Weekdays {
+    pub fn contains(self, day: Weekday) -> bool {
+        self.0 & (1 << day.num_days_from_monday()) != 0
+    }
+}
+impl FromIterator<Weekday> for Weekdays {
+    fn from_iter<T: IntoIterator<Item = Weekday>>(iter: T) -> Self {
+        Self(
+            iter.into_iter()
+                .fold(0, |days, day| days | (1 << day.num_days_from_monday())),
+        )
+    }
 }
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Ord, PartialOrd)]
 pub struct TinkerforgeDevices {
@@ -72,9 +505,13 @@ pub struct TinkerforgeDevices {
     pub motion_detectors: BTreeMap<Uid, MotionDetectorSettings>,
     pub relays: BTreeMap<Uid, RelaySettings>,
     pub temperature_sensors: BTreeMap<Uid, TemperatureSettings>,
+    #[serde(default)]
+    pub ambient_light_sensors: BTreeMap<Uid, AmbientLightSettings>,
+    #[serde(default)]
+    pub humidity_sensors: BTreeMap<Uid, HumiditySettings>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
 pub struct ScreenSettings {
     pub orientation: Orientation,
     pub clock_key: Option<ClockKey>,
@@ -82,6 +519,17 @@ pub struct ScreenSettings {
     pub adjust_temperature_key: Option<TemperatureKey>,
     pub light_color_key: Option<LightColorKey>,
     pub brightness_key: Option<BrightnessKey>,
+    #[serde(default)]
+    pub window_open_key: Option<WindowOpenKey>,
+    /// scene recalled by the scene button on the screen
+    #[serde(default)]
+    pub scene: Option<Box<str>>,
+    /// shows the house mode and switches it by touch
+    #[serde(default)]
+    pub house_mode: bool,
+    /// shows the wake-up time and adjusts it by touch
+    #[serde(default)]
+    pub alarm_time_key: Option<AlarmTimeKey>,
 }
 #[derive(Copy, Clone, Debug, Eq, PartialEq, EnumIter, Serialize, Deserialize, Ord, PartialOrd)]
 pub enum Orientation {
@@ -100,6 +548,13 @@ pub enum DmxConfigEntry {
     Dimm {
         register: BrightnessKey,
         channel: u16,
+        #[serde(default)]
+        transition: Transition,
+        #[serde(default)]
+        curve: DimmCurve,
+        /// nominal power of the channel in 1/10 W
+        #[serde(default)]
+        nominal_power: Option<u16>,
     },
     DimmWhitebalance {
         brightness_register: BrightnessKey,
@@ -108,13 +563,39 @@ pub enum DmxConfigEntry {
         cold_channel: u16,
         warm_mireds: u16,
         cold_mireds: u16,
+        #[serde(default)]
+        transition: Transition,
+        #[serde(default)]
+        curve: DimmCurve,
+        /// nominal power of each of both channels in 1/10 W
+        #[serde(default)]
+        nominal_power: Option<u16>,
     },
     Switch {
         register: SwitchOutputKey,
         channel: u16,
+        #[serde(default)]
+        nominal_power: Option<u16>,
     },
 }
 
+/// Time for a transition over the full range, shorter changes fade proportionally faster
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
+pub struct Transition {
+    pub fade_in: Duration,
+    pub fade_out: Duration,
+    pub color: Duration,
+}
+
+/// Mapping from the brightness register to the output level
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
+pub enum DimmCurve {
+    #[default]
+    Linear,
+    Gamma,
+    Logarithmic,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
 pub struct IoSettings {
     pub entries: Box<[ButtonSetting]>,
@@ -125,13 +606,34 @@ pub enum ButtonSetting {
         up_button: u8,
         down_button: u8,
         output: DualButtonKey,
+        #[serde(default)]
+        timing: ButtonTiming,
     },
     Single {
         button: u8,
         output: SingleButtonKey,
+        #[serde(default)]
+        timing: ButtonTiming,
     },
 }
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
+pub struct ButtonTiming {
+    pub long_press: Duration,
+    /// maximum pause between release and the next press to count as multi click, zero disables
+    pub multi_click: Duration,
+    /// a press is considered released after this time, zero waits for the real release
+    pub max_press: Duration,
+}
+impl Default for ButtonTiming {
+    fn default() -> Self {
+        Self {
+            long_press: Duration::from_millis(500),
+            multi_click: Duration::ZERO,
+            max_press: Duration::from_secs(20),
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
 pub struct MotionDetectorSettings {
     pub output: SingleButtonKey,
 }
@@ -143,11 +645,25 @@ pub struct RelaySettings {
 pub struct RelayChannelEntry {
     pub channel: u8,
     pub input: SwitchOutputKey,
+    /// channel switched off before this one switches on
+    #[serde(default)]
+    pub interlock: Option<u8>,
 }
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
 pub struct TemperatureSettings {
     pub output: TemperatureKey,
 }
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
+pub struct AmbientLightSettings {
+    pub output: IlluminanceKey,
+}
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Ord, PartialOrd)]
+pub struct HumiditySettings {
+    pub output: HumidityKey,
+    /// the sensor measures the temperature as well
+    #[serde(default)]
+    pub temperature: Option<TemperatureKey>,
+}
 
 #[cfg(test)]
 mod test {
@@ -157,12 +673,21 @@ mod test {
         time::Duration,
     };
 
+    use chrono::{NaiveTime, Weekday};
+    use chrono_tz::Tz;
+
     use crate::{
         data::{
-            registry::{BrightnessKey, DualButtonKey, LightColorKey},
+            registry::{
+                AlarmTimeKey, BrightnessKey, ClockKey, ClockKeyResolution, DualButtonKey,
+                HumidityKey, LightColorKey, LightGroupKey, SceneValue, SceneValues,
+                SingleButtonKey, SwitchOutputKey,
+            },
             wiring::{
-                Controllers, DmxConfigEntry, DmxSettings, DualInputDimmer, TinkerforgeDevices,
-                Wiring,
+                default_dimm_duration, BoolOutput, BoolSignal, Controllers, DimmCurve,
+                DmxConfigEntry, DmxSettings, DualInputDimmer, FlashLight, LogicBlock, QuietHours,
+                RingController, Scene, SceneButton, TinkerforgeDevices, Transition, VentilationFan,
+                WakeUpLight, WakeUpOutput, Wiring,
             },
             DeviceInRoom,
         },
@@ -178,11 +703,28 @@ mod test {
                     output: BrightnessKey::Light(Default::default()),
                     auto_switch_off_time: Duration::from_secs(2 * 3600),
                     presence: Box::new([]),
+                    dimm_duration: default_dimm_duration(),
+                    pre_warning: None,
+                    schedule: None,
                 }]),
                 dual_input_switches: Box::new([]),
                 motion_detectors: Box::new([]),
                 heat_controllers: Box::new([]),
                 ring_controllers: Box::new([]),
+                circadian_whitebalances: Box::new([]),
+                single_input_dimmers: Box::new([]),
+                single_input_switches: Box::new([]),
+                constant_lights: Box::new([]),
+                all_off_controllers: Box::new([]),
+                scenes: Box::new([]),
+                scene_buttons: Box::new([]),
+                house_mode_buttons: Box::new([]),
+                presence_simulations: Box::new([]),
+                shutters: Box::new([]),
+                logic_blocks: Box::new([]),
+                wake_up_lights: Box::new([]),
+                ventilation_fans: Box::new([]),
+                scripts: Box::new([]),
             },
             tinkerforge_devices: TinkerforgeDevices {
                 endpoints: Box::new([IpAddr::V4(Ipv4Addr::LOCALHOST)]),
@@ -197,6 +739,9 @@ mod test {
                                     idx: 0,
                                 }),
                                 channel: 3,
+                                transition: Default::default(),
+                                curve: Default::default(),
+                                nominal_power: None,
                             },
                             DmxConfigEntry::DimmWhitebalance {
                                 brightness_register: BrightnessKey::Light(DeviceInRoom {
@@ -211,6 +756,9 @@ mod test {
                                 cold_channel: 3,
                                 warm_mireds: kelvin_2_mireds(2700),
                                 cold_mireds: kelvin_2_mireds(7500),
+                                transition: Default::default(),
+                                curve: Default::default(),
+                                nominal_power: None,
                             },
                         ]),
                     },
@@ -219,10 +767,212 @@ mod test {
                 motion_detectors: Default::default(),
                 relays: Default::default(),
                 temperature_sensors: Default::default(),
+                ambient_light_sensors: Default::default(),
+                humidity_sensors: Default::default(),
             },
         };
         let yaml_data = serde_yaml::to_string(&data).unwrap();
         println!("{}", yaml_data);
         assert_eq!(data, serde_yaml::from_str(&yaml_data).unwrap());
     }
+
+    /// Each case only fills the fields it covers, everything else keeps its default
+    #[test]
+    fn test_serialize_round_trip() {
+        let cases = [
+            (
+                "dimm duration",
+                Wiring {
+                    controllers: Controllers {
+                        dual_input_dimmers: Box::new([DualInputDimmer {
+                            input: Box::new([DualButtonKey(Default::default())]),
+                            output: BrightnessKey::Light(Default::default()),
+                            auto_switch_off_time: Duration::from_secs(2 * 3600),
+                            presence: Box::new([]),
+                            dimm_duration: Duration::from_secs(4),
+                            pre_warning: None,
+                            schedule: None,
+                        }]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "dmx curves, transitions and nominal power",
+                Wiring {
+                    tinkerforge_devices: TinkerforgeDevices {
+                        dmx_bricklets: BTreeMap::from([(
+                            "EHc".parse().unwrap(),
+                            DmxSettings {
+                                entries: Box::new([
+                                    DmxConfigEntry::Dimm {
+                                        register: BrightnessKey::Light(Default::default()),
+                                        channel: 3,
+                                        transition: Default::default(),
+                                        curve: DimmCurve::Gamma,
+                                        nominal_power: Some(95),
+                                    },
+                                    DmxConfigEntry::DimmWhitebalance {
+                                        brightness_register: BrightnessKey::Light(
+                                            Default::default(),
+                                        ),
+                                        whitebalance_register: LightColorKey::Light(
+                                            Default::default(),
+                                        ),
+                                        warm_channel: 5,
+                                        cold_channel: 6,
+                                        warm_mireds: kelvin_2_mireds(2700),
+                                        cold_mireds: kelvin_2_mireds(7500),
+                                        transition: Transition {
+                                            fade_in: Duration::from_millis(800),
+                                            fade_out: Duration::from_millis(1500),
+                                            color: Duration::from_secs(2),
+                                        },
+                                        curve: DimmCurve::Logarithmic,
+                                        nominal_power: None,
+                                    },
+                                    DmxConfigEntry::Switch {
+                                        register: SwitchOutputKey::Light(Default::default()),
+                                        channel: 4,
+                                        nominal_power: Some(400),
+                                    },
+                                ]),
+                            },
+                        )]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "wake up light",
+                Wiring {
+                    controllers: Controllers {
+                        wake_up_lights: Box::new([WakeUpLight {
+                            clock: ClockKey {
+                                resolution: ClockKeyResolution::Seconds,
+                                tz: Tz::Europe__Zurich,
+                            },
+                            alarm: AlarmTimeKey("1.4".parse().unwrap()),
+                            outputs: Box::new([WakeUpOutput {
+                                brightness: BrightnessKey::Light(Default::default()),
+                                whitebalance: LightColorKey::Light(Default::default()),
+                                warm_mireds: kelvin_2_mireds(2700),
+                                cold_mireds: kelvin_2_mireds(6500),
+                            }]),
+                            brightness: 200,
+                            duration: Duration::from_secs(30 * 60),
+                            weekdays: [Weekday::Mon, Weekday::Tue, Weekday::Fri]
+                                .into_iter()
+                                .collect(),
+                            buttons: Box::new([SingleButtonKey::Button(Default::default())]),
+                            dual_buttons: Box::new([]),
+                        }]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "ventilation fan",
+                Wiring {
+                    controllers: Controllers {
+                        ventilation_fans: Box::new([VentilationFan {
+                            sensor: HumidityKey(Default::default()),
+                            output: SwitchOutputKey::Fan(Default::default()),
+                            rise: 100,
+                            baseline_time: Duration::from_secs(60 * 60),
+                            run_on: Duration::from_secs(10 * 60),
+                            max_runtime: Duration::from_secs(60 * 60),
+                        }]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "ring controller",
+                Wiring {
+                    controllers: Controllers {
+                        ring_controllers: Box::new([RingController {
+                            input: SingleButtonKey::Button(Default::default()),
+                            output: SwitchOutputKey::Bell(Default::default()),
+                            pattern: Box::new([
+                                Duration::from_millis(300),
+                                Duration::from_millis(200),
+                            ]),
+                            debounce: Duration::from_secs(5),
+                            quiet_hours: Some(QuietHours {
+                                clock: ClockKey {
+                                    resolution: ClockKeyResolution::Minutes,
+                                    tz: Tz::Europe__Zurich,
+                                },
+                                start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                                end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+                                flash: Some(FlashLight::Dimmer {
+                                    key: BrightnessKey::Light(Default::default()),
+                                }),
+                            }),
+                        }]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "logic block",
+                Wiring {
+                    controllers: Controllers {
+                        logic_blocks: Box::new([LogicBlock::Delay {
+                            input: BoolSignal::Switch {
+                                key: SwitchOutputKey::Light(Default::default()),
+                            },
+                            output: BoolOutput::Switch {
+                                key: SwitchOutputKey::Relay(Default::default()),
+                            },
+                            on_delay: Duration::ZERO,
+                            off_delay: Duration::from_secs(5 * 60),
+                        }]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+            (
+                "scenes",
+                Wiring {
+                    controllers: Controllers {
+                        scenes: Box::new([Scene {
+                            name: "Abend".into(),
+                            transition: Duration::from_secs(3),
+                            values: SceneValues {
+                                brightness: Box::new([SceneValue {
+                                    key: BrightnessKey::Light(Default::default()),
+                                    value: 80,
+                                }]),
+                                light_colors: Box::new([]),
+                                switches: Box::new([SceneValue {
+                                    key: SwitchOutputKey::Light(Default::default()),
+                                    value: false,
+                                }]),
+                            },
+                        }]),
+                        scene_buttons: Box::new([SceneButton {
+                            input: SingleButtonKey::Button(Default::default()),
+                            scene: "Abend".into(),
+                            capture: Some(LightGroupKey::Room("1.4".parse().unwrap())),
+                        }]),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (name, data) in cases {
+            let yaml_data = serde_yaml::to_string(&data).unwrap();
+            let parsed: Wiring = serde_yaml::from_str(&yaml_data).unwrap();
+            assert_eq!(data, parsed, "{name}");
+        }
+    }
 }
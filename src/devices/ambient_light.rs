@@ -0,0 +1,83 @@
+use log::error;
+use thiserror::Error;
+use tinkerforge_async::{
+    ambient_light_v_3::{
+        AmbientLightV3Bricklet, SetIlluminanceCallbackConfigurationRequest, ThresholdOption,
+    },
+    error::TinkerforgeError,
+};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+
+use crate::{
+    data::registry::{EventRegistry, IlluminanceKey},
+    terminator::LifeLineEnd,
+};
+
+pub fn handle_ambient_light(
+    bricklet: AmbientLightV3Bricklet,
+    event_registry: EventRegistry,
+    illuminance_key: IlluminanceKey,
+) -> LifeLineEnd {
+    let (tx, rx) = LifeLineEnd::create();
+    tokio::spawn(async move {
+        if let Err(error) = ambient_light_task(bricklet, event_registry, illuminance_key, rx).await
+        {
+            error!("Error processing illuminance: {error}");
+        }
+    });
+    tx
+}
+
+#[derive(Error, Debug)]
+enum AmbientLightError {
+    #[error("Tinkerforge error: {0}")]
+    Tinkerforge(#[from] TinkerforgeError),
+    #[error("Send error: {0}")]
+    SendError(#[from] mpsc::error::SendError<u32>),
+}
+
+enum AmbientLightEvent {
+    Illuminance(u32),
+    Closed,
+}
+
+/// the bricklet measures in 1/100 lux
+fn lux(illuminance: u32) -> u32 {
+    (illuminance + 50) / 100
+}
+
+async fn ambient_light_task(
+    mut bricklet: AmbientLightV3Bricklet,
+    event_registry: EventRegistry,
+    illuminance_key: IlluminanceKey,
+    termination_receiver: LifeLineEnd,
+) -> Result<(), AmbientLightError> {
+    bricklet
+        .set_illuminance_callback_configuration(SetIlluminanceCallbackConfigurationRequest {
+            period: 10000,
+            value_has_to_change: true,
+            option: ThresholdOption::Off,
+            min: 0,
+            max: 0,
+        })
+        .await?;
+
+    let mut stream = bricklet
+        .illuminance_stream()
+        .await
+        .map(|i| AmbientLightEvent::Illuminance(lux(i)))
+        .merge(termination_receiver.send_on_terminate(AmbientLightEvent::Closed));
+    let sender = event_registry.illuminance_sender(illuminance_key).await;
+    sender.send(lux(bricklet.get_illuminance().await?)).await?;
+    while let Some(event) = stream.next().await {
+        match event {
+            AmbientLightEvent::Illuminance(lux) => {
+                sender.send(lux).await?;
+            }
+            AmbientLightEvent::Closed => break,
+        }
+    }
+    drop(termination_receiver);
+    Ok(())
+}
@@ -13,8 +13,15 @@ use tokio_stream::{Stream, StreamExt};
 use tokio_util::either::Either;
 
 use crate::{
-    data::{registry::EventRegistry, state::StateUpdateMessage, wiring::DmxConfigEntry},
+    data::{
+        registry::{BrightnessKey, EventRegistry, SwitchOutputKey},
+        state::StateUpdateMessage,
+        wiring::DmxConfigEntry,
+        DeviceInRoom,
+    },
+    power::set_light_power,
     terminator::LifeLineEnd,
+    util::{apply_dimm_curve, fade},
 };
 
 pub async fn handle_dmx(
@@ -26,18 +33,53 @@ pub async fn handle_dmx(
     let mut streams = SelectAll::new();
     for config_entry in config.iter().cloned() {
         streams.push(match config_entry {
-            DmxConfigEntry::Dimm { register, channel } => Either::Left(Either::Left(
-                event_registry
-                    .brightness_stream(register)
-                    .await
-                    .map(move |v| DmxCommand::single(channel, v.0)),
-            )),
-            DmxConfigEntry::Switch { register, channel } => Either::Left(Either::Right(
-                event_registry
-                    .switch_stream(register)
-                    .await
-                    .map(move |v| DmxCommand::single(channel, if v { 255 } else { 0 })),
-            )),
+            DmxConfigEntry::Dimm {
+                register,
+                channel,
+                transition,
+                curve,
+                nominal_power,
+            } => Either::Left(Either::Left({
+                let rating = power_rating(
+                    match register {
+                        BrightnessKey::Light(light) => Some(light),
+                        BrightnessKey::TouchscreenController(_) => None,
+                    },
+                    nominal_power,
+                );
+                Box::pin(fade(
+                    event_registry
+                        .brightness_stream(register)
+                        .await
+                        .map(|v| v.0 as u16),
+                    transition.fade_in,
+                    transition.fade_out,
+                    u8::MAX as u16,
+                ))
+                .map(move |v| {
+                    let value = apply_dimm_curve(curve, v as u8);
+                    publish_power(rating, value as u16);
+                    DmxCommand::single(channel, value)
+                })
+            })),
+            DmxConfigEntry::Switch {
+                register,
+                channel,
+                nominal_power,
+            } => Either::Left(Either::Right({
+                let rating = power_rating(
+                    match register {
+                        SwitchOutputKey::Light(light) => Some(light),
+                        _ => None,
+                    },
+                    nominal_power,
+                );
+                event_registry.switch_stream(register).await.map(move |v| {
+                    let value = if v { 255 } else { 0 };
+                    publish_power(rating, value as u16);
+                    DmxCommand::single(channel, value)
+                })
+            })),
             DmxConfigEntry::DimmWhitebalance {
                 brightness_register,
                 whitebalance_register,
@@ -45,44 +87,65 @@ pub async fn handle_dmx(
                 cold_channel,
                 warm_mireds,
                 cold_mireds,
+                transition,
+                curve,
+                nominal_power,
             } => {
+                let rating = power_rating(
+                    match brightness_register {
+                        BrightnessKey::Light(light) => Some(light),
+                        BrightnessKey::TouchscreenController(_) => None,
+                    },
+                    nominal_power,
+                );
                 let mut current_brightness = Saturating(0);
                 let mut current_whitebalance = Saturating((cold_mireds + warm_mireds) / 2);
                 let min_mireds = Saturating(cold_mireds);
                 let max_mireds = Saturating(warm_mireds);
                 Either::Right(
-                    event_registry
-                        .brightness_stream(brightness_register)
-                        .await
-                        .map(DimmColorUpdate::Brightness)
-                        .merge(
+                    Box::pin(fade(
+                        event_registry
+                            .brightness_stream(brightness_register)
+                            .await
+                            .map(|v| v.0 as u16),
+                        transition.fade_in,
+                        transition.fade_out,
+                        u8::MAX as u16,
+                    ))
+                    .map(|v| DimmColorUpdate::Brightness(Saturating(v as u8)))
+                    .merge(
+                        Box::pin(fade(
                             event_registry
                                 .light_color_stream(whitebalance_register)
                                 .await
-                                .map(move |v| v.clamp(min_mireds, max_mireds))
-                                .map(DimmColorUpdate::Color),
-                        )
-                        .map(move |event| {
-                            match event {
-                                DimmColorUpdate::Brightness(br) => {
-                                    current_brightness = br;
-                                }
-                                DimmColorUpdate::Color(c) => current_whitebalance = c,
-                            };
-                            (current_brightness, current_whitebalance)
-                        })
-                        .map(move |(brightness, wb)| {
-                            let warm_part = wb - Saturating(cold_mireds);
-                            let cold_part = Saturating(warm_mireds) - wb;
-                            let stretch =
-                                1.0 / warm_part.max(cold_part).0 as f32 * brightness.0 as f32;
-                            DmxCommand::dual(
-                                warm_channel,
-                                (warm_part.0 as f32 * stretch) as u8,
-                                cold_channel,
-                                (cold_part.0 as f32 * stretch) as u8,
-                            )
-                        }),
+                                .map(move |v| v.clamp(min_mireds, max_mireds).0),
+                            transition.color,
+                            transition.color,
+                            warm_mireds.abs_diff(cold_mireds),
+                        ))
+                        .map(Saturating)
+                        .map(DimmColorUpdate::Color),
+                    )
+                    .map(move |event| {
+                        match event {
+                            DimmColorUpdate::Brightness(br) => {
+                                current_brightness = br;
+                            }
+                            DimmColorUpdate::Color(c) => current_whitebalance = c,
+                        };
+                        (current_brightness, current_whitebalance)
+                    })
+                    .map(move |(brightness, wb)| {
+                        let warm_part = wb - Saturating(cold_mireds);
+                        let cold_part = Saturating(warm_mireds) - wb;
+                        let stretch = 1.0 / warm_part.max(cold_part).0 as f32
+                            * apply_dimm_curve(curve, brightness.0) as f32;
+                        let warm_value = (warm_part.0 as f32 * stretch) as u8;
+                        let cold_value = (cold_part.0 as f32 * stretch) as u8;
+                        // both channels share the rating
+                        publish_power(rating, warm_value as u16 + cold_value as u16);
+                        DmxCommand::dual(warm_channel, warm_value, cold_channel, cold_value)
+                    }),
                 )
             }
         });
@@ -103,6 +166,21 @@ pub async fn handle_dmx(
     end1
 }
 
+/// Light and its nominal power per channel in W, if both are known
+fn power_rating(
+    light: Option<DeviceInRoom>,
+    nominal_power: Option<u16>,
+) -> Option<(DeviceInRoom, f64)> {
+    Some((light?, nominal_power? as f64 / 10.0))
+}
+
+/// Estimates the power by the sum of the channel levels, assuming a linear power draw
+fn publish_power(rating: Option<(DeviceInRoom, f64)>, channel_sum: u16) {
+    if let Some((light, watts)) = rating {
+        set_light_power(light, watts * channel_sum as f64 / u8::MAX as f64);
+    }
+}
+
 enum DmxCommand {
     SetSingleChannel {
         channel: u16,
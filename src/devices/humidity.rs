@@ -0,0 +1,117 @@
+use log::error;
+use thiserror::Error;
+use tinkerforge_async::{
+    error::TinkerforgeError,
+    humidity_v_2::{
+        HumidityV2Bricklet, SetHumidityCallbackConfigurationRequest,
+        SetTemperatureCallbackConfigurationRequest, ThresholdOption,
+    },
+};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+
+use crate::{
+    data::registry::EventRegistry, data::wiring::HumiditySettings, terminator::LifeLineEnd,
+};
+
+pub fn handle_humidity(
+    bricklet: HumidityV2Bricklet,
+    event_registry: EventRegistry,
+    settings: HumiditySettings,
+) -> LifeLineEnd {
+    let (tx, rx) = LifeLineEnd::create();
+    tokio::spawn(async move {
+        if let Err(error) = humidity_task(bricklet, event_registry, settings, rx).await {
+            error!("Error processing humidity: {error}");
+        }
+    });
+    tx
+}
+
+#[derive(Error, Debug)]
+enum HumidityError {
+    #[error("Tinkerforge error: {0}")]
+    Tinkerforge(#[from] TinkerforgeError),
+    #[error("Send error: {0}")]
+    SendError(#[from] mpsc::error::SendError<f32>),
+}
+
+enum HumidityEvent {
+    Humidity(f32),
+    Temperature(f32),
+    Closed,
+}
+
+/// the bricklet measures humidity in 1/100 %RH and temperature in 1/100 °C
+fn percent(humidity: u16) -> f32 {
+    humidity as f32 / 100.0
+}
+fn celsius(temperature: i16) -> f32 {
+    temperature as f32 / 100.0
+}
+
+async fn humidity_task(
+    mut bricklet: HumidityV2Bricklet,
+    event_registry: EventRegistry,
+    settings: HumiditySettings,
+    termination_receiver: LifeLineEnd,
+) -> Result<(), HumidityError> {
+    bricklet
+        .set_humidity_callback_configuration(SetHumidityCallbackConfigurationRequest {
+            period: 10000,
+            value_has_to_change: true,
+            option: ThresholdOption::Off,
+            min: 0,
+            max: 0,
+        })
+        .await?;
+    let temperature_sender = if let Some(key) = settings.temperature {
+        bricklet
+            .set_temperature_callback_configuration(SetTemperatureCallbackConfigurationRequest {
+                period: 10000,
+                value_has_to_change: true,
+                option: ThresholdOption::Off,
+                min: 0,
+                max: 0,
+            })
+            .await?;
+        let sender = event_registry.temperature_sender(key).await;
+        sender
+            .send(celsius(bricklet.get_temperature().await?))
+            .await?;
+        Some(sender)
+    } else {
+        None
+    };
+
+    let mut stream = bricklet
+        .humidity_stream()
+        .await
+        .map(|h| HumidityEvent::Humidity(percent(h)))
+        .merge(
+            bricklet
+                .temperature_stream()
+                .await
+                .map(|t| HumidityEvent::Temperature(celsius(t))),
+        )
+        .merge(termination_receiver.send_on_terminate(HumidityEvent::Closed));
+    let humidity_sender = event_registry.humidity_sender(settings.output).await;
+    humidity_sender
+        .send(percent(bricklet.get_humidity().await?))
+        .await?;
+    while let Some(event) = stream.next().await {
+        match event {
+            HumidityEvent::Humidity(humidity) => {
+                humidity_sender.send(humidity).await?;
+            }
+            HumidityEvent::Temperature(temperature) => {
+                if let Some(sender) = &temperature_sender {
+                    sender.send(temperature).await?;
+                }
+            }
+            HumidityEvent::Closed => break,
+        }
+    }
+    drop(termination_receiver);
+    Ok(())
+}
@@ -1,7 +1,9 @@
 use core::option::Option;
 use std::{
+    hash::Hash,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures::Stream;
@@ -13,7 +15,11 @@ use tinkerforge_async::{
     io_16::{InterruptCallback, Io16Bricklet, SetPortInterruptRequest},
     io_16_v_2::{Io16V2Bricklet, SetInputValueCallbackConfigurationRequest},
 };
-use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{sleep, Instant},
+};
 use tokio_stream::{empty, wrappers::ReceiverStream, StreamExt};
 use tokio_util::either::Either;
 
@@ -21,7 +27,7 @@ use crate::{
     data::{
         registry::{ButtonState, DualButtonLayout, EventRegistry, SingleButtonLayout},
         state::StateUpdateMessage,
-        wiring::ButtonSetting,
+        wiring::{ButtonSetting, ButtonTiming},
     },
     terminator::LifeLineEnd,
 };
@@ -32,9 +38,10 @@ pub async fn handle_io16(
     buttons: &[ButtonSetting],
 ) -> LifeLineEnd {
     let (foreign_end, my_end) = LifeLineEnd::create();
-    let channel_settings = collect_16_channel_settings(event_registry, buttons).await;
+    let (channel_settings, channel_timings) =
+        collect_16_channel_settings(event_registry, buttons).await;
     tokio::spawn(async move {
-        let result = io_16_v1_loop(bricklet, my_end, channel_settings).await;
+        let result = io_16_v1_loop(bricklet, my_end, channel_settings, channel_timings).await;
         match result {
             Err(error) => {
                 error!("Cannot communicate with Io16V2Bricklet: {error}");
@@ -50,19 +57,22 @@ pub async fn handle_io16(
 async fn collect_16_channel_settings(
     event_registry: EventRegistry,
     buttons: &[ButtonSetting],
-) -> [ChannelSetting; 16] {
+) -> ([ChannelSetting; 16], [ButtonTiming; 16]) {
     let mut channel_settings = <[ChannelSetting; 16]>::default();
+    let mut channel_timings = <[ButtonTiming; 16]>::default();
     for setting in buttons {
         match setting {
             ButtonSetting::Dual {
                 up_button,
                 down_button,
                 output,
+                timing,
             } => {
                 if let Some(b) = channel_settings.get_mut(*up_button as usize) {
                     *b = ChannelSetting::DualButtonUp(
                         event_registry.dual_button_sender(*output).await,
                     );
+                    channel_timings[*up_button as usize] = *timing;
                 } else {
                     error!("On Button out of range: {}", up_button);
                 }
@@ -70,22 +80,28 @@ async fn collect_16_channel_settings(
                     *b = ChannelSetting::DualButtonDown(
                         event_registry.dual_button_sender(*output).await,
                     );
+                    channel_timings[*down_button as usize] = *timing;
                 } else {
                     error!("Off Button out of range: {}", up_button);
                 }
             }
-            ButtonSetting::Single { button, output } => {
+            ButtonSetting::Single {
+                button,
+                output,
+                timing,
+            } => {
                 if let Some(b) = channel_settings.get_mut(*button as usize) {
                     *b = ChannelSetting::SingleButton(
                         event_registry.single_button_sender(*output).await,
                     );
+                    channel_timings[*button as usize] = *timing;
                 } else {
                     error!("Button out of range: {}", button);
                 }
             }
         }
     }
-    channel_settings
+    (channel_settings, channel_timings)
 }
 
 struct ByteMaskIterator {
@@ -131,6 +147,7 @@ async fn io_16_v1_loop(
     mut bricklet: Io16Bricklet,
     rx: LifeLineEnd,
     channel_settings: [ChannelSetting; 16],
+    channel_timings: [ButtonTiming; 16],
 ) -> Result<(), IoHandlerError> {
     bricklet.set_debounce_period(30).await?;
     bricklet
@@ -167,7 +184,7 @@ async fn io_16_v1_loop(
             }
         },
     );
-    io_16_loop(rx, channel_settings, button_event_stream).await?;
+    io_16_loop(rx, channel_settings, channel_timings, button_event_stream).await?;
     Ok(())
 }
 
@@ -177,9 +194,10 @@ pub async fn handle_io16_v2(
     buttons: &[ButtonSetting],
 ) -> LifeLineEnd {
     let (foreign_end, my_end) = LifeLineEnd::create();
-    let channel_settings = collect_16_channel_settings(event_registry, buttons).await;
+    let (channel_settings, channel_timings) =
+        collect_16_channel_settings(event_registry, buttons).await;
     tokio::spawn(async move {
-        match io_16_v2_loop(bricklet, my_end, channel_settings).await {
+        match io_16_v2_loop(bricklet, my_end, channel_settings, channel_timings).await {
             Err(error) => {
                 error!("Cannot communicate with Io16V2Bricklet: {error}");
             }
@@ -212,6 +230,7 @@ async fn io_16_v2_loop(
     mut bricklet: Io16V2Bricklet,
     termination_receiver: LifeLineEnd,
     channel_settings: [ChannelSetting; 16],
+    channel_timings: [ButtonTiming; 16],
 ) -> Result<(), IoHandlerError> {
     for i in 0..16 {
         info!("Prepare channel {i}");
@@ -231,17 +250,25 @@ async fn io_16_v2_loop(
             IoMessage::Release(event.channel)
         }
     });
-    io_16_loop(termination_receiver, channel_settings, button_event_stream).await?;
+    io_16_loop(
+        termination_receiver,
+        channel_settings,
+        channel_timings,
+        button_event_stream,
+    )
+    .await?;
     Ok(())
 }
 
 async fn io_16_loop(
     termination_receiver: LifeLineEnd,
     channel_settings: [ChannelSetting; 16],
+    channel_timings: [ButtonTiming; 16],
     button_event_stream: impl Stream<Item = IoMessage> + Sized + Unpin,
 ) -> Result<(), IoHandlerError> {
     let (rx, tx) = mpsc::channel(2);
     let mut channel_timer: [Option<JoinHandle<()>>; 16] = <[Option<JoinHandle<()>>; 16]>::default();
+    let mut channel_clicks = <[ClickCounter; 16]>::default();
     let mut receiver = button_event_stream
         .merge(termination_receiver.send_on_terminate(IoMessage::Close))
         .merge(ReceiverStream::new(tx));
@@ -249,19 +276,27 @@ async fn io_16_loop(
         match message {
             IoMessage::Close => break,
             IoMessage::Press(channel) => {
+                let timing = channel_timings
+                    .get(channel as usize)
+                    .copied()
+                    .unwrap_or_default();
+                let clicks = channel_clicks
+                    .get_mut(channel as usize)
+                    .map(|c| c.press(Instant::now(), timing.multi_click))
+                    .unwrap_or(1);
                 match channel_settings.get(channel as usize) {
                     None => {}
                     Some(ChannelSetting::DualButtonDown(sender)) => sender
-                        .send(ButtonState::ShortPressStart(DualButtonLayout::Down))
+                        .send(press_state(clicks, DualButtonLayout::Down))
                         .await
                         .map_err(IoHandlerError::DualButtonDown)?,
                     Some(ChannelSetting::DualButtonUp(sender)) => sender
-                        .send(ButtonState::ShortPressStart(DualButtonLayout::Up))
+                        .send(press_state(clicks, DualButtonLayout::Up))
                         .await
                         .map_err(IoHandlerError::DualButtonUp)?,
                     Some(ChannelSetting::None) => {}
                     Some(ChannelSetting::SingleButton(sender)) => sender
-                        .send(ButtonState::ShortPressStart(SingleButtonLayout))
+                        .send(press_state(clicks, SingleButtonLayout))
                         .await
                         .map_err(IoHandlerError::SingleButton)?,
                 }
@@ -272,7 +307,7 @@ async fn io_16_loop(
                         .get_mut(channel as usize)
                         .and_then(|timer_option| {
                             timer_option.replace(tokio::spawn(async move {
-                                sleep(core::time::Duration::from_millis(500)).await;
+                                sleep(timing.long_press).await;
                                 if let Err(error) = rx.send(IoMessage::LongPress(channel)).await {
                                     error!("Cannot send message: {error}");
                                 }
@@ -283,6 +318,14 @@ async fn io_16_loop(
                 }
             }
             IoMessage::LongPress(channel) => {
+                if let Some(clicks) = channel_clicks.get_mut(channel as usize) {
+                    clicks.long_press();
+                }
+                // contacts held for a long time, like windows, are never released by the timer
+                let max_press = channel_timings
+                    .get(channel as usize)
+                    .filter(|t| !t.max_press.is_zero())
+                    .map(|t| t.max_press.saturating_sub(t.long_press));
                 match channel_settings.get(channel as usize) {
                     None => {}
                     Some(ChannelSetting::DualButtonDown(sender)) => sender
@@ -299,13 +342,16 @@ async fn io_16_loop(
                         .await
                         .map_err(IoHandlerError::SingleButton)?,
                 }
+                let Some(max_press) = max_press else {
+                    continue;
+                };
                 let rx = rx.clone();
                 if let Some(running) =
                     channel_timer
                         .get_mut(channel as usize)
                         .and_then(|timer_option| {
                             timer_option.replace(tokio::spawn(async move {
-                                sleep(core::time::Duration::from_secs(20)).await;
+                                sleep(max_press).await;
                                 if let Err(error) = rx.send(IoMessage::Release(channel)).await {
                                     error!("Cannot send message: {error}");
                                 }
@@ -316,6 +362,9 @@ async fn io_16_loop(
                 }
             }
             IoMessage::Release(channel) => {
+                if let Some(clicks) = channel_clicks.get_mut(channel as usize) {
+                    clicks.release(Instant::now());
+                }
                 match channel_settings.get(channel as usize) {
                     None => {}
                     Some(ChannelSetting::DualButtonDown(sender)) => sender
@@ -345,6 +394,45 @@ async fn io_16_loop(
     Ok(())
 }
 
+fn press_state<B: Copy + Eq + Hash>(clicks: u8, button: B) -> ButtonState<B> {
+    match clicks {
+        0 | 1 => ButtonState::ShortPressStart(button),
+        2 => ButtonState::DoubleClickStart(button),
+        _ => ButtonState::TripleClickStart(button),
+    }
+}
+
+/// Counts presses following each other within the multi click interval
+#[derive(Debug, Default)]
+struct ClickCounter {
+    clicks: u8,
+    last_release: Option<Instant>,
+    long_press: bool,
+}
+
+impl ClickCounter {
+    fn press(&mut self, now: Instant, multi_click: Duration) -> u8 {
+        let in_sequence = !self.long_press
+            && self
+                .last_release
+                .is_some_and(|release| now.duration_since(release) <= multi_click);
+        self.clicks = if in_sequence {
+            self.clicks.saturating_add(1)
+        } else {
+            1
+        };
+        self.long_press = false;
+        self.last_release = None;
+        self.clicks
+    }
+    fn long_press(&mut self) {
+        self.long_press = true;
+    }
+    fn release(&mut self, now: Instant) {
+        self.last_release = Some(now);
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum IoHandlerError {
     #[error("Cannot communicate to device: {0}")]
@@ -367,7 +455,30 @@ pub enum IoHandlerError {
 
 #[cfg(test)]
 mod test {
-    use crate::devices::io_handler::{ByteMaskIterator, IoMessage};
+    use std::time::Duration;
+
+    use tokio::{
+        sync::{mpsc, watch},
+        time::{sleep, Instant},
+    };
+    use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+    use crate::{
+        controller::{
+            heat::{heat_controller, HeatProtection},
+            manager::ControllerState,
+        },
+        data::{
+            registry::{
+                EventRegistry, SingleButtonKey, SwitchOutputKey, TemperatureKey, WindowOpenKey,
+            },
+            wiring::{ButtonSetting, ButtonTiming, WindowOpenDetection},
+        },
+        devices::io_handler::{
+            collect_16_channel_settings, io_16_loop, ByteMaskIterator, ClickCounter, IoMessage,
+        },
+        terminator::{JoinHandleTerminator, LifeLineEnd},
+    };
 
     #[test]
     fn test_byte_mask() {
@@ -397,4 +508,101 @@ mod test {
             })
         );
     }
+
+    #[test]
+    fn test_click_counter() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(300);
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut counter = ClickCounter::default();
+        assert_eq!(1, counter.press(at(0), interval));
+        counter.release(at(100));
+        assert_eq!(2, counter.press(at(300), interval));
+        counter.release(at(400));
+        assert_eq!(3, counter.press(at(600), interval));
+        counter.release(at(700));
+        assert_eq!(1, counter.press(at(1100), interval));
+        counter.long_press();
+        counter.release(at(1800));
+        assert_eq!(1, counter.press(at(1900), interval));
+        counter.release(at(2000));
+        assert_eq!(1, counter.press(at(2100), Duration::ZERO));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_window_contact_held_open() {
+        let contact = SingleButtonKey::Button(Default::default());
+        let current = TemperatureKey::CurrentTemperature(Default::default());
+        let heat = SwitchOutputKey::Heat(Default::default());
+        let window = WindowOpenKey(Default::default());
+        let event_registry = EventRegistry::new(None);
+        // window contacts are wired without a forced release
+        let timing = ButtonTiming {
+            max_press: Duration::ZERO,
+            ..Default::default()
+        };
+        let (channel_settings, channel_timings) = collect_16_channel_settings(
+            event_registry.clone(),
+            &[ButtonSetting::Single {
+                button: 0,
+                output: contact,
+                timing,
+            }],
+        )
+        .await;
+        let (state, _) = watch::channel(ControllerState::default());
+        let _controller = JoinHandleTerminator::new(
+            heat_controller(
+                &event_registry,
+                current,
+                TemperatureKey::TargetTemperature(Default::default()),
+                heat,
+                Some(&WindowOpenDetection {
+                    window_contacts: Box::new([contact]),
+                    temperature_drop: None,
+                    drop_interval: Duration::from_secs(180),
+                    suspend_time: Duration::from_secs(3600),
+                    state_output: window,
+                }),
+                HeatProtection {
+                    frost_temperature: 7.0,
+                    eco_temperature: 17.0,
+                    alarm_temperature_change: 1.0,
+                    alarm_settle_time: Duration::from_secs(600),
+                },
+                state,
+            )
+            .await,
+        );
+        let (io_tx, io_rx) = mpsc::channel(4);
+        let (_bricklet_end, handler_end) = LifeLineEnd::create();
+        let _io = JoinHandleTerminator::new(tokio::spawn(io_16_loop(
+            handler_end,
+            channel_settings,
+            channel_timings,
+            ReceiverStream::new(io_rx),
+        )));
+        let heating = || async { event_registry.switch_stream(heat).await.next().await };
+        event_registry
+            .temperature_sender(current)
+            .await
+            .send(18.0)
+            .await
+            .unwrap();
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(Some(true), heating().await);
+
+        io_tx.send(IoMessage::Press(0)).await.unwrap();
+        // held open far beyond the default max press time
+        sleep(Duration::from_secs(60)).await;
+        assert_eq!(Some(false), heating().await);
+        assert_eq!(
+            Some(true),
+            event_registry.window_open_stream(window).await.next().await
+        );
+
+        io_tx.send(IoMessage::Release(0)).await.unwrap();
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(Some(true), heating().await);
+    }
 }
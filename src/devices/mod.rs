@@ -1,11 +1,13 @@
 use std::{collections::HashMap, fmt::Debug, net::IpAddr, sync::Arc, time::Duration};
 
-use log::{error, info};
+use log::{error, info, warn};
 use thiserror::Error;
 use tinkerforge_async::{
+    ambient_light_v_3::AmbientLightV3Bricklet,
     base58::Uid,
     dmx::DmxBricklet,
     error::TinkerforgeError,
+    humidity_v_2::HumidityV2Bricklet,
     industrial_quad_relay_v_2::IndustrialQuadRelayV2Bricklet,
     io_16::Io16Bricklet,
     io_16_v_2::Io16V2Bricklet,
@@ -19,9 +21,12 @@ use tokio::{
     pin,
     sync::mpsc,
     task,
-    time::{interval, sleep},
+    time::{interval, sleep, Instant},
+};
+use tokio_stream::{
+    wrappers::{IntervalStream, UnboundedReceiverStream},
+    StreamExt,
 };
-use tokio_stream::{wrappers::IntervalStream, StreamExt};
 
 use crate::{
     data::{
@@ -29,18 +34,23 @@ use crate::{
         state::StateUpdateMessage, wiring::TinkerforgeDevices,
     },
     devices::{
+        ambient_light::handle_ambient_light,
         dmx_handler::handle_dmx,
+        humidity::handle_humidity,
         io_handler::{handle_io16, handle_io16_v2},
         motion_detector::handle_motion_detector,
         relay::handle_quad_relay,
         screen_data_renderer::{show_debug_text, start_screen_thread},
         temperature::handle_temperature,
     },
+    supervisor::RestartBackoff,
     terminator::{LifeLineEnd, TestamentReceiver, TestamentSender},
 };
 
+pub mod ambient_light;
 pub mod display;
 pub mod dmx_handler;
+pub mod humidity;
 pub mod io_handler;
 pub mod motion_detector;
 pub mod relay;
@@ -118,6 +128,13 @@ enum TfBridgeError {
     StatusUpdateMessage(#[from] mpsc::error::SendError<StateUpdateMessage>),
 }
 
+/// Enumeration of a registered bricklet, replayed to restart its handler after a failure
+struct DeviceRestart {
+    packet: EnumerateResponse,
+    backoff: RestartBackoff,
+    restart_at: Option<Instant>,
+}
+
 async fn run_enumeration_listener(
     addr: (IpAddr, u16),
     event_registry: EventRegistry,
@@ -126,6 +143,8 @@ async fn run_enumeration_listener(
     status_updater: mpsc::Sender<StateUpdateMessage>,
 ) -> Result<(), TfBridgeError> {
     let mut registered_devices: HashMap<Uid, LifeLineEnd> = HashMap::new();
+    let mut device_restarts: HashMap<Uid, DeviceRestart> = HashMap::new();
+    let (restart_tx, restart_rx) = mpsc::unbounded_channel();
 
     let mut ipcon = AsyncIpConnection::new(addr).await?;
     //let endpoint_addr = addr.0.clone();
@@ -143,6 +162,7 @@ async fn run_enumeration_listener(
             IntervalStream::new(interval(Duration::from_secs(10)))
                 .map(|_| EnumerationListenerEvent::Ping),
         )
+        .merge(UnboundedReceiverStream::new(restart_rx).map(EnumerationListenerEvent::Packet))
         //.merge(ReceiverStream::new(terminated_rx).map(EnumerationListenerEvent::TerminatedClient))
         ;
     status_updater
@@ -154,6 +174,31 @@ async fn run_enumeration_listener(
             EnumerationListenerEvent::Ping => {
                 //info!("Ping: {}", addr.0);
                 ipcon.disconnect_probe().await?;
+                let now = Instant::now();
+                for (uid, live_end) in registered_devices.iter() {
+                    let Some(restart) = device_restarts.get_mut(uid) else {
+                        continue;
+                    };
+                    if live_end.is_alive() {
+                        restart.backoff.check_stable();
+                        continue;
+                    }
+                    match restart.restart_at {
+                        None => {
+                            let delay = restart.backoff.failed();
+                            warn!("Handler of {uid} terminated, restarting in {delay:?}");
+                            restart.restart_at = Some(now + delay);
+                        }
+                        Some(restart_at) if restart_at <= now => {
+                            restart.restart_at = None;
+                            restart.backoff.restarted();
+                            if restart_tx.send(restart.packet.clone()).is_err() {
+                                error!("Cannot restart handler of {uid}");
+                            }
+                        }
+                        Some(_) => {}
+                    }
+                }
             }
             EnumerationListenerEvent::Packet(paket) => {
                 let uid = paket.uid;
@@ -192,7 +237,7 @@ async fn run_enumeration_listener(
                                         let sender = start_screen_thread(
                                             Lcd128X64Bricklet::new(uid, ipcon.clone()),
                                             event_registry.clone(),
-                                            *screen_settings,
+                                            screen_settings.clone(),
                                         )
                                         .await;
                                         register_handle(&mut registered_devices, uid, sender).await;
@@ -301,6 +346,44 @@ async fn run_enumeration_listener(
                                         info!("Found unused Temperature Sensor {uid} on {addr:?}");
                                     }
                                 }
+                                DeviceIdentifier::AmbientLightV3Bricklet => {
+                                    if let Some(settings) =
+                                        tinkerforge_devices.ambient_light_sensors.get(&uid)
+                                    {
+                                        register_handle(
+                                            &mut registered_devices,
+                                            uid,
+                                            handle_ambient_light(
+                                                AmbientLightV3Bricklet::new(uid, ipcon.clone()),
+                                                event_registry.clone(),
+                                                settings.output,
+                                            ),
+                                        )
+                                        .await;
+                                    } else {
+                                        info!(
+                                            "Found unused Ambient Light Sensor {uid} on {addr:?}"
+                                        );
+                                    }
+                                }
+                                DeviceIdentifier::HumidityV2Bricklet => {
+                                    if let Some(settings) =
+                                        tinkerforge_devices.humidity_sensors.get(&uid)
+                                    {
+                                        register_handle(
+                                            &mut registered_devices,
+                                            uid,
+                                            handle_humidity(
+                                                HumidityV2Bricklet::new(uid, ipcon.clone()),
+                                                event_registry.clone(),
+                                                *settings,
+                                            ),
+                                        )
+                                        .await;
+                                    } else {
+                                        info!("Found unused Humidity Sensor {uid} on {addr:?}");
+                                    }
+                                }
                                 DeviceIdentifier::IndustrialQuadRelayV2Bricklet => {
                                     if let Some(settings) = tinkerforge_devices.relays.get(&uid) {
                                         register_handle(
@@ -324,6 +407,11 @@ async fn run_enumeration_listener(
 
                                 _ => {}
                             }
+                            device_restarts.entry(uid).or_insert_with(|| DeviceRestart {
+                                packet: paket.clone(),
+                                backoff: RestartBackoff::new(format!("bricklet-{uid}")),
+                                restart_at: None,
+                            });
                             if let Some(running_registration) = registered_devices.get(&uid) {
                                 running_registration.update_on_terminate(
                                     StateUpdateMessage::BrickletDisconnected {
@@ -349,6 +437,7 @@ async fn run_enumeration_listener(
                         info!("Disconnected device: {}", uid);
                         device_testaments.remove(&uid);
                         registered_devices.remove(&uid);
+                        device_restarts.remove(&uid);
                     }
                     EnumerationType::Unknown => {
                         info!("Unknown Event: {:?}", paket);
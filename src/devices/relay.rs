@@ -26,11 +26,12 @@ pub async fn handle_quad_relay(
     let mut streams = SelectAll::new();
     for channel_entry in inputs {
         let channel = channel_entry.channel;
+        let interlock = channel_entry.interlock;
         streams.push(
             event_registry
                 .switch_stream(channel_entry.input)
                 .await
-                .map(move |state| RelayMsg::SetState(channel, state)),
+                .map(move |state| RelayMsg::SetState(channel, state, interlock)),
         );
     }
     let input_streams = streams.merge(rx.send_on_terminate(RelayMsg::Closed));
@@ -44,7 +45,7 @@ pub async fn handle_quad_relay(
 }
 
 enum RelayMsg {
-    SetState(u8, bool),
+    SetState(u8, bool, Option<u8>),
     UpdateState,
     Closed,
 }
@@ -69,9 +70,12 @@ async fn quad_relay_task(
     let mut timer_handle = Some(start_send_timer(&tx));
     while let Some(event) = stream.next().await {
         match event {
-            RelayMsg::SetState(channel, state) => {
+            RelayMsg::SetState(channel, state, interlock) => {
                 let mut new_value = current_value;
                 new_value[channel as usize] = state;
+                if let Some(interlock) = interlock.filter(|_| state) {
+                    new_value[interlock as usize] = false;
+                }
                 if new_value != current_value {
                     current_value = new_value;
                     if let Some(old_timer) = timer_handle.replace(start_send_timer(&tx)) {
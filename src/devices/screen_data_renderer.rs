@@ -1,4 +1,4 @@
-use chrono::{DateTime, Timelike, Utc};
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
 use chrono_tz::Tz;
 use embedded_graphics::{
     draw_target::DrawTarget,
@@ -10,7 +10,7 @@ use embedded_graphics::{
     primitives::Rectangle,
     text::Text,
 };
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use simple_layout::prelude::{
     bordered, center, expand, horizontal_layout, optional_placement, owned_text, padding, scale,
     vertical_layout, DashedLine, Layoutable, RoundedLine,
@@ -42,7 +42,7 @@ use tokio_util::either::Either;
 
 use crate::{
     data::{
-        registry::EventRegistry,
+        registry::{EventRegistry, HouseMode, SceneCommand},
         state::StateUpdateMessage,
         wiring::{Orientation, ScreenSettings},
     },
@@ -54,6 +54,8 @@ use crate::{
 
 const TEXT_STYLE: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&FONT_6X12, BinaryColor::On);
 const BIG_TEXT_STYLE: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+/// step of the wake-up time per touch
+const ALARM_TIME_STEP: chrono::Duration = chrono::Duration::minutes(15);
 
 pub struct ScreenData<
     LT: Layoutable<BinaryColor>,
@@ -62,9 +64,13 @@ pub struct ScreenData<
 > {
     current_time: DateTime<Tz>,
     measured_temperature: Option<f32>,
+    window_open: bool,
     configured_temperature: Option<AdjustableValue<f32, LT, BinaryColor>>,
     whitebalance: Option<AdjustableValue<Saturating<u16>, LWB, BinaryColor>>,
     brightness: Option<AdjustableValue<Saturating<u8>, LBR, BinaryColor>>,
+    scene: Option<SceneElement>,
+    house_mode: Option<HouseModeElement>,
+    alarm: Option<AlarmElement>,
 }
 
 impl<LT: Layoutable<BinaryColor>, LWB: Layoutable<BinaryColor>, LBR: Layoutable<BinaryColor>>
@@ -76,6 +82,9 @@ impl<LT: Layoutable<BinaryColor>, LWB: Layoutable<BinaryColor>, LBR: Layoutable<
     pub fn set_current_tempterature(&mut self, value: f32) {
         self.measured_temperature = Some(value);
     }
+    pub fn set_window_open(&mut self, value: bool) {
+        self.window_open = value;
+    }
     pub fn set_configured_temperature(&mut self, value: f32) {
         if let Some(a) = self.configured_temperature.as_mut() {
             a.current_value = value;
@@ -91,6 +100,16 @@ impl<LT: Layoutable<BinaryColor>, LWB: Layoutable<BinaryColor>, LBR: Layoutable<
             a.current_value = Saturating(value);
         }
     }
+    pub fn set_house_mode(&mut self, value: HouseMode) {
+        if let Some(e) = self.house_mode.as_mut() {
+            e.mode = value;
+        }
+    }
+    pub fn set_alarm_time(&mut self, value: Option<NaiveTime>) {
+        if let Some(e) = self.alarm.as_mut() {
+            e.set_time(value);
+        }
+    }
     pub fn draw<DrawError>(
         &mut self,
         target: &mut impl DrawTarget<Color = BinaryColor, Error = DrawError>,
@@ -109,20 +128,33 @@ impl<LT: Layoutable<BinaryColor>, LWB: Layoutable<BinaryColor>, LBR: Layoutable<
             .as_mut()
             .map(AdjustableValue::element);
 
+        let window_open = self.window_open;
         let measured_temperature = self.measured_temperature.map(|temp_value| {
             expand(bordered(
-                center(owned_text(format!("{temp_value:.1}°C"), TEXT_STYLE)),
+                center(
+                    vertical_layout(owned_text(format!("{temp_value:.1}°C"), TEXT_STYLE), 0)
+                        .append(
+                            window_open.then(|| owned_text("Airing".to_string(), TEXT_STYLE)),
+                            0,
+                        ),
+                ),
                 DashedLine::new(2, 2, BinaryColor::On),
             ))
         });
         let whitebalance = self.whitebalance.as_mut().map(AdjustableValue::element);
         let brightness = self.brightness.as_mut().map(AdjustableValue::element);
+        let scene = self.scene.as_mut().map(SceneElement::element);
+        let house_mode = self.house_mode.as_mut().map(HouseModeElement::element);
+        let alarm = self.alarm.as_mut().map(AlarmElement::element);
         if rectangle.size.width > rectangle.size.height {
             horizontal_layout(
                 padding(
                     vertical_layout(center(clock_text), 0)
                         .append(measured_temperature, 2)
-                        .append(temperature_element, 1),
+                        .append(temperature_element, 1)
+                        .append(scene, 1)
+                        .append(house_mode, 1)
+                        .append(alarm, 1),
                     0,
                     1,
                     0,
@@ -138,11 +170,32 @@ impl<LT: Layoutable<BinaryColor>, LWB: Layoutable<BinaryColor>, LBR: Layoutable<
                 .append(whitebalance, 1)
                 .append(brightness, 1)
                 .append(temperature_element, 1)
+                .append(scene, 1)
+                .append(house_mode, 1)
+                .append(alarm, 1)
                 .draw_placed(target, rectangle)?;
         }
         Ok(())
     }
     pub fn process_touch(&self, p: Point) -> Option<AdjustEvent> {
+        if self
+            .scene
+            .as_ref()
+            .and_then(|s| s.button)
+            .is_some_and(|r| r.contains(p))
+        {
+            return Some(AdjustEvent::Scene);
+        }
+        if let Some(e) = self
+            .house_mode
+            .as_ref()
+            .filter(|e| e.button.is_some_and(|r| r.contains(p)))
+        {
+            return Some(AdjustEvent::HouseMode(e.mode.next()));
+        }
+        if let Some(time) = self.alarm.as_ref().and_then(|e| e.detect_adjustment(p)) {
+            return Some(AdjustEvent::AlarmTime(time));
+        }
         self.configured_temperature
             .as_ref()
             .and_then(|e| e.detect_adjustment(p))
@@ -167,6 +220,103 @@ pub enum AdjustEvent {
     Whitebalance(Adjustment<Saturating<u16>>),
     Brightness(Adjustment<Saturating<u8>>),
     Temperature(Adjustment<f32>),
+    Scene,
+    HouseMode(HouseMode),
+    AlarmTime(Option<NaiveTime>),
+}
+
+/// Button recalling a scene
+struct SceneElement {
+    name: Box<str>,
+    button: Option<Rectangle>,
+}
+
+impl SceneElement {
+    pub fn element(&mut self) -> impl Layoutable<BinaryColor> + '_ {
+        expand(center(optional_placement(
+            &mut self.button,
+            bordered(
+                padding(owned_text(self.name.to_string(), TEXT_STYLE), -2, 1, -1, 1),
+                RoundedLine::new(BinaryColor::On),
+            ),
+        )))
+    }
+}
+
+/// Button showing the house mode, a touch switches to the next one
+struct HouseModeElement {
+    mode: HouseMode,
+    button: Option<Rectangle>,
+}
+
+impl HouseModeElement {
+    pub fn element(&mut self) -> impl Layoutable<BinaryColor> + '_ {
+        expand(center(optional_placement(
+            &mut self.button,
+            bordered(
+                padding(
+                    owned_text(format!("{:?}", self.mode), TEXT_STYLE),
+                    -2,
+                    1,
+                    -1,
+                    1,
+                ),
+                RoundedLine::new(BinaryColor::On),
+            ),
+        )))
+    }
+}
+
+/// Wake-up time, a touch on the time switches the alarm on and off
+struct AlarmElement {
+    time: Option<NaiveTime>,
+    /// restored when the alarm is switched on again
+    last_time: NaiveTime,
+    toggle_button: Option<Rectangle>,
+    plus_button: Option<Rectangle>,
+    minus_button: Option<Rectangle>,
+}
+
+impl AlarmElement {
+    fn set_time(&mut self, time: Option<NaiveTime>) {
+        self.time = time;
+        if let Some(time) = time {
+            self.last_time = time;
+        }
+    }
+    pub fn element(&mut self) -> impl Layoutable<BinaryColor> + '_ {
+        let label = self
+            .time
+            .map(|time| time.format("%H:%M").to_string())
+            .unwrap_or_else(|| "Off".to_string());
+        show_adjustable_value(
+            &mut self.minus_button,
+            &mut self.plus_button,
+            center(optional_placement(
+                &mut self.toggle_button,
+                bordered(
+                    padding(owned_text(label, TEXT_STYLE), -2, 1, -1, 1),
+                    RoundedLine::new(BinaryColor::On),
+                ),
+            )),
+        )
+    }
+    /// New alarm time, the buttons shifting the time do nothing while the alarm is off
+    pub fn detect_adjustment(&self, p: Point) -> Option<Option<NaiveTime>> {
+        let touched = |button: Option<Rectangle>| button.is_some_and(|r| r.contains(p));
+        if touched(self.toggle_button) {
+            Some(match self.time {
+                Some(_) => None,
+                None => Some(self.last_time),
+            })
+        } else if touched(self.minus_button) {
+            self.time.map(|time| Some(time - ALARM_TIME_STEP))
+        } else if touched(self.plus_button) {
+            self.time.map(|time| Some(time + ALARM_TIME_STEP))
+        } else {
+            None
+        }
+    }
 }
 
 struct AdjustableValue<V, L, C>
@@ -247,6 +397,9 @@ pub fn screen_data(
     show_change_temp: bool,
     show_color: bool,
     show_brightness: bool,
+    scene: Option<Box<str>>,
+    show_house_mode: bool,
+    show_alarm: bool,
 ) -> ScreenData<
     impl Layoutable<BinaryColor> + Sized,
     impl Layoutable<BinaryColor> + Sized,
@@ -317,9 +470,22 @@ pub fn screen_data(
     ScreenData {
         current_time: Utc::now().with_timezone(&Tz::default()),
         measured_temperature: None,
+        window_open: false,
         configured_temperature,
         whitebalance,
         brightness,
+        scene: scene.map(|name| SceneElement { name, button: None }),
+        house_mode: show_house_mode.then_some(HouseModeElement {
+            mode: HouseMode::Home,
+            button: None,
+        }),
+        alarm: show_alarm.then(|| AlarmElement {
+            time: None,
+            last_time: NaiveTime::from_hms_opt(6, 30, 0).expect("Invalid default alarm time"),
+            toggle_button: None,
+            plus_button: None,
+            minus_button: None,
+        }),
     }
 }
 
@@ -399,6 +565,10 @@ pub enum ScreenDataError {
     UpdateWhitebalance(mpsc::error::SendError<Saturating<u16>>),
     #[error("Cannot update brightness {0}")]
     UpdateBrightness(mpsc::error::SendError<Saturating<u8>>),
+    #[error("Cannot update house mode {0}")]
+    UpdateHouseMode(mpsc::error::SendError<HouseMode>),
+    #[error("Cannot update alarm time {0}")]
+    UpdateAlarmTime(mpsc::error::SendError<Option<NaiveTime>>),
     #[error("Cannot send state update {0}")]
     StateUpdate(#[from] mpsc::error::SendError<StateUpdateMessage>),
     #[error("Cannot parse UID {0}")]
@@ -414,6 +584,9 @@ enum ScreenMessage {
     UpdateTemperature(f32),
     UpdateLightColor(Saturating<u16>),
     UpdateBrightness(Saturating<u8>),
+    UpdateWindowOpen(bool),
+    UpdateHouseMode(HouseMode),
+    UpdateAlarmTime(Option<NaiveTime>),
     PollCounters,
 }
 
@@ -447,6 +620,10 @@ async fn screen_thread_loop(
         adjust_temperature_key,
         light_color_key,
         brightness_key,
+        window_open_key,
+        scene,
+        house_mode,
+        alarm_time_key,
     } = settings;
     let uid = bricklet.uid();
     let mut display = Lcd128x64BrickletDisplay::new(bricklet, orientation).await?;
@@ -503,8 +680,38 @@ async fn screen_thread_loop(
             (Either::Right(empty::<ScreenMessage>()), None)
         };
 
-    let (clock_stream, current_temperature_stream) =
-        join!(clock_stream_future, current_temperature_stream_future);
+    let er = event_registry.clone();
+    let window_open_stream_future = util::optional_stream(window_open_key.map(|key| async move {
+        er.window_open_stream(key)
+            .await
+            .map(ScreenMessage::UpdateWindowOpen)
+    }));
+
+    let er = event_registry.clone();
+    let house_mode_stream_future = util::optional_stream(house_mode.then_some(async move {
+        er.house_mode_stream()
+            .await
+            .map(ScreenMessage::UpdateHouseMode)
+    }));
+
+    let (update_alarm_time_stream, update_alarm_time_sender) =
+        if let Some(alarm_time_key) = alarm_time_key {
+            let current_value_stream = event_registry.alarm_time_stream(alarm_time_key).await;
+            let value_update_sender = event_registry.alarm_time_sender(alarm_time_key).await;
+            (
+                Either::Left(current_value_stream.map(ScreenMessage::UpdateAlarmTime)),
+                Some(value_update_sender),
+            )
+        } else {
+            (Either::Right(empty::<ScreenMessage>()), None)
+        };
+
+    let (clock_stream, current_temperature_stream, window_open_stream, house_mode_stream) = join!(
+        clock_stream_future,
+        current_temperature_stream_future,
+        window_open_stream_future,
+        house_mode_stream_future
+    );
 
     let mut message_stream = StreamNotifyClose::new(display.input_stream().await?)
         .map(|event| match event {
@@ -524,6 +731,9 @@ async fn screen_thread_loop(
         .merge(adjust_temperature_stream)
         .merge(update_color_stream)
         .merge(update_brightness_stream)
+        .merge(window_open_stream)
+        .merge(house_mode_stream)
+        .merge(update_alarm_time_stream)
         .merge(ReceiverStream::new(rx))
         .merge(
             termination_receiver
@@ -535,11 +745,26 @@ async fn screen_thread_loop(
                 .map(|_| ScreenMessage::PollCounters),
         );
 
+    let scene_sender = if scene.is_some() {
+        Some(event_registry.scene_command_sender().await)
+    } else {
+        None
+    };
+
+    let house_mode_sender = if house_mode {
+        Some(event_registry.house_mode_sender().await)
+    } else {
+        None
+    };
+
     let mut dimm_timer_handle = None::<JoinHandle<()>>;
     let mut screen = screen_data(
         update_temperature_sender.is_some(),
         update_color_sender.is_some(),
         update_brightness_sender.is_some(),
+        scene.clone(),
+        house_mode,
+        update_alarm_time_sender.is_some(),
     );
     screen.draw(&mut display).expect("Infallible");
     display.draw().await?;
@@ -574,6 +799,32 @@ async fn screen_thread_loop(
                                 .map_err(ScreenDataError::UpdateTemperature)?;
                         }
                     }
+                    Some(AdjustEvent::Scene) => {
+                        if let (Some(sender), Some(scene)) = (&scene_sender, &scene) {
+                            if let Err(error) = sender.send(SceneCommand::Recall {
+                                scene: scene.clone(),
+                                transition: None,
+                            }) {
+                                warn!("Cannot recall scene {scene}: {error}");
+                            }
+                        }
+                    }
+                    Some(AdjustEvent::HouseMode(mode)) => {
+                        if let Some(sender) = &house_mode_sender {
+                            sender
+                                .send(mode)
+                                .await
+                                .map_err(ScreenDataError::UpdateHouseMode)?;
+                        }
+                    }
+                    Some(AdjustEvent::AlarmTime(time)) => {
+                        if let Some(sender) = &update_alarm_time_sender {
+                            sender
+                                .send(time)
+                                .await
+                                .map_err(ScreenDataError::UpdateAlarmTime)?;
+                        }
+                    }
                 };
                 display.set_backlight(100).await?;
                 let receiver = tx.clone();
@@ -601,6 +852,9 @@ async fn screen_thread_loop(
             }
             ScreenMessage::UpdateLightColor(color) => screen.set_whitebalance(color.0),
             ScreenMessage::UpdateBrightness(brightness) => screen.set_brightness(brightness.0),
+            ScreenMessage::UpdateWindowOpen(open) => screen.set_window_open(open),
+            ScreenMessage::UpdateHouseMode(mode) => screen.set_house_mode(mode),
+            ScreenMessage::UpdateAlarmTime(time) => screen.set_alarm_time(time),
             ScreenMessage::PollCounters => {
                 let counter = display.bricklet_mut().get_spitfp_error_count().await?;
                 debug!("Counters {uid}: {counter:?}");
@@ -1,9 +1,11 @@
-use std::{error::Error, fmt::Debug, fs::File, future::Future, time::Duration};
+use std::{error::Error, fmt::Debug, fs::File, future::Future, path::Path, time::Duration};
 
-use actix_web::{get, App, HttpServer};
+use actix_web::{get, post, put, web, App, HttpResponse, HttpServer};
 use actix_web_prometheus::PrometheusMetricsBuilder;
+use chrono::NaiveTime;
 use env_logger::{Env, TimestampPrecision};
 use log::{error, info};
+use serde::{Deserialize, Serialize};
 use tokio::{
     select,
     signal::unix::{signal, SignalKind},
@@ -13,34 +15,227 @@ use tokio::{
 use tokio_stream::{once, wrappers::ReceiverStream, StreamExt};
 
 use crate::{
+    alarm::active_alarms,
     controller::{
-        action::ring_controller,
-        heat::heat_controller,
-        light::{dual_input_dimmer, dual_input_switch, motion_detector, motion_detector_dimmer},
+        heat::HeatProtection,
+        manager::{ControllerConfig, ControllerError, ControllerManager},
+        script::{clear_script_errors, load_scripts, script_errors},
     },
     data::{
         google_data::read_sheet_data,
-        registry::EventRegistry,
+        registry::{AlarmTimeKey, EventRegistry, HouseMode, LightGroupKey, SceneCommand},
         settings::{Tinkerforge, CONFIG},
         state::{State, StateUpdateMessage},
-        wiring::{Controllers, MotionDetector, Wiring},
+        wiring::{Controllers, LogicBlock, Wiring},
+        Room,
     },
     devices::activate_devices,
+    notification::ring_notifications,
+    power::{flush_light_energy, power_report},
     snapshot::{read_snapshot, write_snapshot},
-    terminator::{AbortHandleTerminator, JoinHandleTerminator},
+    supervisor::{degraded_components, DegradedComponent},
+    terminator::JoinHandleTerminator,
 };
 
+mod alarm;
 mod controller;
 mod data;
 mod devices;
 mod icons;
+mod notification;
+mod power;
 mod snapshot;
+mod supervisor;
 mod terminator;
 mod util;
 
+#[derive(Serialize)]
+struct DegradedHealth {
+    status: &'static str,
+    components: Vec<DegradedComponent>,
+}
+
 #[get("/health")]
-async fn health() -> &'static str {
-    "Ok"
+async fn health() -> HttpResponse {
+    let components = degraded_components();
+    if components.is_empty() {
+        HttpResponse::Ok().body("Ok")
+    } else {
+        HttpResponse::Ok().json(DegradedHealth {
+            status: "Degraded",
+            components,
+        })
+    }
+}
+
+#[get("/alarms")]
+async fn alarms() -> HttpResponse {
+    let alarms = active_alarms();
+    if alarms.is_empty() {
+        HttpResponse::Ok().json(alarms)
+    } else {
+        HttpResponse::ServiceUnavailable().json(alarms)
+    }
+}
+
+#[get("/power")]
+async fn power() -> HttpResponse {
+    HttpResponse::Ok().json(power_report())
+}
+
+#[get("/outputs/usage")]
+async fn output_usage(event_registry: web::Data<EventRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(event_registry.output_usage().await)
+}
+
+#[get("/scripts")]
+async fn scripts() -> HttpResponse {
+    HttpResponse::Ok().json(script_errors())
+}
+
+#[derive(Deserialize)]
+struct RecallSceneQuery {
+    transition_ms: Option<u64>,
+}
+
+#[post("/scenes/{name}/recall")]
+async fn recall_scene(
+    event_registry: web::Data<EventRegistry>,
+    name: web::Path<String>,
+    query: web::Query<RecallSceneQuery>,
+) -> HttpResponse {
+    send_scene_command(
+        &event_registry,
+        SceneCommand::Recall {
+            scene: name.into_inner().into_boxed_str(),
+            transition: query.transition_ms.map(Duration::from_millis),
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct CaptureSceneQuery {
+    room: Option<Room>,
+    floor: Option<i16>,
+}
+
+#[post("/scenes/{name}/capture")]
+async fn capture_scene(
+    event_registry: web::Data<EventRegistry>,
+    name: web::Path<String>,
+    query: web::Query<CaptureSceneQuery>,
+) -> HttpResponse {
+    send_scene_command(
+        &event_registry,
+        SceneCommand::Capture {
+            scene: name.into_inner().into_boxed_str(),
+            group: LightGroupKey::from_location(query.room, query.floor),
+        },
+    )
+    .await
+}
+
+async fn send_scene_command(event_registry: &EventRegistry, command: SceneCommand) -> HttpResponse {
+    match event_registry.scene_command_sender().await.send(command) {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(_) => HttpResponse::ServiceUnavailable().body("No scene controller running"),
+    }
+}
+
+#[get("/house-mode")]
+async fn get_house_mode(event_registry: web::Data<EventRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(event_registry.house_mode().await)
+}
+
+#[put("/house-mode/{mode}")]
+async fn set_house_mode(
+    event_registry: web::Data<EventRegistry>,
+    mode: web::Path<HouseMode>,
+) -> HttpResponse {
+    match event_registry
+        .house_mode_sender()
+        .await
+        .send(mode.into_inner())
+        .await
+    {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(_) => HttpResponse::ServiceUnavailable().body("Cannot update house mode"),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct WakeUpTime {
+    /// none while the alarm is off
+    time: Option<NaiveTime>,
+}
+
+#[get("/wake-up/{room}")]
+async fn get_wake_up_time(
+    event_registry: web::Data<EventRegistry>,
+    room: web::Path<Room>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(WakeUpTime {
+        time: event_registry
+            .alarm_time(AlarmTimeKey(room.into_inner()))
+            .await,
+    })
+}
+
+#[put("/wake-up/{room}")]
+async fn set_wake_up_time(
+    event_registry: web::Data<EventRegistry>,
+    room: web::Path<Room>,
+    body: web::Json<WakeUpTime>,
+) -> HttpResponse {
+    match event_registry
+        .alarm_time_sender(AlarmTimeKey(room.into_inner()))
+        .await
+        .send(body.into_inner().time)
+        .await
+    {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(_) => HttpResponse::ServiceUnavailable().body("Cannot update wake-up time"),
+    }
+}
+
+#[get("/controllers")]
+async fn list_controllers(controller_manager: web::Data<ControllerManager>) -> HttpResponse {
+    HttpResponse::Ok().json(controller_manager.list().await)
+}
+
+#[get("/controllers/{id}")]
+async fn get_controller(
+    controller_manager: web::Data<ControllerManager>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    match controller_manager.get(&id).await {
+        Some(controller) => HttpResponse::Ok().json(controller),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[post("/controllers/{id}/enable")]
+async fn enable_controller(
+    controller_manager: web::Data<ControllerManager>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    controller_enabled_response(controller_manager.set_enabled(&id, true).await)
+}
+
+#[post("/controllers/{id}/disable")]
+async fn disable_controller(
+    controller_manager: web::Data<ControllerManager>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    controller_enabled_response(controller_manager.set_enabled(&id, false).await)
+}
+
+fn controller_enabled_response(result: Result<(), ControllerError>) -> HttpResponse {
+    match result {
+        Ok(()) => HttpResponse::Accepted().finish(),
+        Err(error) => HttpResponse::NotFound().body(error.to_string()),
+    }
 }
 
 #[actix_web::main]
@@ -57,15 +252,6 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let state_file = CONFIG.server.state_file();
     let setup_file = CONFIG.server.setup_file();
 
-    let prometheus = PrometheusMetricsBuilder::new("")
-        .endpoint("/metrics")
-        .build()
-        .unwrap();
-    let mgmt_server = HttpServer::new(move || App::new().wrap(prometheus.clone()).service(health))
-        .bind((*bind_addr, mgmt_port))?
-        .workers(2)
-        .run();
-
     let initial_snapshot = read_snapshot(state_file).await.unwrap_or_else(|error| {
         error!("Cannot load snapshot: {error}");
         None
@@ -73,9 +259,64 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let event_registry = EventRegistry::new(initial_snapshot);
 
+    let prometheus = PrometheusMetricsBuilder::new("")
+        .endpoint("/metrics")
+        .registry(prometheus::default_registry().clone())
+        .build()
+        .unwrap();
+    let controller_manager = ControllerManager::new(
+        event_registry.clone(),
+        HeatProtection {
+            frost_temperature: CONFIG.heating.frost_temperature(),
+            eco_temperature: CONFIG.heating.eco_temperature(),
+            alarm_temperature_change: CONFIG.heating.alarm_temperature_change(),
+            alarm_settle_time: CONFIG.heating.alarm_settle_time(),
+        },
+    );
+    {
+        let controller_manager = controller_manager.clone();
+        tokio::spawn(async move { controller_manager.supervise().await });
+    }
+    let registry_data = web::Data::new(event_registry.clone());
+    let manager_data = web::Data::new(controller_manager.clone());
+    let mgmt_server = HttpServer::new(move || {
+        App::new()
+            .wrap(prometheus.clone())
+            .app_data(registry_data.clone())
+            .app_data(manager_data.clone())
+            .service(health)
+            .service(alarms)
+            .service(output_usage)
+            .service(power)
+            .service(scripts)
+            .service(recall_scene)
+            .service(capture_scene)
+            .service(get_house_mode)
+            .service(set_house_mode)
+            .service(get_wake_up_time)
+            .service(set_wake_up_time)
+            .service(list_controllers)
+            .service(get_controller)
+            .service(enable_controller)
+            .service(disable_controller)
+    })
+    .bind((*bind_addr, mgmt_port))?
+    .workers(2)
+    .run();
+
+    if let Some(url) = CONFIG.doorbell.webhook() {
+        tokio::spawn(ring_notifications(event_registry.clone(), url));
+    }
+    tokio::spawn(async {
+        loop {
+            sleep(Duration::from_secs(60)).await;
+            flush_light_energy();
+        }
+    });
     let snapshot_storage_thread = start_snapshot_thread(&event_registry, state_file);
     let mut terminate_signal = signal(SignalKind::terminate())?;
-    let config_update_future = config_update_loop(tinkerforge, setup_file, event_registry);
+    let config_update_future =
+        config_update_loop(tinkerforge, setup_file, event_registry, controller_manager);
     select! {
         _ = snapshot_storage_thread =>{info!("Snapshot storage thread terminated");}
         status =
@@ -112,9 +353,9 @@ async fn config_update_loop(
     tinkerforge: &Tinkerforge,
     setup_file: &str,
     event_registry: EventRegistry,
+    controller_manager: ControllerManager,
 ) -> Result<(), Box<dyn Error>> {
     let mut current_wiring = Wiring::default();
-    let mut running_controllers = Vec::new();
     let mut running_connections = Vec::new();
     let (tx, rx) = mpsc::channel(100);
     let (main_tx, main_rx) = mpsc::channel(3);
@@ -159,6 +400,7 @@ async fn config_update_loop(
                     } else {
                         None
                     },
+                    &current_wiring.controllers,
                 )
                 .await?;
                 info!(
@@ -178,12 +420,7 @@ async fn config_update_loop(
                     continue;
                 }
                 if wiring.controllers != current_wiring.controllers || reconfig {
-                    activate_controllers(
-                        &event_registry,
-                        &mut running_controllers,
-                        wiring.controllers.clone(),
-                    )
-                    .await;
+                    activate_controllers(&controller_manager, wiring.controllers.clone()).await;
                 }
                 if wiring.tinkerforge_devices != current_wiring.tinkerforge_devices || reconfig {
                     activate_devices(
@@ -216,107 +453,54 @@ fn fech_next_in(
     })));
 }
 
-async fn activate_controllers(
-    event_registry: &EventRegistry,
-    running_controllers: &mut Vec<AbortHandleTerminator>,
-    controllers: Controllers,
-) {
-    info!("Terminating running controllers");
-    running_controllers.clear();
-    let Controllers {
-        dual_input_dimmers,
-        dual_input_switches,
-        motion_detectors,
-        heat_controllers,
-        ring_controllers,
-    } = controllers;
-    for dimmer_cfg in dual_input_dimmers.iter() {
-        running_controllers.push(AbortHandleTerminator::new(
-            dual_input_dimmer(
-                event_registry,
-                dimmer_cfg.input.as_ref(),
-                dimmer_cfg.output,
-                dimmer_cfg.auto_switch_off_time,
-                dimmer_cfg.presence.as_ref(),
-            )
-            .await,
-        ));
-    }
-    for switch_cfg in dual_input_switches.iter() {
-        running_controllers.push(AbortHandleTerminator::new(
-            dual_input_switch(
-                event_registry,
-                switch_cfg.input.as_ref(),
-                switch_cfg.output,
-                switch_cfg.auto_switch_off_time,
-                switch_cfg.presence.as_ref(),
-            )
-            .await,
-        ));
-    }
-    for motion_detector_cfg in motion_detectors.iter() {
-        running_controllers.push(AbortHandleTerminator::new(match motion_detector_cfg {
-            MotionDetector::Switch {
-                input,
-                output,
-                switch_off_time,
-            } => motion_detector(event_registry, input.as_ref(), *output, *switch_off_time).await,
-            MotionDetector::Dimmer {
-                input,
-                output,
-                brightness,
-                switch_off_time,
-            } => {
-                motion_detector_dimmer(
-                    event_registry,
-                    input.as_ref(),
-                    *brightness,
-                    *output,
-                    *switch_off_time,
-                )
-                .await
-            }
-        }));
-    }
-    for cfg in heat_controllers.iter() {
-        running_controllers.push(AbortHandleTerminator::new(
-            heat_controller(
-                event_registry,
-                cfg.current_value_input,
-                cfg.target_value_input,
-                cfg.output,
-            )
-            .await,
-        ));
-    }
-    for cfg in ring_controllers.iter() {
-        running_controllers.push(AbortHandleTerminator::new(
-            ring_controller(event_registry, cfg.input, cfg.output).await,
-        ));
-    }
+async fn activate_controllers(controller_manager: &ControllerManager, controllers: Controllers) {
+    clear_script_errors();
+    controller_manager
+        .replace(ControllerConfig::from_controllers(controllers))
+        .await;
     info!("Controllers updated");
 }
 
 async fn fetch_config(
     setup_file: &str,
     current_state: Option<&State>,
+    previous: &Controllers,
 ) -> Result<Wiring, Box<dyn Error>> {
-    Ok(
-        if let Some(google_data) = match read_sheet_data(current_state).await {
-            Ok(data) => {
-                serde_yaml::to_writer(File::create(setup_file)?, &data)?;
-                data
-            }
+    let mut wiring = if let Some(google_data) = match read_sheet_data(current_state).await {
+        Ok(Some(data)) => {
+            serde_yaml::to_writer(File::create(setup_file)?, &data)?;
+            Some(data)
+        }
+        Ok(None) => None,
+        Err(error) => {
+            error!("Cannot read config from google: {error}");
+            None
+        }
+    } {
+        google_data
+    } else {
+        serde_yaml::from_reader::<_, Wiring>(File::open(setup_file)?)?
+    };
+    if let Some(logic_file) = CONFIG.server.logic_file() {
+        // a broken logic file keeps the blocks loaded before
+        wiring.controllers.logic_blocks = match read_logic_file(logic_file) {
+            Ok(logic_blocks) => logic_blocks,
             Err(error) => {
-                error!("Cannot read config from google: {error}");
-                None
+                error!("Cannot read logic file {logic_file}: {error}");
+                previous.logic_blocks.clone()
             }
-        } {
-            google_data
-        } else {
-            serde_yaml::from_reader(File::open(setup_file)?)?
-        },
-    )
+        };
+    }
+    let script_directory = Path::new(setup_file)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("scripts");
+    wiring.controllers.scripts = load_scripts(&script_directory, &previous.scripts).await;
+    Ok(wiring)
+}
+
+fn read_logic_file(logic_file: &str) -> Result<Box<[LogicBlock]>, Box<dyn Error>> {
+    Ok(serde_yaml::from_reader(File::open(logic_file)?)?)
 }
 
 fn start_snapshot_thread(
@@ -0,0 +1,70 @@
+use std::io;
+
+use http_body_util::Full;
+use hyper::{body::Bytes, header::CONTENT_TYPE, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use log::{error, info, warn};
+use thiserror::Error;
+use tokio_stream::StreamExt;
+
+use crate::data::registry::{EventRegistry, RingEvent};
+
+type HttpClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+#[derive(Error, Debug)]
+pub enum NotificationError {
+    #[error("Cannot load root certificates: {0}")]
+    Certificates(#[from] io::Error),
+    #[error("Cannot encode event: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid request: {0}")]
+    Request(#[from] hyper::http::Error),
+    #[error("Cannot post event: {0}")]
+    Client(#[from] hyper_util::client::legacy::Error),
+}
+
+/// Posts every ring of a doorbell as json to the webhook
+pub async fn ring_notifications(event_registry: EventRegistry, url: &str) {
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(error) => {
+            error!("Cannot create webhook client: {error}");
+            return;
+        }
+    };
+    let mut events = event_registry.ring_event_stream().await;
+    while let Some(event) = events.next().await {
+        match post_event(&client, url, &event).await {
+            Ok(status) if status.is_success() => {
+                info!("Notified ring of {:?}", event.button);
+            }
+            Ok(status) => warn!("Webhook answered {status} on ring of {:?}", event.button),
+            Err(error) => error!("Cannot notify ring of {:?}: {error}", event.button),
+        }
+    }
+}
+
+fn http_client() -> Result<HttpClient, NotificationError> {
+    Ok(Client::builder(TokioExecutor::new()).build(
+        hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()?
+            .https_or_http()
+            .enable_http1()
+            .build(),
+    ))
+}
+
+async fn post_event(
+    client: &HttpClient,
+    url: &str,
+    event: &RingEvent,
+) -> Result<StatusCode, NotificationError> {
+    let request = Request::post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(serde_json::to_vec(event)?)))?;
+    Ok(client.request(request).await?.status())
+}
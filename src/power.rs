@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_counter, register_counter_vec, register_gauge, register_gauge_vec, Counter,
+    CounterVec, Gauge, GaugeVec,
+};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::data::{DeviceInRoom, Room};
+
+struct LightPower {
+    watts: f64,
+    watt_hours: f64,
+    since: Instant,
+}
+
+impl LightPower {
+    /// Energy since the last update at the previous power
+    fn integrate(&mut self, now: Instant) -> f64 {
+        let energy = self.watts * now.duration_since(self.since).as_secs_f64() / 3600.0;
+        self.watt_hours += energy;
+        self.since = now;
+        energy
+    }
+}
+
+/// Power of all lights, with the energy used since the start
+#[derive(Default)]
+struct LightPowers(BTreeMap<DeviceInRoom, LightPower>);
+
+impl LightPowers {
+    /// Sets the power of a light, returns the energy used at the previous power and if it changed
+    fn set(&mut self, light: DeviceInRoom, watts: f64, now: Instant) -> (f64, bool) {
+        let power = self.0.entry(light).or_insert(LightPower {
+            watts: 0.0,
+            watt_hours: 0.0,
+            since: now,
+        });
+        let energy = power.integrate(now);
+        let changed = power.watts != watts;
+        power.watts = watts;
+        (energy, changed)
+    }
+    fn room_watts(&self, room: Room) -> f64 {
+        self.0
+            .iter()
+            .filter(|(device, _)| device.room == room)
+            .map(|(_, power)| power.watts)
+            .sum()
+    }
+    fn house_watts(&self) -> f64 {
+        self.0.values().map(|power| power.watts).sum()
+    }
+    fn report(&self, now: Instant) -> PowerReport {
+        let mut report = PowerReport::default();
+        for (light, power) in self.0.iter() {
+            let estimate = PowerEstimate {
+                watts: power.watts,
+                watt_hours: power.watt_hours
+                    + power.watts * now.duration_since(power.since).as_secs_f64() / 3600.0,
+            };
+            report.house.add(estimate);
+            report.rooms.entry(light.room).or_default().add(estimate);
+            report.lights.push(LightEstimate {
+                light: *light,
+                estimate,
+            });
+        }
+        report
+    }
+}
+
+lazy_static! {
+    static ref LIGHT_POWER: Mutex<LightPowers> = Mutex::new(LightPowers::default());
+    static ref LIGHT_POWER_GAUGE: GaugeVec = register_gauge_vec!(
+        "light_power_watts",
+        "Estimated power of a light",
+        &["light"]
+    )
+    .expect("Cannot register light power gauge");
+    static ref ROOM_POWER_GAUGE: GaugeVec = register_gauge_vec!(
+        "room_light_power_watts",
+        "Estimated power of all lights in a room",
+        &["room"]
+    )
+    .expect("Cannot register room power gauge");
+    static ref HOUSE_POWER_GAUGE: Gauge =
+        register_gauge!("house_light_power_watts", "Estimated power of all lights")
+            .expect("Cannot register house power gauge");
+    static ref LIGHT_ENERGY_COUNTER: CounterVec = register_counter_vec!(
+        "light_energy_watt_hours",
+        "Estimated energy used by a light",
+        &["light"]
+    )
+    .expect("Cannot register light energy counter");
+    static ref ROOM_ENERGY_COUNTER: CounterVec = register_counter_vec!(
+        "room_light_energy_watt_hours",
+        "Estimated energy used by all lights in a room",
+        &["room"]
+    )
+    .expect("Cannot register room energy counter");
+    static ref HOUSE_ENERGY_COUNTER: Counter = register_counter!(
+        "house_light_energy_watt_hours",
+        "Estimated energy used by all lights"
+    )
+    .expect("Cannot register house energy counter");
+}
+
+/// Updates the estimated power of a light from its output levels
+pub fn set_light_power(light: DeviceInRoom, watts: f64) {
+    let mut lights = LIGHT_POWER.lock().expect("Light power lock poisoned");
+    let (energy, changed) = lights.set(light, watts, Instant::now());
+    count_energy(light, energy);
+    if !changed {
+        return;
+    }
+    LIGHT_POWER_GAUGE
+        .with_label_values(&[&format!("{light:?}")])
+        .set(watts);
+    ROOM_POWER_GAUGE
+        .with_label_values(&[&light.room.to_string()])
+        .set(lights.room_watts(light.room));
+    HOUSE_POWER_GAUGE.set(lights.house_watts());
+}
+
+/// Adds the energy of lights staying on for a long time to the counters
+pub fn flush_light_energy() {
+    let now = Instant::now();
+    let mut lights = LIGHT_POWER.lock().expect("Light power lock poisoned");
+    for (light, power) in lights.0.iter_mut() {
+        count_energy(*light, power.integrate(now));
+    }
+}
+
+fn count_energy(light: DeviceInRoom, watt_hours: f64) {
+    if watt_hours > 0.0 {
+        LIGHT_ENERGY_COUNTER
+            .with_label_values(&[&format!("{light:?}")])
+            .inc_by(watt_hours);
+        ROOM_ENERGY_COUNTER
+            .with_label_values(&[&light.room.to_string()])
+            .inc_by(watt_hours);
+        HOUSE_ENERGY_COUNTER.inc_by(watt_hours);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq)]
+pub struct PowerEstimate {
+    pub watts: f64,
+    pub watt_hours: f64,
+}
+
+impl PowerEstimate {
+    fn add(&mut self, other: PowerEstimate) {
+        self.watts += other.watts;
+        self.watt_hours += other.watt_hours;
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LightEstimate {
+    pub light: DeviceInRoom,
+    #[serde(flatten)]
+    pub estimate: PowerEstimate,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PowerReport {
+    pub house: PowerEstimate,
+    pub rooms: BTreeMap<Room, PowerEstimate>,
+    pub lights: Vec<LightEstimate>,
+}
+
+/// Estimated power and energy since the start per light, summed up per room and for the house
+pub fn power_report() -> PowerReport {
+    LIGHT_POWER
+        .lock()
+        .expect("Light power lock poisoned")
+        .report(Instant::now())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::{
+        data::{DeviceInRoom, Room},
+        power::LightPowers,
+    };
+
+    #[test]
+    fn test_power_report() {
+        let room = Room { floor: 3, room: 7 };
+        let other_room = Room { floor: 3, room: 8 };
+        let start = Instant::now();
+        let hour = Duration::from_secs(3600);
+        let mut lights = LightPowers::default();
+        assert_eq!(
+            (0.0, true),
+            lights.set(DeviceInRoom { room, idx: 0 }, 9.5, start)
+        );
+        lights.set(DeviceInRoom { room, idx: 1 }, 20.0, start);
+        lights.set(
+            DeviceInRoom {
+                room: other_room,
+                idx: 0,
+            },
+            6.0,
+            start,
+        );
+        // the energy at the previous power is counted on every change
+        assert_eq!(
+            (20.0, true),
+            lights.set(DeviceInRoom { room, idx: 1 }, 4.0, start + hour)
+        );
+        assert_eq!(
+            (0.0, false),
+            lights.set(DeviceInRoom { room, idx: 1 }, 4.0, start + hour)
+        );
+        assert_eq!(13.5, lights.room_watts(room));
+        assert_eq!(19.5, lights.house_watts());
+        let report = lights.report(start + 2 * hour);
+        assert_eq!(13.5, report.rooms[&room].watts);
+        assert_eq!(19.0 + 24.0, report.rooms[&room].watt_hours);
+        assert_eq!(19.5, report.house.watts);
+        assert_eq!(19.0 + 24.0 + 12.0, report.house.watt_hours);
+        assert_eq!(3, report.lights.len());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use prometheus::{register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec};
+use serde::Serialize;
+use tokio::time::Instant;
+
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+/// running this long without a failure resets the backoff
+const STABLE_TIME: Duration = Duration::from_secs(10 * 60);
+/// failures in a row after which a component counts as degraded
+const DEGRADED_AFTER: u32 = 3;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DegradedComponent {
+    pub component: Box<str>,
+    pub failures: u32,
+    pub since: DateTime<Utc>,
+}
+
+lazy_static! {
+    static ref DEGRADED_COMPONENTS: Mutex<BTreeMap<Box<str>, DegradedComponent>> =
+        Mutex::new(BTreeMap::new());
+    static ref RESTART_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "component_restarts",
+        "Restarts of failed controllers and bricklet handlers",
+        &["component"]
+    )
+    .expect("Cannot register restart counter");
+    static ref DEGRADED_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "component_degraded",
+        "Components failing repeatedly",
+        &["component"]
+    )
+    .expect("Cannot register degraded gauge");
+}
+
+pub fn degraded_components() -> Vec<DegradedComponent> {
+    DEGRADED_COMPONENTS
+        .lock()
+        .expect("Degraded components lock poisoned")
+        .values()
+        .cloned()
+        .collect()
+}
+
+fn mark_degraded(component: &str, failures: u32) {
+    let mut components = DEGRADED_COMPONENTS
+        .lock()
+        .expect("Degraded components lock poisoned");
+    let entry = components.entry(component.into()).or_insert_with(|| {
+        warn!("Component {component} degraded");
+        DEGRADED_GAUGE.with_label_values(&[component]).set(1);
+        DegradedComponent {
+            component: component.into(),
+            failures,
+            since: Utc::now(),
+        }
+    });
+    entry.failures = failures;
+}
+
+fn clear_degraded(component: &str) {
+    let mut components = DEGRADED_COMPONENTS
+        .lock()
+        .expect("Degraded components lock poisoned");
+    if components.remove(component).is_some() {
+        info!("Component {component} recovered");
+        DEGRADED_GAUGE.with_label_values(&[component]).set(0);
+    }
+}
+
+/// Failures in a row of a supervised task, the delay before a restart doubles with each one
+pub struct RestartBackoff {
+    component: Box<str>,
+    failures: u32,
+    started: Instant,
+}
+
+impl RestartBackoff {
+    pub fn new(component: impl Into<Box<str>>) -> Self {
+        Self {
+            component: component.into(),
+            failures: 0,
+            started: Instant::now(),
+        }
+    }
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+    /// Counts a failure and returns the delay before the next start
+    pub fn failed(&mut self) -> Duration {
+        self.check_stable();
+        self.failures += 1;
+        if self.failures >= DEGRADED_AFTER {
+            mark_degraded(&self.component, self.failures);
+        }
+        restart_delay(self.failures)
+    }
+    pub fn restarted(&mut self) {
+        RESTART_COUNTER.with_label_values(&[&self.component]).inc();
+        self.started = Instant::now();
+    }
+    /// Forgets the failures once the task runs long enough
+    pub fn check_stable(&mut self) {
+        if self.failures > 0 && self.started.elapsed() >= STABLE_TIME {
+            self.failures = 0;
+            clear_degraded(&self.component);
+        }
+    }
+}
+
+impl Drop for RestartBackoff {
+    fn drop(&mut self) {
+        clear_degraded(&self.component);
+    }
+}
+
+fn restart_delay(failures: u32) -> Duration {
+    FIRST_DELAY
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::supervisor::restart_delay;
+
+    #[test]
+    fn test_restart_delay() {
+        assert_eq!(Duration::from_secs(1), restart_delay(1));
+        assert_eq!(Duration::from_secs(2), restart_delay(2));
+        assert_eq!(Duration::from_secs(64), restart_delay(7));
+        assert_eq!(Duration::from_secs(300), restart_delay(10));
+        assert_eq!(Duration::from_secs(300), restart_delay(100));
+    }
+}
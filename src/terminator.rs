@@ -4,7 +4,7 @@ use std::sync::Arc;
 use log::info;
 use tokio::{
     sync::{mpsc, watch},
-    task::{AbortHandle, JoinHandle},
+    task::{JoinError, JoinHandle},
 };
 use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
 
@@ -104,23 +104,13 @@ impl<T> JoinHandleTerminator<T> {
     pub(crate) fn is_finished(&self) -> bool {
         self.0.is_finished()
     }
-}
-
-impl<T> Drop for JoinHandleTerminator<T> {
-    fn drop(&mut self) {
-        self.0.abort();
+    /// Waits for the result of the task, must not be called again once it returned
+    pub(crate) async fn join(&mut self) -> Result<T, JoinError> {
+        (&mut self.0).await
     }
 }
 
-pub struct AbortHandleTerminator(AbortHandle);
-
-impl AbortHandleTerminator {
-    pub fn new(handle: AbortHandle) -> Self {
-        Self(handle)
-    }
-}
-
-impl Drop for AbortHandleTerminator {
+impl<T> Drop for JoinHandleTerminator<T> {
     fn drop(&mut self) {
         self.0.abort();
     }
@@ -1,8 +1,18 @@
-use std::future::Future;
+use std::{future::Future, time::Duration};
 
-use tokio_stream::{empty, Empty, Stream};
+use chrono::NaiveTime;
+
+use futures::stream;
+use rand::Rng;
+use tokio::{
+    select,
+    time::{sleep_until, Instant},
+};
+use tokio_stream::{empty, Empty, Stream, StreamExt};
 use tokio_util::either::Either;
 
+use crate::data::wiring::DimmCurve;
+
 pub async fn optional_stream<ISF: Future<Output = IS>, IS: Stream<Item = T>, T>(
     is: Option<ISF>,
 ) -> Either<IS, Empty<T>> {
@@ -13,6 +23,231 @@ pub async fn optional_stream<ISF: Future<Output = IS>, IS: Stream<Item = T>, T>(
     }
 }
 
+/// Whether the time lies between start and end, a range with end before start wraps midnight
+pub fn time_in_range(start: NaiveTime, end: NaiveTime, time: NaiveTime) -> bool {
+    if start <= end {
+        start <= time && time < end
+    } else {
+        start <= time || time < end
+    }
+}
+
+/// Uniformly distributed between min and max in whole seconds
+pub fn random_duration(rng: &mut impl Rng, min: Duration, max: Duration) -> Duration {
+    let min = min.as_secs();
+    let max = max.as_secs().max(min);
+    Duration::from_secs(rng.random_range(min..=max))
+}
+
 pub fn kelvin_2_mireds(temp: u16) -> u16 {
     (1000000 / temp as u32) as u16
 }
+
+const DIMM_GAMMA: f32 = 2.2;
+const DIMM_LOG_BASE: f32 = 100.0;
+
+/// Maps a perceived brightness level to the output level, a light never turns off above zero
+pub fn apply_dimm_curve(curve: DimmCurve, level: u8) -> u8 {
+    if level == 0 {
+        return 0;
+    }
+    let relative = level as f32 / u8::MAX as f32;
+    let output = match curve {
+        DimmCurve::Linear => return level,
+        DimmCurve::Gamma => relative.powf(DIMM_GAMMA),
+        DimmCurve::Logarithmic => (DIMM_LOG_BASE.powf(relative) - 1.0) / (DIMM_LOG_BASE - 1.0),
+    };
+    ((output * u8::MAX as f32).round() as u8).max(1)
+}
+
+const FADE_STEP: Duration = Duration::from_millis(20);
+
+/// Ramps towards each value of `input` instead of jumping to it.
+///
+/// The durations define the time for a transition over the whole `full_range`, smaller changes
+/// take proportionally less time. A zero duration passes values through unchanged. When `input`
+/// ends, the running ramp still reaches its target before the output ends.
+pub fn fade<S: Stream<Item = u16> + Unpin>(
+    input: S,
+    fade_up: Duration,
+    fade_down: Duration,
+    full_range: u16,
+) -> impl Stream<Item = u16> {
+    stream::unfold(
+        (input, None::<(f32, u16)>, false, None::<Instant>),
+        move |(mut input, mut state, mut ended, mut next_step)| async move {
+            loop {
+                match state {
+                    Some((current, target)) if current != target as f32 => {
+                        let rising = target as f32 > current;
+                        let duration = if rising { fade_up } else { fade_down };
+                        if duration.is_zero() {
+                            let state = Some((target as f32, target));
+                            return Some((target, (input, state, ended, None)));
+                        }
+                        // new input values must not delay the running step
+                        let step_at = *next_step.get_or_insert_with(|| Instant::now() + FADE_STEP);
+                        select! {
+                            next = input.next(), if !ended => match next {
+                                Some(next) => state = Some((current, next)),
+                                None => ended = true,
+                            },
+                            _ = sleep_until(step_at) => {
+                                let step = full_range as f32 * FADE_STEP.as_secs_f32()
+                                    / duration.as_secs_f32();
+                                let next = if rising {
+                                    (current + step).min(target as f32)
+                                } else {
+                                    (current - step).max(target as f32)
+                                };
+                                let next_step = Some(step_at + FADE_STEP);
+                                return Some((
+                                    next.round() as u16,
+                                    (input, Some((next, target)), ended, next_step),
+                                ));
+                            }
+                        }
+                    }
+                    _ if ended => return None,
+                    Some((current, _)) => {
+                        next_step = None;
+                        state = Some((current, input.next().await?));
+                    }
+                    None => {
+                        let target = input.next().await?;
+                        let state = Some((target as f32, target));
+                        return Some((target, (input, state, ended, None)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::NaiveTime;
+    use rand::{rngs::SmallRng, SeedableRng};
+    use tokio::{
+        sync::mpsc,
+        time::{sleep_until, Instant},
+    };
+    use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+    use crate::{
+        data::wiring::DimmCurve,
+        util::{apply_dimm_curve, fade, random_duration, time_in_range},
+    };
+
+    /// Fades the inputs sent at their offsets, the input ends after the last one
+    async fn run_fade(
+        inputs: Vec<(Duration, u16)>,
+        fade_up: Duration,
+        fade_down: Duration,
+    ) -> Vec<(Duration, u16)> {
+        let start = Instant::now();
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            for (at, value) in inputs {
+                sleep_until(start + at).await;
+                tx.send(value).await.unwrap();
+            }
+        });
+        fade(ReceiverStream::new(rx), fade_up, fade_down, 255)
+            .map(|value| (start.elapsed(), value))
+            .collect()
+            .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fade_ramp() {
+        let ms = Duration::from_millis;
+        // the input ends before the ramp is done
+        let output = run_fade(vec![(ms(0), 0), (ms(0), 255)], ms(1000), ms(0)).await;
+        assert_eq!((ms(0), 0), output[0]);
+        assert_eq!((ms(20), 5), output[1]);
+        assert_eq!((ms(500), 127), output[25]);
+        assert_eq!(Some(&(ms(1000), 255)), output.last());
+        assert_eq!(51, output.len());
+        assert!(output.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+        // without a duration the value jumps
+        let output = run_fade(vec![(ms(0), 255), (ms(100), 0)], ms(1000), ms(0)).await;
+        assert_eq!(vec![(ms(0), 255), (ms(100), 0)], output);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fade_retarget() {
+        let ms = Duration::from_millis;
+        let output = run_fade(
+            vec![(ms(0), 0), (ms(0), 255), (ms(510), 0)],
+            ms(1000),
+            ms(2000),
+        )
+        .await;
+        let turn = output.iter().position(|(at, _)| *at > ms(510)).unwrap();
+        assert_eq!((ms(500), 127), output[turn - 1]);
+        // the ramp turns from the current value instead of jumping to the old target
+        assert_eq!((ms(520), 125), output[turn]);
+        assert!(output[turn..].windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert_eq!(Some(&(ms(1500), 0)), output.last());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fade_fast_input() {
+        let ms = Duration::from_millis;
+        // values arriving faster than a step, like while dimming with a held button
+        let mut inputs = vec![(ms(0), 0)];
+        inputs.extend((1..=30).map(|i| (ms(i * 10), 200 + i as u16)));
+        let output = run_fade(inputs, ms(1000), ms(0)).await;
+        // the ramp starts with the first new value and keeps its pace
+        assert_eq!((ms(30), 5), output[1]);
+        assert_eq!((ms(290), 71), output[14]);
+        assert!(output[1..]
+            .windows(2)
+            .all(|pair| pair[1].0 - pair[0].0 == ms(20)));
+        assert!(output.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert_eq!(Some(&230), output.last().map(|(_, value)| value));
+    }
+
+    #[test]
+    fn test_dimm_curve() {
+        for curve in [DimmCurve::Linear, DimmCurve::Gamma, DimmCurve::Logarithmic] {
+            assert_eq!(0, apply_dimm_curve(curve, 0));
+            assert_eq!(1, apply_dimm_curve(curve, 1));
+            assert_eq!(255, apply_dimm_curve(curve, 255));
+            let mut last = 0;
+            for level in 0..=255 {
+                let output = apply_dimm_curve(curve, level);
+                assert!(output >= last);
+                last = output;
+            }
+        }
+        assert_eq!(128, apply_dimm_curve(DimmCurve::Linear, 128));
+        assert!(apply_dimm_curve(DimmCurve::Gamma, 128) < 64);
+        assert!(apply_dimm_curve(DimmCurve::Logarithmic, 128) < 32);
+    }
+
+    #[test]
+    fn test_time_in_range() {
+        let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+        assert!(time_in_range(time(18), time(23), time(20)));
+        assert!(!time_in_range(time(18), time(23), time(23)));
+        assert!(time_in_range(time(22), time(6), time(2)));
+        assert!(!time_in_range(time(22), time(6), time(12)));
+    }
+
+    #[test]
+    fn test_random_duration() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let min = Duration::from_secs(60);
+        let max = Duration::from_secs(120);
+        for _ in 0..1000 {
+            let duration = random_duration(&mut rng, min, max);
+            assert!(min <= duration && duration <= max);
+        }
+        assert_eq!(min, random_duration(&mut rng, min, min));
+    }
+}